@@ -0,0 +1,368 @@
+use serde::Serialize;
+use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
+use crate::config::{PRICE_PRECISION, MAX_ORDERS_PER_BOOK, MAX_ORDERS_PER_OWNER, MAX_CLOB_FILLS_PER_ORDER};
+use crate::error::*;
+use crate::event::{insert_event, emit_market_indexed_object, EVENT_ORDER_UPDATE};
+use crate::market::MarketData;
+use crate::math_safe::safe_add;
+use crate::orderbook::{OrderBook, ORDER_PLACED, ORDER_FILLED, ORDER_CANCELLED};
+use crate::player::{Player, PlayerMarketManager};
+use crate::state::{MarketManager, GLOBAL_STATE};
+
+// Bid in an order book market: buy up to `shares` of `side` at no more than `price`
+// each. A YES bid at p and a NO bid at 1 - p or better together mint complete sets.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ClobOrder {
+    pub owner: [u64; 2],
+    pub market_id: u64,
+    pub side: u64,        // 0 = NO, 1 = YES
+    pub price: u64,       // limit price, scaled by PRICE_PRECISION
+    pub shares: u64,      // shares wanted
+    pub filled: u64,      // shares received so far
+    pub escrow: u64,      // collateral taken at placement
+    pub spent: u64,       // part of the escrow paid for filled shares
+    pub play_credit: u64, // part of the escrow taken from faucet play credit
+    pub counter: u64,     // counter at placement
+}
+
+impl StorageData for ClobOrder {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        ClobOrder {
+            owner: [*u64data.next().unwrap(), *u64data.next().unwrap()],
+            market_id: *u64data.next().unwrap(),
+            side: *u64data.next().unwrap(),
+            price: *u64data.next().unwrap(),
+            shares: *u64data.next().unwrap(),
+            filled: *u64data.next().unwrap(),
+            escrow: *u64data.next().unwrap(),
+            spent: *u64data.next().unwrap(),
+            play_credit: *u64data.next().unwrap(),
+            counter: *u64data.next().unwrap(),
+        }
+    }
+
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.owner[0]);
+        data.push(self.owner[1]);
+        data.push(self.market_id);
+        data.push(self.side);
+        data.push(self.price);
+        data.push(self.shares);
+        data.push(self.filled);
+        data.push(self.escrow);
+        data.push(self.spent);
+        data.push(self.play_credit);
+        data.push(self.counter);
+    }
+}
+
+impl ClobOrder {
+    pub fn remaining(&self) -> u64 {
+        self.shares - self.filled
+    }
+
+    // Collateral owed for the first `shares` shares of this order, rounded up
+    fn cost_of(&self, shares: u64) -> u64 {
+        let cost = shares as u128 * self.price as u128;
+        cost.div_ceil(PRICE_PRECISION as u128) as u64
+    }
+
+    // Records a fill at the order's own price and returns what it costs. Costs are
+    // taken from the cumulative total so rounding never exceeds the escrow.
+    pub fn fill_at_limit(&mut self, shares: u64) -> u64 {
+        let cost = self.cost_of(self.filled + shares) - self.cost_of(self.filled);
+        self.filled += shares;
+        self.spent += cost;
+        cost
+    }
+
+    pub fn refundable(&self) -> u64 {
+        self.escrow - self.spent
+    }
+
+    // Play credit already paid for fills; refunds give the credit back first
+    pub fn credit_spent(&self) -> u64 {
+        self.play_credit.saturating_sub(self.refundable())
+    }
+}
+
+pub struct ClobBook;
+
+impl ClobBook {
+    const ORDER_PREFIX: [u64; 2] = [13, 0]; // Prefix for order book market orders
+    const BOOK_PREFIX: [u64; 2] = [14, 0];  // Prefix for per market and side bid lists
+
+    pub fn get_order(order_id: u64) -> Option<ClobOrder> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let mut data = kvpair.get(&[Self::ORDER_PREFIX[0], Self::ORDER_PREFIX[1], order_id, 0]);
+        if !data.is_empty() {
+            let mut u64data = data.iter_mut();
+            Some(ClobOrder::from_data(&mut u64data))
+        } else {
+            None
+        }
+    }
+
+    fn store_order(order_id: u64, order: &ClobOrder) {
+        let mut data = vec![];
+        order.to_data(&mut data);
+        crate::journal::set(&[Self::ORDER_PREFIX[0], Self::ORDER_PREFIX[1], order_id, 0], data.as_slice());
+    }
+
+    fn remove_order(order_id: u64) {
+        crate::journal::set(&[Self::ORDER_PREFIX[0], Self::ORDER_PREFIX[1], order_id, 0], &[]);
+    }
+
+    // Resting bids of one side, best (highest) price first, oldest first within a price
+    pub fn get_book(market_id: u64, side: u64) -> Vec<u64> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        kvpair.get(&[Self::BOOK_PREFIX[0], Self::BOOK_PREFIX[1], market_id, side])
+    }
+
+    fn store_book(market_id: u64, side: u64, order_ids: &[u64]) {
+        crate::journal::set(&[Self::BOOK_PREFIX[0], Self::BOOK_PREFIX[1], market_id, side], order_ids);
+    }
+
+    // Escrows `amount`, matches it against the opposite side and rests any remainder.
+    // A remainder that would still cross the other side is refunded instead, so the
+    // book never rests crossed. The caller stores `player`.
+    pub fn place(player: &mut Player, market_id: u64, side: u64, price: u64, amount: u64, counter: u64) -> Result<u64, u32> {
+        if side > 1 {
+            return Err(ERROR_INVALID_BET_TYPE);
+        }
+        if price == 0 || price >= PRICE_PRECISION {
+            return Err(ERROR_INVALID_PRICE);
+        }
+        GLOBAL_STATE.0.borrow().ensure_market_active(market_id)?;
+        let market = MarketManager::get_market(market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+        if !market.is_clob() {
+            return Err(ERROR_INVALID_MARKET_KIND);
+        }
+        let shares = (amount as u128 * PRICE_PRECISION as u128 / price as u128) as u64;
+        if shares == 0 {
+            return Err(ERROR_INVALID_BET_AMOUNT);
+        }
+        let mut book = Self::get_book(market_id, side);
+        if book.len() as u64 >= MAX_ORDERS_PER_BOOK {
+            return Err(ERROR_TOO_MANY_ORDERS);
+        }
+        let resting: Vec<ClobOrder> = book.iter().filter_map(|id| Self::get_order(*id)).collect();
+        if resting.iter().filter(|o| o.owner == player.player_id).count() as u64 >= MAX_ORDERS_PER_OWNER {
+            return Err(ERROR_TOO_MANY_ORDERS);
+        }
+
+        let play_credit = player.data.spend_stake_of(market.token, amount)?;
+        let mut order = ClobOrder {
+            owner: player.player_id,
+            market_id,
+            side,
+            price,
+            shares,
+            filled: 0,
+            escrow: amount,
+            spent: 0,
+            play_credit,
+            counter,
+        };
+        let order_id = {
+            let mut global_state = GLOBAL_STATE.0.borrow_mut();
+            let order_id = global_state.next_order_id;
+            global_state.next_order_id += 1;
+            order_id
+        };
+        Self::emit_order_event(ORDER_PLACED, order_id, &order, order.escrow, order.shares, counter);
+
+        let crossed = Self::match_incoming(player, order_id, &mut order, market, counter)?;
+        if order.remaining() == 0 {
+            Self::refund(player, &order);
+        } else if crossed {
+            // Out of fills or a rounding unit short: what is left goes back
+            Self::refund(player, &order);
+            Self::emit_order_event(ORDER_CANCELLED, order_id, &order, order.refundable(), order.remaining(), counter);
+        } else {
+            let prices: Vec<u64> = resting.iter().map(|o| o.price).collect();
+            book.insert(OrderBook::insert_position(&prices, price), order_id);
+            Self::store_order(order_id, &order);
+            Self::store_book(market_id, side, &book);
+        }
+        Ok(order_id)
+    }
+
+    // Crosses the incoming bid with resting bids on the other side, best price first.
+    // Trades execute at the resting bid's price and the taker pays the complement.
+    // Returns whether the best resting bid still crosses the taker's price.
+    fn match_incoming(player: &mut Player, taker_id: u64, taker: &mut ClobOrder, mut market: MarketData, counter: u64) -> Result<bool, u32> {
+        let maker_side = 1 - taker.side;
+        let mut book = Self::get_book(taker.market_id, maker_side);
+        let mut fills = 0;
+        while taker.remaining() > 0 && !book.is_empty() && fills < MAX_CLOB_FILLS_PER_ORDER {
+            let maker_id = book[0];
+            let mut maker = Self::get_order(maker_id).ok_or(ERROR_ORDER_NOT_FOUND)?;
+            if safe_add(maker.price, taker.price)? < PRICE_PRECISION {
+                break;
+            }
+            let shares = taker.remaining().min(maker.remaining());
+            let (maker_credit, taker_credit) = (maker.credit_spent(), taker.credit_spent());
+            let maker_cost = maker.fill_at_limit(shares);
+            let taker_cost = shares - maker_cost;
+            // Rounding in the maker's favour can leave the taker a unit short
+            if taker.spent + taker_cost > taker.escrow {
+                break;
+            }
+            taker.filled += shares;
+            taker.spent += taker_cost;
+            market.mint_complete_sets(shares)?;
+            market.add_cost_basis(shares)?;
+
+            for (order, cost, credit_before) in [(&maker, maker_cost, maker_credit), (&*taker, taker_cost, taker_credit)] {
+                let mut position = PlayerMarketManager::get_position(&order.owner, order.market_id);
+                position.record_buy(order.side == 1, shares, cost, order.credit_spent() - credit_before)?;
+                PlayerMarketManager::store_position(&order.owner, order.market_id, &position);
+            }
+            Self::emit_order_event(ORDER_FILLED, maker_id, &maker, maker_cost, shares, counter);
+            Self::emit_order_event(ORDER_FILLED, taker_id, taker, taker_cost, shares, counter);
+
+            if maker.remaining() == 0 {
+                book.remove(0);
+                Self::remove_order(maker_id);
+                if maker.owner == player.player_id {
+                    Self::refund(player, &maker);
+                } else {
+                    crate::journal::track_player(&maker.owner);
+                    let mut owner = Player::get_from_pid(&maker.owner).ok_or(ERROR_PLAYER_NOT_EXIST)?;
+                    Self::refund(&mut owner, &maker);
+                    owner.store();
+                }
+            } else {
+                Self::store_order(maker_id, &maker);
+            }
+            fills += 1;
+        }
+        if fills > 0 {
+            Self::store_book(taker.market_id, maker_side, &book);
+            MarketManager::update_market(taker.market_id, &market);
+            emit_market_indexed_object(&market, taker.market_id);
+        }
+        let best = book.first().and_then(|id| Self::get_order(*id));
+        Ok(best.is_some_and(|maker| maker.price + taker.price >= PRICE_PRECISION))
+    }
+
+    // Returns the unspent escrow, restoring play credit up to what the order locked
+    fn refund(player: &mut Player, order: &ClobOrder) {
+        let refund = order.refundable();
+        let token = MarketManager::get_market(order.market_id).map_or(0, |market| market.token);
+        player.data.add_balance_of(token, refund);
+        if token == 0 {
+            player.data.play_credit += order.play_credit.min(refund);
+        }
+    }
+
+    // The caller stores `player`
+    pub fn cancel(player: &mut Player, order_id: u64, counter: u64) -> Result<(), u32> {
+        let order = Self::get_order(order_id).ok_or(ERROR_ORDER_NOT_FOUND)?;
+        if order.owner != player.player_id {
+            return Err(ERROR_UNAUTHORIZED);
+        }
+        Self::refund(player, &order);
+        let mut book = Self::get_book(order.market_id, order.side);
+        book.retain(|id| *id != order_id);
+        Self::store_book(order.market_id, order.side, &book);
+        Self::remove_order(order_id);
+        Self::emit_order_event(ORDER_CANCELLED, order_id, &order, order.refundable(), order.remaining(), counter);
+        Ok(())
+    }
+
+    // Same layout as limit orders on LMSR markets; `amount` is the collateral
+    // escrowed, paid or refunded and `shares` the shares wanted or received
+    fn emit_order_event(status: u64, order_id: u64, order: &ClobOrder, amount: u64, shares: u64, counter: u64) {
+        let mut data = vec![
+            status,
+            order_id,
+            order.owner[0],
+            order.owner[1],
+            order.market_id,
+            order.side,
+            order.price,
+            amount,
+            shares,
+            counter,
+        ];
+        insert_event(EVENT_ORDER_UPDATE, &mut data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{Activity, CommandHandler};
+    use crate::test_support;
+
+    #[test]
+    fn test_fill_cost_rounds_within_escrow() {
+        let mut order = ClobOrder { price: 333_333, shares: 10, escrow: 4, ..Default::default() };
+        let costs: Vec<u64> = (0..10).map(|_| order.fill_at_limit(1)).collect();
+        assert_eq!(costs.iter().sum::<u64>(), 4);
+        assert_eq!(order.refundable(), 0);
+    }
+
+    #[test]
+    fn test_crossing_bids_mint_complete_sets() {
+        let _guard = test_support::lock();
+        let market_id = MarketManager::create_clob_market(0, 1_000, 1_000, 0).unwrap();
+        let (yes_bidder, no_bidder) = (test_support::player(10_000), test_support::player(10_000));
+
+        // 600 at 0.60 rests as a bid for 1000 YES
+        Activity::PlaceLimitOrder(market_id, 1, 600_000, 600).handle(&yes_bidder, 0, &[0; 4], 0).unwrap();
+        assert_eq!(ClobBook::get_book(market_id, 1).len(), 1);
+        assert_eq!(Player::get_from_pid(&yes_bidder).unwrap().data.balance, 9_400);
+
+        // A NO bid at 0.50 crosses and buys 800 NO at the complement 0.40
+        Activity::PlaceLimitOrder(market_id, 0, 500_000, 400).handle(&no_bidder, 0, &[0; 4], 0).unwrap();
+        assert!(ClobBook::get_book(market_id, 0).is_empty());
+        let no_position = PlayerMarketManager::get_position(&no_bidder, market_id);
+        assert_eq!((no_position.no_shares, no_position.no_cost_basis), (800, 320));
+        assert_eq!(Player::get_from_pid(&no_bidder).unwrap().data.balance, 9_680);
+        let yes_position = PlayerMarketManager::get_position(&yes_bidder, market_id);
+        assert_eq!((yes_position.yes_shares, yes_position.yes_cost_basis), (800, 480));
+
+        let market = MarketManager::get_market(market_id).unwrap();
+        assert_eq!((market.total_yes_shares, market.total_no_shares, market.pool_balance), (800, 800, 800));
+        let maker_id = ClobBook::get_book(market_id, 1)[0];
+        assert_eq!(ClobBook::get_order(maker_id).unwrap().remaining(), 200);
+
+        // Bets are not available on order book markets
+        assert_eq!(Activity::Bet(market_id, 1, 100).handle(&no_bidder, 1, &[0; 4], 0), Err(ERROR_INVALID_MARKET_KIND));
+
+        // Cancelling the rest of the maker returns 600 - 480
+        Activity::CancelOrder(maker_id).handle(&yes_bidder, 1, &[0; 4], 0).unwrap();
+        assert_eq!(Player::get_from_pid(&yes_bidder).unwrap().data.balance, 9_520);
+        assert!(ClobBook::get_book(market_id, 1).is_empty());
+    }
+
+    #[test]
+    fn test_remainder_never_rests_crossed() {
+        let _guard = test_support::lock();
+        let market_id = MarketManager::create_clob_market(0, 1_000, 1_000, 0).unwrap();
+        let makers = [test_support::player(10_000), test_support::player(10_000), test_support::player(10_000)];
+        let taker = test_support::player(10_000);
+
+        // Nine resting YES bids of 100 shares at 0.60, one more than a taker may cross
+        for maker in 0..=MAX_CLOB_FILLS_PER_ORDER {
+            let owner = makers[(maker / MAX_ORDERS_PER_OWNER) as usize];
+            let nonce = maker % MAX_ORDERS_PER_OWNER;
+            Activity::PlaceLimitOrder(market_id, 1, 600_000, 60).handle(&owner, nonce, &[0; 4], 0).unwrap();
+        }
+        assert_eq!(
+            Activity::PlaceLimitOrder(market_id, 1, 600_000, 60).handle(&makers[0], MAX_ORDERS_PER_OWNER, &[0; 4], 0),
+            Err(ERROR_TOO_MANY_ORDERS)
+        );
+
+        // 2_000 NO at 0.50: eight fills, the rest would cross the ninth bid and is refunded
+        Activity::PlaceLimitOrder(market_id, 0, 500_000, 1_000).handle(&taker, 0, &[0; 4], 0).unwrap();
+        assert!(ClobBook::get_book(market_id, 0).is_empty());
+        assert_eq!(ClobBook::get_book(market_id, 1).len(), 1);
+        let filled = 100 * MAX_CLOB_FILLS_PER_ORDER;
+        assert_eq!(PlayerMarketManager::get_position(&taker, market_id).no_shares, filled);
+        assert_eq!(Player::get_from_pid(&taker).unwrap().data.balance, 10_000 - filled * 4 / 10);
+    }
+}
@@ -1,7 +1,18 @@
+use crate::deposit::{DepositManager, DepositRecord};
 use crate::error::*;
-use crate::event::{insert_event, EVENT_BET_UPDATE, emit_market_indexed_object};
-use crate::player::Player;
+use crate::event::{insert_event, EVENT_BET_UPDATE, EVENT_CLAIM_UPDATE, EVENT_SHARE_TRANSFER, EVENT_BALANCE_TRANSFER, EVENT_DEPOSIT, EVENT_PARAM_UPDATE, emit_market_indexed_object};
+use crate::clob::ClobBook;
+use crate::config::{MAX_ORDER_FILLS_PER_MATCH, MarketTemplate};
+use crate::market::MARKET_KIND_CLOB;
+use crate::math_safe::safe_add;
+use crate::orderbook::OrderBook;
+use crate::parlay::ParlayManager;
+use crate::series::SeriesManager;
+use crate::player::{Player, PlayerMarketManager};
+use crate::roles::{RoleManager, ROLE_SUPER_ADMIN, ROLE_MARKET_CREATOR, ROLE_RESOLVER, ROLE_TREASURER};
 use crate::state::{GLOBAL_STATE};
+use crate::trigger::TriggerManager;
+use crate::withdrawal::{WithdrawalManager, PendingWithdrawal, WITHDRAWAL_SETTLED};
 
 #[derive(Clone)]
 pub enum Command {
@@ -21,22 +32,67 @@ pub trait CommandHandler {
 
 #[derive(Clone)]
 pub struct Withdraw {
-    pub data: [u64; 3],
+    pub token: u64,
+    pub amount: u64,
+    // L1 address as [high 32 bits, middle 64 bits, low 64 bits]
+    pub address: [u64; 3],
+}
+
+impl Withdraw {
+    // Legacy layout: amount in the low 32 bits of the first word, address high bits above it
+    pub fn from_packed(token: u64, data: [u64; 3]) -> Self {
+        Withdraw {
+            token,
+            amount: data[0] & 0xffffffff,
+            address: [data[0] >> 32, data[1], data[2]],
+        }
+    }
+
+    // Checks the encoding and builds the settlement record carrying the full 64-bit amount
+    pub fn settlement_info(&self) -> Result<zkwasm_rest_abi::WithdrawInfo, u32> {
+        crate::math_safe::validate_token(self.token)?;
+        if self.amount == 0 {
+            return Err(ERROR_INVALID_BET_AMOUNT);
+        }
+        if self.address[0] > 0xffffffff {
+            return Err(ERROR_INVALID_WITHDRAW);
+        }
+        let mut withdrawinfo = zkwasm_rest_abi::WithdrawInfo::new(&[self.address[0] << 32, self.address[1], self.address[2]], self.token);
+        withdrawinfo.amount = self.amount;
+        Ok(withdrawinfo)
+    }
 }
 
 impl CommandHandler for Withdraw {
-    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4], _counter: u64) -> Result<(), u32> {
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4], counter: u64) -> Result<(), u32> {
         let mut player = Player::get_from_pid(pid);
         match player.as_mut() {
             None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
                 player.check_and_inc_nonce(nonce);
-                let balance = player.data.balance;
-                let amount = self.data[0] & 0xffffffff;
-                unsafe { zkwasm_rust_sdk::require(balance >= amount) };
-                player.data.balance -= amount;
-                let withdrawinfo = zkwasm_rest_abi::WithdrawInfo::new(&[self.data[0], self.data[1], self.data[2]], 0);
-                crate::settlement::SettlementInfo::append_settlement(withdrawinfo);
+                // Validate the payload before touching the balance so the debit and settlement agree
+                let withdrawinfo = self.settlement_info()?;
+                // Faucet play credit cannot be withdrawn
+                if player.data.withdrawable_balance_of(self.token) < self.amount {
+                    return Err(ERROR_INSUFFICIENT_BALANCE);
+                }
+                WithdrawalManager::record_usage(&mut player.data, self.amount, counter)?;
+                player.data.spend_balance_of(self.token, self.amount)?;
+                if WithdrawalManager::is_large(self.amount) {
+                    // Large withdrawals wait in the pending queue and can be cancelled by the admin
+                    WithdrawalManager::queue(&player.player_id, self, counter);
+                } else {
+                    crate::settlement::SettlementInfo::append_settlement(withdrawinfo);
+                    let settled = PendingWithdrawal {
+                        player_id: player.player_id,
+                        token: self.token,
+                        amount: self.amount,
+                        address: self.address,
+                        release_counter: counter,
+                        usage_epoch: WithdrawalManager::epoch(counter),
+                    };
+                    WithdrawalManager::emit_withdrawal_event(WITHDRAWAL_SETTLED, 0, &settled, counter);
+                }
                 player.store();
                 Ok(())
             }
@@ -46,26 +102,61 @@ impl CommandHandler for Withdraw {
 
 #[derive(Clone)]
 pub struct Deposit {
+    pub token: u64,
     pub data: [u64; 3],
+    pub tx_hash: [u64; 4], // L1 transaction hash, each one is credited once
 }
 
 impl CommandHandler for Deposit {
-    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4], _counter: u64) -> Result<(), u32> {
-        let mut admin = Player::get_from_pid(pid).unwrap();
+    fn handle(&self, pid: &[u64; 2], nonce: u64, _rand: &[u64; 4], counter: u64) -> Result<(), u32> {
+        // Depositors are granted a role, they need not have installed a player
+        let mut admin = Player::get_from_pid(pid).ok_or(ERROR_PLAYER_NOT_EXIST)?;
         admin.check_and_inc_nonce(nonce);
+        crate::math_safe::validate_token(self.token)?;
+        if DepositManager::is_processed(&self.tx_hash) {
+            return Err(ERROR_DEPOSIT_ALREADY_PROCESSED);
+        }
+        crate::journal::track_player(&[self.data[0], self.data[1]]);
         let mut player = Player::get_from_pid(&[self.data[0], self.data[1]]);
         match player.as_mut() {
             None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
-                player.data.balance += self.data[2];
+                player.data.add_balance_of(self.token, self.data[2]);
+                DepositManager::store_record(&self.tx_hash, &DepositRecord {
+                    tx_hash_tail: self.tx_hash[3],
+                    player_id: player.player_id,
+                    token: self.token,
+                    amount: self.data[2],
+                    counter,
+                });
                 player.store();
                 admin.store();
+                self.emit_deposit_event(counter);
                 Ok(())
             }
         }
     }
 }
 
+impl Deposit {
+    fn emit_deposit_event(&self, counter: u64) {
+        let txid = GLOBAL_STATE.0.borrow().txcounter;
+        let mut data = vec![
+            txid,
+            self.data[0],
+            self.data[1],
+            self.token,
+            self.data[2],
+            self.tx_hash[0],
+            self.tx_hash[1],
+            self.tx_hash[2],
+            self.tx_hash[3],
+            counter,
+        ];
+        insert_event(EVENT_DEPOSIT, &mut data);
+    }
+}
+
 #[derive(Clone)]
 pub enum Activity {
     // Prediction market activities
@@ -73,8 +164,44 @@ pub enum Activity {
     Sell(u64, u64, u64),       // market_id, sell_type, shares_amount
     Resolve(u64, u64),         // market_id, outcome
     Claim(u64),                // market_id
+    ClaimAll,                  // claim every resolved market in the player's position index
     WithdrawFees(u64),         // market_id
-    CreateMarket(u64, u64, u64, u64, u64, u64), // start_time_offset, end_time_offset, resolution_time_offset, yes_liquidity, no_liquidity, b
+    CreateMarket(u64, u64, u64, u64, u64, u64, u64, u64), // start_time_offset, end_time_offset, resolution_time_offset, yes_liquidity, no_liquidity, b, token, kind
+    TransferShares(u64, u64, u64, [u64; 2]), // market_id, side, shares_amount, to_pid
+    Transfer([u64; 2], u64, u64), // to_pid, amount, token
+    CancelWithdrawal(u64),     // pending withdrawal id
+    SetParam(u64, u64),        // param_id, value
+    FundFaucet(u64),           // amount added to the faucet budget
+    ClaimFaucet,
+    GrantRole([u64; 2], u64),  // player_id, role mask
+    RevokeRole([u64; 2], u64), // player_id, role mask
+    RotateAdmin([u64; 2]),     // new super-admin player_id
+    Batch(Vec<Activity>),      // trading activities applied atomically
+    PlaceLimitOrder(u64, u64, u64, u64), // market_id, side, limit price, amount
+    CancelOrder(u64),          // order id
+    PlaceTrigger(u64, u64, u64, u64, u64), // market_id, side, kind, trigger price, shares
+    CancelTrigger(u64),        // trigger id
+    PlaceParlay(u64, Vec<(u64, u64)>), // stake, (market_id, side) per leg
+    ClaimParlay([u64; 2], u64), // owner player_id, parlay id
+    FundParlayBankroll(u64, u64), // token, amount
+    CreateConditionalMarket(u64, u64, u64, u64, u64, u64, u64, u64), // start_time_offset, end_time_offset, resolution_time_offset, yes_liquidity, no_liquidity, b, parent_market_id, condition
+    CreateSeries(MarketTemplate), // template of every market in the series
+    StopSeries(u64),           // series id
+}
+
+impl Activity {
+    // Role a player must hold to run this activity, None for player commands
+    pub fn required_role(&self) -> Option<u64> {
+        match self {
+            Activity::CreateMarket(..) | Activity::CreateConditionalMarket(..)
+                | Activity::CreateSeries(_) | Activity::StopSeries(_) => Some(ROLE_MARKET_CREATOR),
+            Activity::Resolve(..) => Some(ROLE_RESOLVER),
+            Activity::WithdrawFees(_) | Activity::CancelWithdrawal(_) | Activity::FundFaucet(_)
+                | Activity::FundParlayBankroll(..) => Some(ROLE_TREASURER),
+            Activity::SetParam(..) | Activity::GrantRole(..) | Activity::RevokeRole(..) | Activity::RotateAdmin(_) => Some(ROLE_SUPER_ADMIN),
+            _ => None,
+        }
+    }
 }
 
 impl CommandHandler for Activity {
@@ -84,29 +211,152 @@ impl CommandHandler for Activity {
             None => Err(ERROR_PLAYER_NOT_EXIST),
             Some(player) => {
                 player.check_and_inc_nonce(nonce);
-                match self {
-                    Activity::Bet(market_id, bet_type, amount) => {
-                        Self::handle_bet(player, *market_id, *bet_type, *amount, counter)
-                    },
-                    Activity::Sell(market_id, sell_type, shares) => {
-                        Self::handle_sell(player, *market_id, *sell_type, *shares, counter)
-                    },
-                    Activity::Resolve(market_id, outcome) => {
-                        // Only admin can resolve - we need to check this at a higher level
-                        Self::handle_resolve(*market_id, *outcome, counter)
-                    },
-                    Activity::Claim(market_id) => {
-                        Self::handle_claim(player, *market_id, counter)
-                    },
-                    Activity::WithdrawFees(market_id) => {
-                        // Only admin can withdraw fees - we need to check this at a higher level
-                        Self::handle_withdraw_fees(player, *market_id, counter)
-                    },
-                    Activity::CreateMarket(start_time, end_time, resolution_time, yes_liquidity, no_liquidity, b) => {
-                        // Only admin can create markets - we need to check this at a higher level
-                        Self::handle_create_market(*start_time, *end_time, *resolution_time, *yes_liquidity, *no_liquidity, *b, counter)
-                    }
+                self.apply(player, counter)
+            }
+        }
+    }
+}
+
+impl Activity {
+    fn apply(&self, player: &mut Player, counter: u64) -> Result<(), u32> {
+        match self {
+            Activity::Bet(market_id, bet_type, amount) => {
+                Self::handle_bet(player, *market_id, *bet_type, *amount, counter)
+            },
+            Activity::Sell(market_id, sell_type, shares) => {
+                Self::handle_sell(player, *market_id, *sell_type, *shares, counter)
+            },
+            Activity::Resolve(market_id, outcome) => {
+                // Only admin can resolve - we need to check this at a higher level
+                Self::handle_resolve(*market_id, *outcome, counter)
+            },
+            Activity::Claim(market_id) => {
+                Self::handle_claim(player, *market_id, counter)
+            },
+            Activity::ClaimAll => {
+                Self::handle_claim_all(player, counter)
+            },
+            Activity::WithdrawFees(market_id) => {
+                // Only admin can withdraw fees - we need to check this at a higher level
+                Self::handle_withdraw_fees(player, *market_id, counter)
+            },
+            Activity::CreateMarket(start_time, end_time, resolution_time, yes_liquidity, no_liquidity, b, token, kind) => {
+                // Only admin can create markets - we need to check this at a higher level
+                Self::handle_create_market(*start_time, *end_time, *resolution_time, *yes_liquidity, *no_liquidity, *b, *token, *kind, counter)
+            },
+            Activity::TransferShares(market_id, side, shares, to_pid) => {
+                Self::handle_transfer_shares(player, *market_id, *side, *shares, to_pid, counter)
+            },
+            Activity::Transfer(to_pid, amount, token) => {
+                Self::handle_transfer(player, to_pid, *amount, *token, counter)
+            },
+            Activity::CancelWithdrawal(withdrawal_id) => {
+                // Only admin can cancel withdrawals - we need to check this at a higher level
+                Self::handle_cancel_withdrawal(player, *withdrawal_id, counter)
+            },
+            Activity::SetParam(param_id, value) => {
+                // Only admin can change protocol parameters - we need to check this at a higher level
+                Self::handle_set_param(player, *param_id, *value, counter)
+            },
+            Activity::FundFaucet(amount) => {
+                // Only admin can fund the faucet - we need to check this at a higher level
+                crate::faucet::FaucetManager::fund(*amount);
+                player.store();
+                Ok(())
+            },
+            Activity::ClaimFaucet => {
+                crate::faucet::FaucetManager::claim(player, counter)?;
+                player.store();
+                Ok(())
+            },
+            Activity::GrantRole(target, roles) => {
+                RoleManager::grant(target, *roles, counter)?;
+                player.store();
+                Ok(())
+            },
+            Activity::RevokeRole(target, roles) => {
+                RoleManager::revoke(target, *roles, counter)?;
+                player.store();
+                Ok(())
+            },
+            Activity::RotateAdmin(new_admin) => {
+                RoleManager::rotate_super_admin(new_admin, counter)?;
+                player.store();
+                Ok(())
+            },
+            Activity::Batch(activities) => {
+                Self::handle_batch(player, activities, counter)
+            },
+            Activity::PlaceLimitOrder(market_id, side, price, amount) => {
+                let is_clob = crate::state::MarketManager::get_market(*market_id)
+                    .is_some_and(|market| market.is_clob());
+                if is_clob {
+                    ClobBook::place(player, *market_id, *side, *price, *amount, counter)?;
+                    player.store();
+                    return Ok(());
                 }
+                OrderBook::place(player, *market_id, *side, *price, *amount, counter)?;
+                // The new order may already be marketable
+                OrderBook::match_orders(*market_id, MAX_ORDER_FILLS_PER_MATCH, counter)?;
+                Ok(())
+            },
+            Activity::CancelOrder(order_id) => {
+                // Order ids are shared by both order kinds
+                if ClobBook::get_order(*order_id).is_some() {
+                    ClobBook::cancel(player, *order_id, counter)?;
+                } else {
+                    OrderBook::cancel(player, *order_id, counter)?;
+                }
+                player.store();
+                Ok(())
+            },
+            Activity::PlaceTrigger(market_id, side, kind, price, shares) => {
+                TriggerManager::place(player, *market_id, *side, *kind, *price, *shares, counter)?;
+                player.store();
+                Ok(())
+            },
+            Activity::CancelTrigger(trigger_id) => {
+                TriggerManager::cancel(player, *trigger_id, counter)?;
+                player.store();
+                Ok(())
+            },
+            Activity::PlaceParlay(stake, legs) => {
+                ParlayManager::place(player, legs, *stake, counter)?;
+                player.store();
+                Ok(())
+            },
+            Activity::ClaimParlay(owner, parlay_id) => {
+                ParlayManager::claim(player, owner, *parlay_id, counter)?;
+                player.store();
+                Ok(())
+            },
+            Activity::FundParlayBankroll(token, amount) => {
+                ParlayManager::fund(player, *token, *amount)?;
+                player.store();
+                Ok(())
+            },
+            Activity::CreateConditionalMarket(start_time, end_time, resolution_time, yes_liquidity, no_liquidity, b, parent_market_id, condition) => {
+                crate::state::MarketManager::create_conditional_market(
+                    counter + *start_time,
+                    counter + *end_time,
+                    counter + *resolution_time,
+                    *yes_liquidity,
+                    *no_liquidity,
+                    *b,
+                    *parent_market_id,
+                    *condition,
+                )?;
+                Ok(())
+            },
+            Activity::CreateSeries(template) => {
+                SeriesManager::create(template, counter)?;
+                player.store();
+                Ok(())
+            },
+            Activity::StopSeries(series_id) => {
+                SeriesManager::stop(*series_id, counter)?;
+                player.store();
+                Ok(())
             }
         }
     }
@@ -131,19 +381,26 @@ impl Activity {
         let current_time = global_state.ensure_market_active(market_id)?;
         let txid = global_state.txcounter;
 
-        // Check player balance
-        player.data.spend_balance(amount)?;
-
-        // Place bet using unified function
         let mut market = crate::state::MarketManager::get_market(market_id)
             .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+        // Order book markets only trade through limit orders
+        if market.is_clob() {
+            return Err(ERROR_INVALID_MARKET_KIND);
+        }
+
+        // Check player balance in the market's collateral token
+        let credit = player.data.spend_stake_of(market.token, amount)?;
+
+        // Place bet using unified function
         let shares = market.place_bet(bet_type, amount)?;
+        market.add_cost_basis(amount)?;
+        market.record_price(current_time)?;
         crate::state::MarketManager::update_market(market_id, &market);
         
         if bet_type == 1 {
-            player.data.add_yes_shares_for_market(&player.player_id, market_id, shares);
+            player.data.add_yes_shares_for_market(&player.player_id, market_id, shares, amount, credit)?;
         } else {
-            player.data.add_no_shares_for_market(&player.player_id, market_id, shares);
+            player.data.add_no_shares_for_market(&player.player_id, market_id, shares, amount, credit)?;
         }
 
         // Store updated data
@@ -154,6 +411,9 @@ impl Activity {
         
         // Emit IndexedObject event for updated market
         emit_market_indexed_object(&market, market_id);
+
+        // The trade moved the price, resting orders on the other side may now fill
+        OrderBook::match_orders(market_id, MAX_ORDER_FILLS_PER_MATCH, current_time)?;
         
         Ok(())
     }
@@ -179,18 +439,23 @@ impl Activity {
         // Sell shares using unified function
         let mut market = crate::state::MarketManager::get_market(market_id)
             .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+        if market.is_clob() {
+            return Err(ERROR_INVALID_MARKET_KIND);
+        }
         let payout = market.sell_shares(sell_type, shares)?;
-        crate::state::MarketManager::update_market(market_id, &market);
-        
+        market.record_price(current_time)?;
+
         // Update player shares
-        if sell_type == 1 {
-            player.data.subtract_yes_shares_for_market(&player.player_id, market_id, shares)?;
+        let (released, credit) = if sell_type == 1 {
+            player.data.subtract_yes_shares_for_market(&player.player_id, market_id, shares, payout)?
         } else {
-            player.data.subtract_no_shares_for_market(&player.player_id, market_id, shares)?;
-        }
+            player.data.subtract_no_shares_for_market(&player.player_id, market_id, shares, payout)?
+        };
+        market.release_cost_basis(released);
+        crate::state::MarketManager::update_market(market_id, &market);
 
-        // Add payout to player balance
-        player.data.balance += payout;
+        // Add payout to player balance, shares bought with play credit sell back into it
+        player.data.add_proceeds_of(market.token, payout, credit);
 
         // Store updated data
         player.store();
@@ -201,6 +466,8 @@ impl Activity {
         // Emit IndexedObject event for updated market
         emit_market_indexed_object(&market, market_id);
 
+        OrderBook::match_orders(market_id, MAX_ORDER_FILLS_PER_MATCH, current_time)?;
+
         Ok(())
     }
 
@@ -215,8 +482,13 @@ impl Activity {
              return Err(crate::error::ERROR_MARKET_NOT_RESOLVED);
         }
 
-        let outcome_bool = outcome != 0;
-        market.resolve(outcome_bool)?;
+        // A conditional market waits for its parent and is voided if the
+        // condition fails, whatever outcome the resolver reports
+        match crate::state::MarketManager::condition_met(&market) {
+            None => return Err(crate::error::ERROR_CONDITION_PENDING),
+            Some(false) => market.void()?,
+            Some(true) => market.resolve(outcome != 0)?,
+        }
         crate::state::MarketManager::update_market(market_id, &market);
         
         // Emit IndexedObject event for updated market
@@ -226,27 +498,94 @@ impl Activity {
     }
 
     fn handle_claim(player: &mut Player, market_id: u64, _counter: u64) -> Result<(), u32> {
-        let market = crate::state::MarketManager::get_market(market_id)
+        let mut market = crate::state::MarketManager::get_market(market_id)
             .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
-        
+        crate::state::MarketManager::settle_condition(market_id, &mut market)?;
+
         if !market.resolved {
+            if crate::state::MarketManager::condition_met(&market).is_none() {
+                return Err(crate::error::ERROR_CONDITION_PENDING);
+            }
             return Err(crate::error::ERROR_MARKET_NOT_RESOLVED);
         }
 
-        // Check if already claimed
-        player.data.claim_winnings_for_market(&player.player_id, market_id)?;
-
-        // Calculate payout
-        let yes_shares = player.data.get_yes_shares_for_market(&player.player_id, market_id);
-        let no_shares = player.data.get_no_shares_for_market(&player.player_id, market_id);
-        let payout = market.calculate_payout(yes_shares, no_shares)?;
+        let global_state = GLOBAL_STATE.0.borrow();
+        let txid = global_state.txcounter;
+        let current_time = global_state.counter;
 
-        if payout == 0 {
+        // Quote the payout before anything is written; voided markets refund at cost
+        let position = PlayerMarketManager::get_position(&player.player_id, market_id);
+        if position.claimed {
+            return Err(crate::error::ERROR_ALREADY_CLAIMED);
+        }
+        if market.mark_to_market(&position)? == 0 {
             return Err(crate::error::ERROR_NO_WINNING_POSITION);
         }
 
+        let (payout, credit) = if market.is_void() {
+            let refund = player.data.refund_position_for_market(&player.player_id, market_id, &mut market)?;
+            crate::state::MarketManager::update_market(market_id, &market);
+            refund
+        } else {
+            player.data.claim_winnings_for_market(&player.player_id, market_id, &market)?
+        };
+
         // Add payout to balance
-        player.data.add_balance(payout);
+        player.data.add_proceeds_of(market.token, payout, credit);
+        player.store();
+
+        Self::emit_claim_event(player.player_id, market_id, payout, txid, current_time);
+
+        Ok(())
+    }
+
+    fn handle_claim_all(player: &mut Player, _counter: u64) -> Result<(), u32> {
+        let global_state = GLOBAL_STATE.0.borrow();
+        let txid = global_state.txcounter;
+        let current_time = global_state.counter;
+
+        // Collect every resolved, unclaimed position before touching any state
+        let mut claims = vec![];
+        let mut total_payout = 0;
+        for market_id in PlayerMarketManager::get_market_ids(&player.player_id) {
+            let position = PlayerMarketManager::get_position(&player.player_id, market_id);
+            if position.claimed || (position.yes_shares == 0 && position.no_shares == 0) {
+                continue;
+            }
+            let mut market = match crate::state::MarketManager::get_market(market_id) {
+                Some(market) => market,
+                None => continue,
+            };
+            crate::state::MarketManager::settle_condition(market_id, &mut market)?;
+            if !market.resolved {
+                continue;
+            }
+            let payout = if market.is_void() {
+                market.refund_quote(safe_add(position.yes_cost_basis, position.no_cost_basis)?)?
+            } else {
+                market.calculate_payout(position.yes_shares, position.no_shares)?
+            };
+            total_payout = safe_add(total_payout, payout)?;
+            claims.push((market_id, market));
+        }
+
+        if total_payout == 0 {
+            return Err(crate::error::ERROR_NO_WINNING_POSITION);
+        }
+
+        // Losing positions are marked claimed as well so they drop out of later batches
+        for (market_id, mut market) in claims {
+            let (payout, credit) = if market.is_void() {
+                let refund = player.data.refund_position_for_market(&player.player_id, market_id, &mut market)?;
+                crate::state::MarketManager::update_market(market_id, &market);
+                refund
+            } else {
+                player.data.claim_winnings_for_market(&player.player_id, market_id, &market)?
+            };
+            player.data.add_proceeds_of(market.token, payout, credit);
+            Self::emit_claim_event(player.player_id, market_id, payout, txid, current_time);
+        }
+
         player.store();
 
         Ok(())
@@ -255,6 +594,11 @@ impl Activity {
     fn handle_withdraw_fees(player: &mut Player, market_id: u64, _counter: u64) -> Result<(), u32> {
         let mut market = crate::state::MarketManager::get_market(market_id)
             .ok_or(crate::error::ERROR_MARKET_NOT_ACTIVE)?;
+
+        // Fees of a conditional market back its refunds until it settles with a winner
+        if market.is_conditional() && !(market.resolved && market.outcome.is_some()) {
+            return Err(crate::error::ERROR_CONDITION_PENDING);
+        }
         
         let fees_collected = market.total_fees_collected;
         
@@ -263,7 +607,7 @@ impl Activity {
         }
 
         // Transfer fees to admin's balance
-        player.data.add_balance(fees_collected);
+        player.data.add_balance_of(market.token, fees_collected);
         
         // Reset collected fees to zero
         market.total_fees_collected = 0;
@@ -275,12 +619,19 @@ impl Activity {
         Ok(())
     }
 
-    fn handle_create_market(start_time_offset: u64, end_time_offset: u64, resolution_time_offset: u64, yes_liquidity: u64, no_liquidity: u64, b: u64, counter: u64) -> Result<(), u32> {
+    #[allow(clippy::too_many_arguments)]
+    fn handle_create_market(start_time_offset: u64, end_time_offset: u64, resolution_time_offset: u64, yes_liquidity: u64, no_liquidity: u64, b: u64, token: u64, kind: u64, counter: u64) -> Result<(), u32> {
         // Calculate absolute times by adding offsets to current counter
         let absolute_start_time = counter + start_time_offset;
         let absolute_end_time = counter + end_time_offset;
         let absolute_resolution_time = counter + resolution_time_offset;
 
+        // Order book markets need no liquidity, so the LMSR arguments are ignored
+        if kind == MARKET_KIND_CLOB {
+            crate::state::MarketManager::create_clob_market(absolute_start_time, absolute_end_time, absolute_resolution_time, token)?;
+            return Ok(());
+        }
+
         let _market_id = crate::state::MarketManager::create_market_with_liquidity(
             absolute_start_time,
             absolute_end_time,
@@ -288,11 +639,116 @@ impl Activity {
             yes_liquidity,
             no_liquidity,
             b,
+            token,
         )?;
 
         Ok(())
     }
 
+    fn handle_transfer_shares(player: &mut Player, market_id: u64, side: u64, shares: u64, to_pid: &[u64; 2], _counter: u64) -> Result<(), u32> {
+        if shares == 0 {
+            return Err(ERROR_INVALID_BET_AMOUNT);
+        }
+        if side > 1 {
+            return Err(ERROR_INVALID_BET_TYPE);
+        }
+        if *to_pid == player.player_id {
+            return Err(ERROR_INVALID_TRANSFER);
+        }
+
+        let market = crate::state::MarketManager::get_market(market_id)
+            .ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+        if market.resolved {
+            return Err(ERROR_MARKET_ALREADY_RESOLVED);
+        }
+        if Player::get_from_pid(to_pid).is_none() {
+            return Err(ERROR_PLAYER_NOT_EXIST);
+        }
+
+        let global_state = GLOBAL_STATE.0.borrow();
+        let txid = global_state.txcounter;
+        let current_time = global_state.counter;
+
+        let mut from_position = PlayerMarketManager::get_position(&player.player_id, market_id);
+        let mut to_position = PlayerMarketManager::get_position(to_pid, market_id);
+        if from_position.claimed || to_position.claimed {
+            return Err(ERROR_ALREADY_CLAIMED);
+        }
+
+        let is_yes = side == 1;
+        // Play credit the shares were bought with moves with them
+        let (cost, credit) = from_position.transfer_out(is_yes, shares)?;
+        to_position.transfer_in(is_yes, shares, cost, credit)?;
+        PlayerMarketManager::store_position(&player.player_id, market_id, &from_position);
+        PlayerMarketManager::store_position(to_pid, market_id, &to_position);
+
+        // Persist the sender's nonce
+        player.store();
+
+        Self::emit_share_transfer_event(player.player_id, *to_pid, market_id, side, shares, txid, current_time);
+
+        Ok(())
+    }
+
+    fn handle_transfer(player: &mut Player, to_pid: &[u64; 2], amount: u64, token: u64, _counter: u64) -> Result<(), u32> {
+        if amount == 0 {
+            return Err(ERROR_INVALID_BET_AMOUNT);
+        }
+        crate::math_safe::validate_token(token)?;
+        if *to_pid == player.player_id {
+            return Err(ERROR_INVALID_TRANSFER);
+        }
+
+        crate::journal::track_player(to_pid);
+        let mut recipient = Player::get_from_pid(to_pid).ok_or(ERROR_PLAYER_NOT_EXIST)?;
+        // Play credit stays with the player it was granted to
+        if player.data.withdrawable_balance_of(token) < amount {
+            return Err(ERROR_INSUFFICIENT_BALANCE);
+        }
+
+        let global_state = GLOBAL_STATE.0.borrow();
+        let txid = global_state.txcounter;
+        let current_time = global_state.counter;
+
+        player.data.spend_balance_of(token, amount)?;
+        recipient.data.add_balance_of(token, amount);
+
+        player.store();
+        recipient.store();
+
+        Self::emit_balance_transfer_event(player.player_id, *to_pid, amount, token, txid, current_time);
+
+        Ok(())
+    }
+
+    fn handle_cancel_withdrawal(player: &mut Player, withdrawal_id: u64, counter: u64) -> Result<(), u32> {
+        WithdrawalManager::cancel(withdrawal_id, player, counter)?;
+        // Persist the admin's nonce
+        player.store();
+        Ok(())
+    }
+
+    // All sub-activities succeed or none of their writes and events survive. Inside
+    // `Transaction::process` this journal nests in the per-transaction one.
+    fn handle_batch(player: &mut Player, activities: &[Activity], counter: u64) -> Result<(), u32> {
+        crate::journal::atomic(|| {
+            crate::journal::track_player(&player.player_id);
+            for activity in activities {
+                activity.apply(player, counter)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn handle_set_param(player: &mut Player, param_id: u64, value: u64, counter: u64) -> Result<(), u32> {
+        crate::params::ProtocolParams::update(param_id, value)?;
+        player.store();
+
+        let mut data = vec![param_id, value, counter];
+        insert_event(EVENT_PARAM_UPDATE, &mut data);
+        Ok(())
+    }
+
     fn emit_bet_event(player_id: [u64; 2], market_id: u64, bet_type: u64, amount: u64, shares: u64, txid: u64, counter: u64) {
         let mut data = vec![
             txid,
@@ -320,6 +776,47 @@ impl Activity {
         ];
         insert_event(EVENT_BET_UPDATE, &mut data); // Reuse BET_UPDATE event for now
     }
+
+    fn emit_claim_event(player_id: [u64; 2], market_id: u64, payout: u64, txid: u64, counter: u64) {
+        let mut data = vec![
+            txid,
+            player_id[0],
+            player_id[1],
+            market_id,
+            payout,
+            counter,
+        ];
+        insert_event(EVENT_CLAIM_UPDATE, &mut data);
+    }
+
+    fn emit_share_transfer_event(from_pid: [u64; 2], to_pid: [u64; 2], market_id: u64, side: u64, shares: u64, txid: u64, counter: u64) {
+        let mut data = vec![
+            txid,
+            from_pid[0],
+            from_pid[1],
+            to_pid[0],
+            to_pid[1],
+            market_id,
+            side,
+            shares,
+            counter,
+        ];
+        insert_event(EVENT_SHARE_TRANSFER, &mut data);
+    }
+
+    fn emit_balance_transfer_event(from_pid: [u64; 2], to_pid: [u64; 2], amount: u64, token: u64, txid: u64, counter: u64) {
+        let mut data = vec![
+            txid,
+            from_pid[0],
+            from_pid[1],
+            to_pid[0],
+            to_pid[1],
+            amount,
+            token,
+            counter,
+        ];
+        insert_event(EVENT_BALANCE_TRANSFER, &mut data);
+    }
 }
 
 pub fn decode_error(e: u32) -> &'static str {
@@ -338,6 +835,238 @@ pub fn decode_error(e: u32) -> &'static str {
         ERROR_PLAYER_NOT_EXIST => "PlayerNotExist",
         ERROR_PLAYER_ALREADY_EXISTS => "PlayerAlreadyExists",
         ERROR_NO_FEES_TO_WITHDRAW => "NoFeesToWithdraw",
+        ERROR_INVALID_TRANSFER => "InvalidTransfer",
+        ERROR_INVALID_TOKEN => "InvalidToken",
+        ERROR_INVALID_WITHDRAW => "InvalidWithdraw",
+        ERROR_DEPOSIT_ALREADY_PROCESSED => "DepositAlreadyProcessed",
+        ERROR_WITHDRAW_LIMIT_EXCEEDED => "WithdrawLimitExceeded",
+        ERROR_WITHDRAWAL_NOT_FOUND => "WithdrawalNotFound",
+        ERROR_INVALID_PARAM => "InvalidParam",
+        ERROR_FAUCET_EXHAUSTED => "FaucetExhausted",
+        ERROR_FAUCET_COOLDOWN => "FaucetCooldown",
+        ERROR_FAUCET_DISABLED => "FaucetDisabled",
+        ERROR_INVALID_ROLE => "InvalidRole",
+        ERROR_UNKNOWN_COMMAND => "UnknownCommand",
+        ERROR_INVALID_ARITY => "InvalidArity",
+        ERROR_FIELD_OUT_OF_RANGE => "FieldOutOfRange",
+        ERROR_UNSUPPORTED_VERSION => "UnsupportedVersion",
+        ERROR_UNSUPPORTED_EXTENSION => "UnsupportedExtension",
+        ERROR_TRANSACTION_EXPIRED => "TransactionExpired",
+        ERROR_ORDER_NOT_FOUND => "OrderNotFound",
+        ERROR_INVALID_PRICE => "InvalidPrice",
+        ERROR_TOO_MANY_ORDERS => "TooManyOrders",
+        ERROR_TRIGGER_NOT_FOUND => "TriggerNotFound",
+        ERROR_TOO_MANY_TRIGGERS => "TooManyTriggers",
+        ERROR_INVALID_MARKET_KIND => "InvalidMarketKind",
+        ERROR_INVALID_PARLAY => "InvalidParlay",
+        ERROR_PARLAY_NOT_FOUND => "ParlayNotFound",
+        ERROR_PARLAY_BANKROLL_EXHAUSTED => "ParlayBankrollExhausted",
+        ERROR_CONDITION_PENDING => "ConditionPending",
+        ERROR_INVALID_CONDITION => "InvalidCondition",
+        ERROR_SERIES_NOT_FOUND => "SeriesNotFound",
+        ERROR_TOO_MANY_SERIES => "TooManySeries",
+        ERROR_INVALID_TWAP_WINDOW => "InvalidTwapWindow",
         _ => "Unknown",
     }
-} 
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settlement::SettlementInfo;
+    use crate::state::MarketManager;
+    use crate::test_support;
+    use zkwasm_rest_abi::StorageData;
+
+    #[test]
+    fn test_withdraw_full_amount_round_trips_through_settlement() {
+        let _guard = test_support::lock();
+        let amount = 5_000_000_000_000u64; // well above 32 bits
+        let address = [0x1234_5678, 0x0102_0304_0506_0708, 0x1112_1314_1516_1718];
+        let withdraw = Withdraw { token: 2, amount, address };
+
+        SettlementInfo::flush_settlement();
+        SettlementInfo::append_settlement(withdraw.settlement_info().unwrap());
+        let packed = Withdraw::from_packed(2, [(address[0] << 32) | 77, address[1], address[2]]);
+        SettlementInfo::append_settlement(packed.settlement_info().unwrap());
+        let bytes = SettlementInfo::flush_settlement();
+
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(u64::from_be_bytes(bytes[24..32].try_into().unwrap()), amount);
+        assert_eq!(u64::from_be_bytes(bytes[56..64].try_into().unwrap()), 77);
+        // Both layouts encode the same feature and address bytes
+        assert_eq!(bytes[0..24], bytes[32..56]);
+        assert_eq!(SettlementInfo::settlement_size(), 0);
+    }
+
+    fn market_words(market_id: u64) -> Vec<u64> {
+        let mut data = vec![];
+        MarketManager::get_market(market_id).unwrap().to_data(&mut data);
+        data
+    }
+
+    #[test]
+    fn test_batch_applies_all_or_nothing() {
+        let _guard = test_support::lock();
+        let market_id = test_support::market(0);
+        let pid = test_support::player(10_000);
+
+        // The second bet overdraws the balance, so the first must not stick either
+        let market_before = market_words(market_id);
+        let events_before = unsafe { crate::event::EVENTS.len() };
+        let batch = Activity::Batch(vec![Activity::Bet(market_id, 1, 1_000), Activity::Bet(market_id, 0, 50_000)]);
+        assert!(batch.handle(&pid, 0, &[0; 4], 0).is_err());
+        let player = Player::get_from_pid(&pid).unwrap();
+        assert_eq!((player.nonce, player.data.balance), (0, 10_000));
+        assert_eq!(PlayerMarketManager::get_position(&pid, market_id).yes_shares, 0);
+        assert!(PlayerMarketManager::get_market_ids(&pid).is_empty());
+        assert_eq!(market_words(market_id), market_before);
+        assert_eq!(unsafe { crate::event::EVENTS.len() }, events_before);
+
+        let batch = Activity::Batch(vec![Activity::Bet(market_id, 1, 1_000), Activity::Bet(market_id, 0, 2_000)]);
+        batch.handle(&pid, 0, &[0; 4], 0).unwrap();
+        let player = Player::get_from_pid(&pid).unwrap();
+        assert_eq!((player.nonce, player.data.balance), (1, 7_000));
+        let position = PlayerMarketManager::get_position(&pid, market_id);
+        assert!(position.yes_shares > 0 && position.no_shares > 0);
+    }
+
+    #[test]
+    fn test_conditional_market_refunds_when_condition_fails() {
+        let _guard = test_support::lock();
+        let parent = test_support::market(0);
+        let child = MarketManager::create_conditional_market(0, 1_000, 1_000, 1_000, 1_000, 10_000, parent, 1).unwrap();
+        assert_eq!(MarketManager::create_conditional_market(0, 1_000, 1_000, 1_000, 1_000, 10_000, 999, 1), Err(ERROR_INVALID_CONDITION));
+        let pid = test_support::player(10_000);
+
+        Activity::Bet(child, 1, 1_000).handle(&pid, 0, &[0; 4], 0).unwrap();
+        let cost = PlayerMarketManager::get_position(&pid, child).yes_cost_basis;
+        assert_eq!(Activity::Claim(child).handle(&pid, 1, &[0; 4], 0), Err(ERROR_CONDITION_PENDING));
+        assert_eq!(Activity::Resolve(child, 1).handle(&pid, 1, &[0; 4], 0), Err(ERROR_CONDITION_PENDING));
+
+        // The parent resolves NO, so the YES-conditional child is voided
+        let mut market = MarketManager::get_market(parent).unwrap();
+        market.resolve(false).unwrap();
+        MarketManager::update_market(parent, &market);
+        assert_eq!(Activity::Bet(child, 1, 1_000).handle(&pid, 1, &[0; 4], 0), Err(ERROR_MARKET_NOT_ACTIVE));
+        assert_eq!(Activity::WithdrawFees(child).handle(&pid, 1, &[0; 4], 0), Err(ERROR_CONDITION_PENDING));
+
+        Activity::Claim(child).handle(&pid, 1, &[0; 4], 0).unwrap();
+        let player = Player::get_from_pid(&pid).unwrap();
+        assert_eq!(player.data.balance, 9_000 + cost);
+        assert!(MarketManager::get_market(child).unwrap().is_void());
+        let position = PlayerMarketManager::get_position(&pid, child);
+        assert!(position.claimed);
+        assert_eq!((position.yes_cost_basis, position.yes_total_received), (0, cost));
+        assert_eq!(Activity::Claim(child).handle(&pid, 2, &[0; 4], 0), Err(ERROR_ALREADY_CLAIMED));
+    }
+
+    #[test]
+    fn test_void_refunds_are_pro_rata() {
+        let _guard = test_support::lock();
+        let parent = test_support::market(0);
+        let child = MarketManager::create_conditional_market(0, 1_000, 1_000, 1_000, 1_000, 10_000, parent, 1).unwrap();
+        let (early, late, other) = (test_support::player(10_000), test_support::player(10_000), test_support::player(10_000));
+
+        // The early buyer sells at a profit, leaving less than the open cost basis behind
+        Activity::Bet(child, 1, 1_000).handle(&early, 0, &[0; 4], 0).unwrap();
+        Activity::Bet(child, 1, 5_000).handle(&late, 0, &[0; 4], 0).unwrap();
+        let shares = PlayerMarketManager::get_position(&early, child).yes_shares;
+        Activity::Sell(child, 1, shares).handle(&early, 1, &[0; 4], 0).unwrap();
+        assert!(Player::get_from_pid(&early).unwrap().data.balance > 10_000);
+        Activity::Bet(child, 0, 2_000).handle(&other, 0, &[0; 4], 0).unwrap();
+
+        let mut market = MarketManager::get_market(parent).unwrap();
+        market.resolve(false).unwrap();
+        MarketManager::update_market(parent, &market);
+        let bases = [5_000, 2_000];
+        for (pid, basis) in [(late, bases[0]), (other, bases[1])] {
+            assert_eq!(PlayerMarketManager::get_position(&pid, child).yes_cost_basis
+                + PlayerMarketManager::get_position(&pid, child).no_cost_basis, basis);
+            Activity::Claim(child).handle(&pid, 1, &[0; 4], 0).unwrap();
+        }
+
+        // Both are short by the same fraction, whoever claims first
+        let market = MarketManager::get_market(child).unwrap();
+        assert_eq!(market.open_cost_basis, 7_000);
+        assert!(market.refund_funds < 7_000);
+        for (pid, basis) in [(late, bases[0]), (other, bases[1])] {
+            let refund = Player::get_from_pid(&pid).unwrap().data.balance - (10_000 - basis);
+            assert_eq!(refund, basis * market.refund_funds / 7_000);
+        }
+    }
+
+    #[test]
+    fn test_conditional_market_settles_when_condition_holds() {
+        let _guard = test_support::lock();
+        let parent = test_support::market(0);
+        let child = MarketManager::create_conditional_market(0, 1_000, 1_000, 1_000, 1_000, 10_000, parent, 0).unwrap();
+        let pid = test_support::player(10_000);
+        Activity::Bet(child, 1, 1_000).handle(&pid, 0, &[0; 4], 0).unwrap();
+
+        let mut market = MarketManager::get_market(parent).unwrap();
+        market.resolve(false).unwrap();
+        MarketManager::update_market(parent, &market);
+        Activity::Resolve(child, 1).handle(&pid, 1, &[0; 4], 0).unwrap();
+        assert_eq!(MarketManager::get_market(child).unwrap().outcome, Some(true));
+
+        // Resolve does not store the resolver, so the nonce is not consumed
+        Activity::Claim(child).handle(&pid, 1, &[0; 4], 0).unwrap();
+        assert!(Player::get_from_pid(&pid).unwrap().data.balance > 9_000);
+    }
+
+    #[test]
+    fn test_play_credit_survives_trading() {
+        let _guard = test_support::lock();
+        let market_id = test_support::market(0);
+        let (pid, friend) = (test_support::new_pid(), test_support::player(0));
+        let mut player = Player::new_from_pid(pid);
+        player.data.add_faucet_grant(2_000, true);
+        player.store();
+
+        // Selling shares bought with credit pays the credit back, not withdrawable balance
+        Activity::Bet(market_id, 1, 1_000).handle(&pid, 0, &[0; 4], 0).unwrap();
+        let shares = PlayerMarketManager::get_position(&pid, market_id).yes_shares;
+        Activity::Sell(market_id, 1, shares / 2).handle(&pid, 1, &[0; 4], 0).unwrap();
+        let data = Player::get_from_pid(&pid).unwrap().data;
+        assert_eq!(data.play_credit, 1_500);
+        assert_eq!(data.withdrawable_balance_of(0), data.balance - 1_500); // only the profit
+
+        // Shares handed to another account carry their credit along
+        Activity::TransferShares(market_id, 1, shares - shares / 2, friend).handle(&pid, 2, &[0; 4], 0).unwrap();
+        assert_eq!(PlayerMarketManager::get_position(&friend, market_id).yes_play_credit, 500);
+        Activity::Sell(market_id, 1, shares - shares / 2).handle(&friend, 0, &[0; 4], 0).unwrap();
+        let data = Player::get_from_pid(&friend).unwrap().data;
+        assert_eq!(data.play_credit, 500.min(data.balance));
+    }
+
+    #[test]
+    fn test_deposit_replay_checks_the_full_hash() {
+        let _guard = test_support::lock();
+        let (admin, pid) = (test_support::player(0), test_support::player(0));
+        let deposit = |tx_hash| Deposit { token: 0, data: [pid[0], pid[1], 100], tx_hash };
+        deposit([7, 8, 9, 1]).handle(&admin, 0, &[0; 4], 0).unwrap();
+        // Same first three words, different tail: a different L1 transaction
+        deposit([7, 8, 9, 2]).handle(&admin, 1, &[0; 4], 0).unwrap();
+        assert_eq!(deposit([7, 8, 9, 1]).handle(&admin, 2, &[0; 4], 0), Err(ERROR_DEPOSIT_ALREADY_PROCESSED));
+        assert_eq!(DepositManager::get_record(&[7, 8, 9, 2]).unwrap().amount, 100);
+        assert_eq!(Player::get_from_pid(&pid).unwrap().data.balance, 200);
+    }
+
+    #[test]
+    fn test_deposit_by_depositor_without_player_is_rejected() {
+        let _guard = test_support::lock();
+        let pid = test_support::player(0);
+        let deposit = Deposit { token: 0, data: [pid[0], pid[1], 100], tx_hash: [3, 1, 4, 1] };
+        assert_eq!(deposit.handle(&test_support::new_pid(), 0, &[0; 4], 0), Err(ERROR_PLAYER_NOT_EXIST));
+        assert!(!DepositManager::is_processed(&[3, 1, 4, 1]));
+    }
+
+    #[test]
+    fn test_withdraw_rejects_malformed_encoding() {
+        let address = [0x1_0000_0000, 1, 2]; // high word wider than 32 bits
+        assert_eq!(Withdraw { token: 0, amount: 1, address }.settlement_info().err(), Some(ERROR_INVALID_WITHDRAW));
+        assert_eq!(Withdraw { token: 0, amount: 0, address: [0, 1, 2] }.settlement_info().err(), Some(ERROR_INVALID_BET_AMOUNT));
+        assert_eq!(Withdraw { token: 99, amount: 1, address: [0, 1, 2] }.settlement_info().err(), Some(ERROR_INVALID_TOKEN));
+    }
+}
@@ -39,9 +39,54 @@ pub const EVENT_PLAYER_UPDATE: u64 = 3;
 
 // Market constants
 pub const PLATFORM_FEE_RATE: u64 = 100; // 1% platform fee (100/10000)
+pub const MARKET_FEE_PROTOCOL: u64 = u64::MAX; // market fee rate that follows the platform fee param
 
-// New player initial balance
+// Number of collateral token indexes supported for deposits, withdrawals and markets
+pub const MAX_COLLATERAL_TOKENS: u64 = 8;
+
+// Withdrawal safety limits (amounts are summed across tokens).
+// These and the other tunables below are defaults for `ProtocolParams`.
+pub const WITHDRAW_EPOCH_TICKS: u64 = TICKS_PER_DAY;
+pub const PLAYER_WITHDRAW_LIMIT_PER_EPOCH: u64 = 10_000_000;
+pub const GLOBAL_WITHDRAW_LIMIT_PER_EPOCH: u64 = 1_000_000_000;
+pub const LARGE_WITHDRAW_THRESHOLD: u64 = 1_000_000;   // withdrawals at or above this are delayed
+pub const LARGE_WITHDRAW_DELAY_TICKS: u64 = TICKS_PER_DAY;
+pub const MAX_WITHDRAW_RELEASES_PER_TICK: u64 = 16;
+
+// Maximum number of sub-commands in an atomic batch
+pub const MAX_BATCH_COMMANDS: u64 = 8;
+
+// Limit order book bounds, keeping the cost of matching predictable
+pub const MAX_ORDERS_PER_BOOK: u64 = 64;       // resting orders per market and side
+pub const MAX_ORDERS_PER_OWNER: u64 = 4;       // resting orders of one player per market and side
+pub const MAX_ORDER_FILLS_PER_MATCH: u64 = 4;  // fills triggered by a single trade or placement
+pub const MAX_ORDER_FILLS_PER_TICK: u64 = 16;  // fills across all markets in one tick
+pub const MAX_CLOB_FILLS_PER_ORDER: u64 = 8;   // resting bids an incoming order book bid can cross
+
+// Parlay bounds
+pub const MAX_PARLAY_LEGS: u64 = 4;
+pub const PARLAY_MARGIN_BPS: u64 = 500; // default margin taken off fair parlay odds (5%)
+pub const MIN_PARLAY_LEG_PRICE: u64 = 20_000;       // 2%, so a single leg pays at most 50x
+
+// Recurring market series bounds
+pub const MAX_ACTIVE_SERIES: u64 = 64;              // series rolled over at tick
+pub const MIN_SERIES_DURATION: u64 = TICKS_PER_HOUR; // so a series opens at most 24 markets a day
+
+// TWAP checkpoints, together covering one day of trading
+pub const TWAP_OBSERVATION_INTERVAL: u64 = 10 * TICKS_PER_MINUTE; // ticks between checkpoints
+pub const MAX_TWAP_OBSERVATIONS: u64 = 144;                       // checkpoints kept per market
+pub const TWAP_QUOTE_WINDOW: u64 = TICKS_PER_HOUR;                // TWAP published at checkpoints and floor of parlay leg prices
+
+// Stop-loss / take-profit bounds
+pub const MAX_ACTIVE_TRIGGERS: u64 = 256;         // triggers waiting across all markets
+pub const MAX_TRIGGERS_PER_OWNER: u64 = 8;        // triggers one player may have waiting
+pub const MAX_TRIGGER_CHECKS_PER_TICK: u64 = 16;  // triggers evaluated per tick, in rotation
+
+// New player initial balance, paid out of the faucet budget
 pub const NEW_PLAYER_INITIAL_BALANCE: u64 = 100000; // Initial balance for new players
+pub const INITIAL_FAUCET_BUDGET: u64 = 100_000_000; // Faucet budget of a fresh state (1000 grants)
+pub const FAUCET_COOLDOWN_TICKS: u64 = 0;           // 0 = only the grant at install, no repeat claims
+pub const FAUCET_PLAY_CREDIT: u64 = 1;              // 1 = grants are non-withdrawable play credit
 
 // Price precision constants
 pub const BASIS_POINTS_PRECISION: u64 = 10000;     // 10,000 basis points = 100%
@@ -55,24 +100,30 @@ pub const PERCENTAGE_TO_PRICE: u64 = PRICE_PRECISION / PERCENTAGE_PRECISION;
 // Fee calculation constant (matches PLATFORM_FEE_RATE denominator)
 pub const FEE_BASIS_POINTS: u64 = 10000; // Same as BASIS_POINTS_PRECISION for fees
 
-// Default market configuration
-pub struct DefaultMarketConfig {
-    pub start_time: u64,
-    pub end_time: u64,
-    pub resolution_time: u64,
+// Market configuration, relative to the counter at which a market starts.
+// Recurring series store one of these to instantiate each market.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct MarketTemplate {
+    pub duration: u64,         // ticks from start to end of trading
+    pub resolution_delay: u64, // ticks from end of trading to resolution
     pub initial_yes_liquidity: u64,
     pub initial_no_liquidity: u64,
+    pub b: u64,
+    pub fee_rate: u64,         // basis points, MARKET_FEE_PROTOCOL follows the platform fee
+    pub token: u64,
+    pub metadata_hash: [u64; 4], // hash of the off-chain question text
 }
 
-lazy_static::lazy_static! {
-    pub static ref DEFAULT_MARKET: DefaultMarketConfig = DefaultMarketConfig {
-        start_time: 0,      // Start immediately (counter = 0)
-        end_time: TICKS_PER_4_MONTHS,    // End after 4 months
-        resolution_time: TICKS_PER_4_MONTHS, // Resolution time same as end time (4 months)
-        initial_yes_liquidity: 100000, // Initial YES liquidity for AMM
-        initial_no_liquidity: 100000,  // Initial NO liquidity for AMM
-    };
-}
+pub const DEFAULT_MARKET: MarketTemplate = MarketTemplate {
+    duration: TICKS_PER_4_MONTHS,    // End after 4 months
+    resolution_delay: 0,             // Resolution time same as end time
+    initial_yes_liquidity: 100000,   // Initial YES liquidity for AMM
+    initial_no_liquidity: 100000,    // Initial NO liquidity for AMM
+    b: 100000,
+    fee_rate: MARKET_FEE_PROTOCOL,
+    token: 0,
+    metadata_hash: [0; 4],
+};
 
 // Time conversion helpers (5 seconds per tick)
 pub const SECONDS_PER_TICK: u64 = 5;
@@ -81,7 +132,7 @@ pub const TICKS_PER_HOUR: u64 = 720;
 pub const TICKS_PER_DAY: u64 = 17280;
 pub const TICKS_PER_4_MONTHS: u64 = 2073600; // 120 days × 17280 ticks/day
 
-impl DefaultMarketConfig {
+impl MarketTemplate {
     /// Convert seconds to ticks
     pub fn seconds_to_ticks(seconds: u64) -> u64 {
         seconds / SECONDS_PER_TICK
@@ -94,7 +145,7 @@ impl DefaultMarketConfig {
     
     /// Get market duration in ticks
     pub fn duration_ticks(&self) -> u64 {
-        self.end_time - self.start_time
+        self.duration
     }
     
     /// Get market duration in seconds
@@ -0,0 +1,74 @@
+use serde::Serialize;
+use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
+
+// Record of an L1 deposit that has been credited, keyed by its L1 transaction hash
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct DepositRecord {
+    pub tx_hash_tail: u64, // last word of the tx hash; the first three words form the storage key
+    pub player_id: [u64; 2],
+    pub token: u64,
+    pub amount: u64,
+    pub counter: u64,
+}
+
+impl StorageData for DepositRecord {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        DepositRecord {
+            tx_hash_tail: *u64data.next().unwrap(),
+            player_id: [*u64data.next().unwrap(), *u64data.next().unwrap()],
+            token: *u64data.next().unwrap(),
+            amount: *u64data.next().unwrap(),
+            counter: *u64data.next().unwrap(),
+        }
+    }
+
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.tx_hash_tail);
+        data.push(self.player_id[0]);
+        data.push(self.player_id[1]);
+        data.push(self.token);
+        data.push(self.amount);
+        data.push(self.counter);
+    }
+}
+
+pub struct DepositManager;
+
+impl DepositManager {
+    const DEPOSIT_PREFIX: u64 = 5; // Prefix for processed L1 deposit records
+
+    fn key(tx_hash: &[u64; 4]) -> [u64; 4] {
+        [Self::DEPOSIT_PREFIX, tx_hash[0], tx_hash[1], tx_hash[2]]
+    }
+
+    // Every record whose hash shares the first three words, in processing order
+    fn get_records(tx_hash: &[u64; 4]) -> Vec<DepositRecord> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let mut data = kvpair.get(&Self::key(tx_hash));
+        let mut u64data = data.iter_mut();
+        let mut records = vec![];
+        while u64data.len() > 0 {
+            records.push(DepositRecord::from_data(&mut u64data));
+        }
+        records
+    }
+
+    pub fn get_record(tx_hash: &[u64; 4]) -> Option<DepositRecord> {
+        Self::get_records(tx_hash).into_iter().find(|record| record.tx_hash_tail == tx_hash[3])
+    }
+
+    pub fn is_processed(tx_hash: &[u64; 4]) -> bool {
+        Self::get_record(tx_hash).is_some()
+    }
+
+    // Appends to the records stored under the same key so two hashes that only
+    // differ in the last word are both remembered
+    pub fn store_record(tx_hash: &[u64; 4], record: &DepositRecord) {
+        let mut data = vec![];
+        for stored in Self::get_records(tx_hash) {
+            stored.to_data(&mut data);
+        }
+        record.to_data(&mut data);
+        crate::journal::set(&Self::key(tx_hash), data.as_slice());
+    }
+}
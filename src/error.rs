@@ -15,6 +15,37 @@ pub const ERROR_INVALID_MARKET_TITLE: u32 = 1014;
 pub const ERROR_PLAYER_NOT_EXIST: u32 = 6;
 pub const ERROR_PLAYER_ALREADY_EXISTS: u32 = 8;
 pub const ERROR_NO_FEES_TO_WITHDRAW: u32 = 1013;
+pub const ERROR_INVALID_TRANSFER: u32 = 1015;
+pub const ERROR_INVALID_TOKEN: u32 = 1016;
+pub const ERROR_INVALID_WITHDRAW: u32 = 1017;
+pub const ERROR_DEPOSIT_ALREADY_PROCESSED: u32 = 1018;
+pub const ERROR_WITHDRAW_LIMIT_EXCEEDED: u32 = 1019;
+pub const ERROR_WITHDRAWAL_NOT_FOUND: u32 = 1020;
+pub const ERROR_INVALID_PARAM: u32 = 1021;
+pub const ERROR_FAUCET_EXHAUSTED: u32 = 1022;
+pub const ERROR_FAUCET_COOLDOWN: u32 = 1023;
+pub const ERROR_FAUCET_DISABLED: u32 = 1024;
+pub const ERROR_INVALID_ROLE: u32 = 1025;
+pub const ERROR_UNKNOWN_COMMAND: u32 = 1026;
+pub const ERROR_INVALID_ARITY: u32 = 1027;
+pub const ERROR_FIELD_OUT_OF_RANGE: u32 = 1028;
+pub const ERROR_UNSUPPORTED_VERSION: u32 = 1029;
+pub const ERROR_UNSUPPORTED_EXTENSION: u32 = 1030;
+pub const ERROR_TRANSACTION_EXPIRED: u32 = 1031;
+pub const ERROR_ORDER_NOT_FOUND: u32 = 1032;
+pub const ERROR_INVALID_PRICE: u32 = 1033;
+pub const ERROR_TOO_MANY_ORDERS: u32 = 1034;
+pub const ERROR_TRIGGER_NOT_FOUND: u32 = 1035;
+pub const ERROR_TOO_MANY_TRIGGERS: u32 = 1036;
+pub const ERROR_INVALID_MARKET_KIND: u32 = 1037;
+pub const ERROR_INVALID_PARLAY: u32 = 1038;
+pub const ERROR_PARLAY_NOT_FOUND: u32 = 1039;
+pub const ERROR_PARLAY_BANKROLL_EXHAUSTED: u32 = 1040;
+pub const ERROR_CONDITION_PENDING: u32 = 1041;
+pub const ERROR_INVALID_CONDITION: u32 = 1042;
+pub const ERROR_SERIES_NOT_FOUND: u32 = 1043;
+pub const ERROR_TOO_MANY_SERIES: u32 = 1044;
+pub const ERROR_INVALID_TWAP_WINDOW: u32 = 1045;
 
 // Security-related error codes
 pub const ERROR_OVERFLOW: u32 = 100;
@@ -22,4 +53,26 @@ pub const ERROR_DIVISION_BY_ZERO: u32 = 101;
 pub const ERROR_UNDERFLOW: u32 = 102;
 pub const ERROR_BET_TOO_LARGE: u32 = 103;
 pub const ERROR_LIQUIDITY_TOO_HIGH: u32 = 104;
-pub const ERROR_INVALID_CALCULATION: u32 = 105; 
\ No newline at end of file
+pub const ERROR_INVALID_CALCULATION: u32 = 105; 
+
+// Reasons a transaction could not be decoded into a command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodeError {
+    UnknownCommand(u64),                        // command id
+    WrongArity { command: u64, len: usize },    // number of u64 params received, header included
+    OutOfRange { command: u64, index: usize },  // position of the offending param
+    UnsupportedVersion(u64),                    // encoding version from the header
+    UnsupportedExtension { command: u64, flags: u64 },
+}
+
+impl DecodeError {
+    pub fn code(&self) -> u32 {
+        match self {
+            DecodeError::UnknownCommand(_) => ERROR_UNKNOWN_COMMAND,
+            DecodeError::WrongArity { .. } => ERROR_INVALID_ARITY,
+            DecodeError::OutOfRange { .. } => ERROR_FIELD_OUT_OF_RANGE,
+            DecodeError::UnsupportedVersion(_) => ERROR_UNSUPPORTED_VERSION,
+            DecodeError::UnsupportedExtension { .. } => ERROR_UNSUPPORTED_EXTENSION,
+        }
+    }
+}
@@ -26,10 +26,23 @@ pub const EVENT_PLAYER_UPDATE: u64 = 1;
 pub const EVENT_MARKET_UPDATE: u64 = 2;
 pub const EVENT_BET_UPDATE: u64 = 3;
 pub const EVENT_INDEXED_OBJECT: u64 = 4;
+pub const EVENT_CLAIM_UPDATE: u64 = 5;
+pub const EVENT_SHARE_TRANSFER: u64 = 6;
+pub const EVENT_BALANCE_TRANSFER: u64 = 7;
+pub const EVENT_DEPOSIT: u64 = 8;
+pub const EVENT_WITHDRAWAL_UPDATE: u64 = 9;
+pub const EVENT_PARAM_UPDATE: u64 = 10;
+pub const EVENT_FAUCET_GRANT: u64 = 11;
+pub const EVENT_ROLE_UPDATE: u64 = 12;
+pub const EVENT_ORDER_UPDATE: u64 = 13;
+pub const EVENT_TRIGGER_UPDATE: u64 = 14;
+pub const EVENT_PARLAY_UPDATE: u64 = 15;
+pub const EVENT_SERIES_UPDATE: u64 = 16;
 
 // Market info constants for IndexedObject
 pub const MARKET_INFO: u64 = 1;
 pub const LIQUIDITY_HISTORY_INFO: u64 = 2;
+pub const TWAP_INFO: u64 = 3;
 
 pub struct MarketEvent {
     // LMSR state = outstanding shares
@@ -100,6 +113,38 @@ impl IndexedObject<LiquidityHistoryEntry> for LiquidityHistoryEntry {
     const EVENT_NAME: u64 = 0x03;
 }
 
+// TWAP checkpoint of an LMSR market, published every TWAP_OBSERVATION_INTERVAL ticks
+#[derive(Debug, Clone)]
+pub struct TwapEntry {
+    pub market_id: u64,
+    pub counter: u64,
+    pub price_cumulative: u64, // accumulator at `counter`, TWAPs over any two entries follow from it
+    pub twap: u64,             // YES TWAP over TWAP_QUOTE_WINDOW, as used to price parlay legs
+}
+
+impl StorageData for TwapEntry {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        TwapEntry {
+            market_id: *u64data.next().unwrap(),
+            counter: *u64data.next().unwrap(),
+            price_cumulative: *u64data.next().unwrap(),
+            twap: *u64data.next().unwrap(),
+        }
+    }
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.market_id);
+        data.push(self.counter);
+        data.push(self.price_cumulative);
+        data.push(self.twap);
+    }
+}
+
+impl IndexedObject<TwapEntry> for TwapEntry {
+    const PREFIX: u64 = 0x3dd5;
+    const POSTFIX: u64 = 0xfee5;
+    const EVENT_NAME: u64 = 0x04;
+}
+
 // Helper function to emit IndexedObject events for market data
 pub fn emit_market_indexed_object(market: &MarketData, market_id: u64) {
     let mut data = Vec::new();
@@ -127,3 +172,18 @@ pub fn emit_liquidity_history(market_id: u64, counter: u64, yes_liquidity: u64,
     
     insert_event(EVENT_INDEXED_OBJECT, &mut data);
 }
+
+pub fn emit_twap_checkpoint(market_id: u64, counter: u64, price_cumulative: u64, twap: u64) {
+    let entry = TwapEntry {
+        market_id,
+        counter,
+        price_cumulative,
+        twap,
+    };
+
+    let mut data = Vec::new();
+    data.push(TWAP_INFO); // object index
+    entry.to_data(&mut data);
+
+    insert_event(EVENT_INDEXED_OBJECT, &mut data);
+}
@@ -0,0 +1,90 @@
+use crate::error::{ERROR_FAUCET_DISABLED, ERROR_FAUCET_COOLDOWN, ERROR_FAUCET_EXHAUSTED};
+use crate::event::{insert_event, EVENT_FAUCET_GRANT};
+use crate::params::ProtocolParams;
+use crate::player::{Player, PlayerData};
+use crate::state::GLOBAL_STATE;
+
+// Faucet that funds starting balances out of an admin-managed budget
+pub struct FaucetManager;
+
+impl FaucetManager {
+    // Amount that can be granted given the remaining budget
+    pub fn grant_amount(budget: u64, requested: u64) -> u64 {
+        requested.min(budget)
+    }
+
+    // Whether a player may claim again; a zero cooldown only allows the grant at install
+    pub fn check_cooldown(data: &PlayerData, counter: u64, cooldown: u64) -> Result<(), u32> {
+        if cooldown == 0 {
+            return Err(ERROR_FAUCET_DISABLED);
+        }
+        if counter < data.last_faucet_claim.saturating_add(cooldown) {
+            return Err(ERROR_FAUCET_COOLDOWN);
+        }
+        Ok(())
+    }
+
+    // Credits the configured grant from the budget and returns the amount granted
+    pub fn grant(player: &mut Player, counter: u64) -> u64 {
+        let params = ProtocolParams::get();
+        let (amount, budget_left) = {
+            let mut global_state = GLOBAL_STATE.0.borrow_mut();
+            let amount = Self::grant_amount(global_state.faucet_budget, params.new_player_initial_balance);
+            global_state.faucet_budget -= amount;
+            (amount, global_state.faucet_budget)
+        };
+        let play_credit = params.faucet_play_credit != 0;
+        player.data.add_faucet_grant(amount, play_credit);
+        player.data.last_faucet_claim = counter;
+        if amount > 0 {
+            Self::emit_grant_event(&player.player_id, amount, play_credit, budget_left, counter);
+        }
+        amount
+    }
+
+    // Repeat claim by an existing player once the cooldown has passed
+    pub fn claim(player: &mut Player, counter: u64) -> Result<u64, u32> {
+        Self::check_cooldown(&player.data, counter, ProtocolParams::get().faucet_cooldown_ticks)?;
+        if GLOBAL_STATE.0.borrow().faucet_budget == 0 {
+            return Err(ERROR_FAUCET_EXHAUSTED);
+        }
+        Ok(Self::grant(player, counter))
+    }
+
+    pub fn fund(amount: u64) {
+        let mut global_state = GLOBAL_STATE.0.borrow_mut();
+        global_state.faucet_budget = global_state.faucet_budget.saturating_add(amount);
+    }
+
+    fn emit_grant_event(player_id: &[u64; 2], amount: u64, play_credit: bool, budget_left: u64, counter: u64) {
+        let mut data = vec![
+            player_id[0],
+            player_id[1],
+            amount,
+            play_credit as u64,
+            budget_left,
+            counter,
+        ];
+        insert_event(EVENT_FAUCET_GRANT, &mut data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grant_is_capped_by_budget() {
+        assert_eq!(FaucetManager::grant_amount(1_000, 300), 300);
+        assert_eq!(FaucetManager::grant_amount(200, 300), 200);
+        assert_eq!(FaucetManager::grant_amount(0, 300), 0);
+    }
+
+    #[test]
+    fn test_claim_cooldown() {
+        let data = PlayerData { last_faucet_claim: 100, ..Default::default() };
+        assert_eq!(FaucetManager::check_cooldown(&data, 500, 0), Err(ERROR_FAUCET_DISABLED));
+        assert_eq!(FaucetManager::check_cooldown(&data, 150, 100), Err(ERROR_FAUCET_COOLDOWN));
+        assert_eq!(FaucetManager::check_cooldown(&data, 200, 100), Ok(()));
+    }
+}
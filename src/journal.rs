@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+use zkwasm_rest_abi::MERKLE_MAP;
+use crate::event::EVENTS;
+use crate::params::{ProtocolParams, PROTOCOL_PARAMS};
+use crate::player::Player;
+use crate::settlement::SettlementInfo;
+use crate::state::{GlobalState, GLOBAL_STATE};
+
+// Undo log for one unit of work. While a journal is open every write made through
+// `set` and every player passed to `track_player` remembers its value from before
+// the first change, so `rollback` can put the state back exactly as it was.
+//
+// Undo entries are keyed so recording a write costs one lookup however many the
+// unit of work has made; only the first value seen for a key is kept.
+struct Journal {
+    keys: BTreeMap<[u64; 4], Vec<u64>>,
+    players: BTreeMap<[u64; 2], Option<Player>>,
+    global_state: GlobalState,
+    params: ProtocolParams,
+    events_len: usize,
+    settlement_len: usize,
+}
+
+// Open journals, innermost last
+static mut JOURNALS: Vec<Journal> = vec![];
+
+fn current() -> Option<&'static mut Journal> {
+    unsafe { JOURNALS.last_mut() }
+}
+
+pub fn begin() {
+    let journal = Journal {
+        keys: BTreeMap::new(),
+        players: BTreeMap::new(),
+        global_state: GLOBAL_STATE.0.borrow().clone(),
+        params: ProtocolParams::get(),
+        events_len: unsafe { EVENTS.len() },
+        settlement_len: SettlementInfo::settlement_size(),
+    };
+    unsafe { JOURNALS.push(journal) };
+}
+
+// Keeps the changes; an enclosing journal inherits the undo entries it has not seen yet
+pub fn commit() {
+    let journal = unsafe { JOURNALS.pop() }.expect("commit without open journal");
+    if let Some(outer) = current() {
+        for (key, data) in journal.keys {
+            outer.keys.entry(key).or_insert(data);
+        }
+        for (pid, player) in journal.players {
+            outer.players.entry(pid).or_insert(player);
+        }
+    }
+}
+
+pub fn rollback() {
+    let journal = unsafe { JOURNALS.pop() }.expect("rollback without open journal");
+    let kvpair = unsafe { &mut MERKLE_MAP };
+    for (key, data) in journal.keys.iter() {
+        kvpair.set(key, data);
+    }
+    // Players that did not exist before cannot be removed; only install creates players
+    for (_, player) in journal.players {
+        if let Some(player) = player {
+            player.store();
+        }
+    }
+    *GLOBAL_STATE.0.borrow_mut() = journal.global_state;
+    *PROTOCOL_PARAMS.0.borrow_mut() = journal.params;
+    unsafe { EVENTS.truncate(journal.events_len) };
+    SettlementInfo::truncate(journal.settlement_len);
+}
+
+// Runs `f` inside a journal and discards all of its writes and events if it fails
+pub fn atomic<T>(f: impl FnOnce() -> Result<T, u32>) -> Result<T, u32> {
+    begin();
+    let result = f();
+    if result.is_ok() {
+        commit();
+    } else {
+        rollback();
+    }
+    result
+}
+
+// Journaled replacement for `MERKLE_MAP.set`
+pub fn set(key: &[u64; 4], data: &[u64]) {
+    let kvpair = unsafe { &mut MERKLE_MAP };
+    if let Some(journal) = current() {
+        journal.keys.entry(*key).or_insert_with(|| kvpair.get(key));
+    }
+    kvpair.set(key, data);
+}
+
+// Must be called before a player record is stored for the first time in a unit of work
+pub fn track_player(player_id: &[u64; 2]) {
+    if let Some(journal) = current() {
+        journal.players.entry(*player_id).or_insert_with(|| Player::get_from_pid(player_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::*;
+    use crate::player::PlayerMarketManager;
+    use crate::roles::{RoleManager, GRANTABLE_ROLES};
+    use crate::spec::*;
+    use crate::state::{MarketManager, Transaction};
+    use zkwasm_rest_abi::StorageData;
+
+    const TRADER: [u64; 4] = [0, 961, 962, 0];
+    const OTHER: [u64; 4] = [0, 963, 964, 0];
+    const OPERATOR: [u64; 4] = [0, 965, 966, 0]; // holds every grantable role
+
+    fn pid(pkey: &[u64; 4]) -> [u64; 2] {
+        Player::pkey_to_pid(pkey)
+    }
+
+    fn run(pkey: &[u64; 4], command: u64, args: &[u64]) -> Vec<u64> {
+        let nonce = Player::get_from_pid(&pid(pkey)).map_or(0, |player| player.nonce);
+        let mut params = vec![command | (nonce << 16)];
+        params.extend_from_slice(args);
+        Transaction::decode(&params).process(pkey, &[0; 4])
+    }
+
+    // Everything the commands below can touch
+    fn fingerprint(markets: &[u64]) -> String {
+        let mut words = vec![];
+        for pkey in [TRADER, OTHER, OPERATOR] {
+            let pid = pid(&pkey);
+            if let Some(player) = Player::get_from_pid(&pid) {
+                words.push(player.nonce);
+                player.data.to_data(&mut words);
+            }
+            words.push(RoleManager::get_roles(&pid));
+            words.extend(PlayerMarketManager::get_market_ids(&pid));
+            for market_id in markets {
+                PlayerMarketManager::get_position(&pid, *market_id).to_data(&mut words);
+            }
+        }
+        for market_id in markets {
+            MarketManager::get_market(*market_id).unwrap().to_data(&mut words);
+        }
+        format!(
+            "{:?} {} {:?} {}",
+            words,
+            GlobalState::snapshot(),
+            ProtocolParams::get(),
+            SettlementInfo::settlement_size()
+        )
+    }
+
+    #[test]
+    fn test_failed_commands_leave_no_trace() {
+        let _guard = crate::test_support::lock();
+        for pkey in [TRADER, OTHER, OPERATOR] {
+            run(&pkey, INSTALL_PLAYER, &[]);
+        }
+        RoleManager::grant(&pid(&OPERATOR), GRANTABLE_ROLES, 0).unwrap();
+        let (t0, t1) = (TRADER[1], TRADER[2]);
+        assert_eq!(run(&OPERATOR, DEPOSIT, &[t0, t1, 0, 50_000, 96, 1, 2, 3])[0], 0);
+        let market_id = GLOBAL_STATE.0.borrow().next_market_id;
+        assert_eq!(run(&OPERATOR, CREATE_MARKET, &[0, 1_000, 1_000, 1_000, 1_000, 10_000])[0], 0);
+        assert_eq!(run(&TRADER, BET, &[market_id, 1, 1_000])[0], 0);
+        let yes_shares = PlayerMarketManager::get_position(&pid(&TRADER), market_id).yes_shares;
+
+        let failures: Vec<(&[u64; 4], u64, Vec<u64>, u32)> = vec![
+            (&TRADER, BET, vec![market_id, 1, 0], ERROR_INVALID_BET_AMOUNT),
+            (&TRADER, BET, vec![9_999, 1, 100], ERROR_MARKET_NOT_ACTIVE),
+            (&TRADER, BET, vec![market_id, 0, 10_000_000], ERROR_INSUFFICIENT_BALANCE),
+            (&TRADER, SELL, vec![market_id, 1, yes_shares + 1], ERROR_INSUFFICIENT_BALANCE),
+            (&TRADER, CLAIM, vec![market_id], ERROR_MARKET_NOT_RESOLVED),
+            (&TRADER, CLAIM_ALL, vec![], ERROR_NO_WINNING_POSITION),
+            (&TRADER, TRANSFER_SHARES, vec![market_id, 1, 1, 1, 2], ERROR_PLAYER_NOT_EXIST),
+            (&TRADER, TRANSFER, vec![OTHER[1], OTHER[2], 10_000_000], ERROR_INSUFFICIENT_BALANCE),
+            (&TRADER, TRANSFER, vec![t0, t1, 1], ERROR_INVALID_TRANSFER),
+            (&TRADER, WITHDRAW, vec![0, 10_000_000, 0, 1, 2], ERROR_INSUFFICIENT_BALANCE),
+            (&TRADER, CLAIM_FAUCET, vec![], ERROR_FAUCET_DISABLED),
+            (&TRADER, RESOLVE, vec![market_id, 1], ERROR_UNAUTHORIZED),
+            (&OPERATOR, DEPOSIT, vec![t0, t1, 0, 50_000, 96, 1, 2, 3], ERROR_DEPOSIT_ALREADY_PROCESSED),
+            (&OPERATOR, DEPOSIT, vec![1, 2, 0, 50_000, 97, 1, 2, 3], ERROR_PLAYER_NOT_EXIST),
+            (&OPERATOR, RESOLVE, vec![9_999, 1], ERROR_MARKET_NOT_ACTIVE),
+            (&OPERATOR, WITHDRAW_FEES, vec![9_999], ERROR_MARKET_NOT_ACTIVE),
+            (&OPERATOR, CANCEL_WITHDRAWAL, vec![9_999], ERROR_WITHDRAWAL_NOT_FOUND),
+            (&OPERATOR, SET_PARAM, vec![0, 1], ERROR_UNAUTHORIZED),
+            // The first bet succeeds and writes the market, position and balance before the sell fails
+            (&TRADER, BATCH, vec![2, BET | (3 << 8), market_id, 1, 500, SELL | (3 << 8), market_id, 0, 1], ERROR_INSUFFICIENT_BALANCE),
+        ];
+
+        for (pkey, command, args, expected) in failures {
+            let before = fingerprint(&[market_id]);
+            let result = run(pkey, command, &args);
+            assert_eq!(result[0], expected as u64, "command {} {:?}", command, args);
+            assert_eq!(result.len(), 2, "command {} emitted events", command);
+            assert_eq!(fingerprint(&[market_id]), before, "command {} {:?}", command, args);
+        }
+    }
+
+    #[test]
+    fn test_nested_journal_rolls_back_into_outer() {
+        let _guard = crate::test_support::lock();
+        let key = [99, 0, 1, 0];
+        begin();
+        set(&key, &[1]);
+        begin();
+        set(&key, &[2]);
+        set(&[99, 0, 2, 0], &[3]);
+        commit();
+        rollback();
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        assert!(kvpair.get(&key).is_empty());
+        assert!(kvpair.get(&[99, 0, 2, 0]).is_empty());
+    }
+}
@@ -5,13 +5,28 @@ use zkwasm_rest_abi::*;
 pub mod config;
 pub mod error;
 pub mod event;
+pub mod clob;
 pub mod command;
+pub mod deposit;
+pub mod faucet;
+pub mod journal;
 pub mod player;
+pub mod series;
 pub mod settlement;
+pub mod spec;
+pub mod trigger;
+pub mod twap;
+pub mod withdrawal;
 pub mod state;
 pub mod market;
+pub mod orderbook;
+pub mod parlay;
+pub mod params;
+pub mod roles;
 pub mod math_safe;
 pub mod security_tests;
+#[cfg(test)]
+mod test_support;
 
 use crate::config::Config;
 use crate::state::{GlobalState, Transaction};
@@ -3,7 +3,13 @@ use zkwasm_rest_abi::StorageData;
 use zkwasm_rest_convention::IndexedObject;
 use crate::error::*;
 use crate::math_safe::*;
+use crate::config::{PRICE_PRECISION, MARKET_FEE_PROTOCOL};
+use crate::player::PlayerMarketPosition;
 
+// Market kinds chosen at creation
+pub const MARKET_KIND_LMSR: u64 = 0; // trades against the LMSR market maker
+pub const MARKET_KIND_CLOB: u64 = 1; // YES bids are matched against NO bids in an order book
+pub const MARKET_KIND_CONDITIONAL: u64 = 2; // LMSR market that only settles if its parent resolves a given way
 
 #[derive(Serialize, Clone, Debug)]
 pub struct MarketData {
@@ -29,6 +35,37 @@ pub struct MarketData {
     pub resolved: bool,
     pub outcome: Option<bool>, // None = unresolved, Some(true) = Yes wins, Some(false) = No wins
     pub total_fees_collected: u64,
+
+    // Collateral token index used for bets, payouts and fees
+    pub token: u64,
+
+    // MARKET_KIND_LMSR, MARKET_KIND_CLOB or MARKET_KIND_CONDITIONAL
+    pub kind: u64,
+
+    // Conditional markets only: the parent market and the outcome it must
+    // resolve to (0 = NO, 1 = YES) for this market to settle normally
+    pub parent_market_id: u64,
+    pub condition: u64,
+
+    // Recurring series this market was instantiated from, 0 = none
+    pub series_id: u64,
+    // Trading fee in basis points, MARKET_FEE_PROTOCOL follows the platform fee param
+    pub fee_rate: u64,
+
+    // TWAP accumulator: sum of the YES price (PRICE_PRECISION units) times the ticks
+    // it was in effect, counted from `price_tracked_since`. LMSR markets only.
+    pub last_yes_price: u64,
+    pub price_cumulative: u64,
+    pub price_updated_at: u64,
+    pub price_tracked_since: u64,
+
+    // Cost basis of all open positions. Voiding freezes it together with the pool
+    // and fees on hand, and every position is refunded the same share of its basis.
+    pub open_cost_basis: u64,
+    pub refund_funds: u64, // pool and fees at void time
+
+    // False until the first recorded price, the accumulator fields are meaningless before
+    pub price_tracked: bool,
 }
 
 impl MarketData {
@@ -41,12 +78,7 @@ impl MarketData {
         b: u64
     ) -> Result<Self, u32> {
         // 验证时间参数
-        if start_time >= end_time {
-            return Err(crate::error::ERROR_INVALID_MARKET_TIME);
-        }
-        if end_time > resolution_time {
-            return Err(crate::error::ERROR_INVALID_MARKET_TIME);
-        }
+        Self::validate_times(start_time, end_time, resolution_time)?;
 
         // 验证初始流动性
         validate_liquidity(initial_yes_liquidity)?;
@@ -55,7 +87,7 @@ impl MarketData {
         // 验证LMSR参数b
         validate_b(b)?;
 
-        Ok(MarketData {
+        let mut market = MarketData {
             start_time,
             end_time,
             resolution_time,
@@ -68,9 +100,128 @@ impl MarketData {
             resolved: false,
             outcome: None,
             total_fees_collected: 0,
+            token: 0,
+            kind: MARKET_KIND_LMSR,
+            parent_market_id: 0,
+            condition: 0,
+            series_id: 0,
+            fee_rate: MARKET_FEE_PROTOCOL,
+            last_yes_price: 0,
+            price_cumulative: 0,
+            price_updated_at: 0,
+            price_tracked_since: 0,
+            open_cost_basis: 0,
+            refund_funds: 0,
+            price_tracked: false,
+        };
+        market.record_price(start_time)?;
+        Ok(market)
+    }
+
+    // Order book market: no virtual liquidity, every share is minted as half of a
+    // fully collateralised YES/NO pair, so a winning share pays exactly one token
+    pub fn new_clob(start_time: u64, end_time: u64, resolution_time: u64) -> Result<Self, u32> {
+        Self::validate_times(start_time, end_time, resolution_time)?;
+        Ok(MarketData {
+            start_time,
+            end_time,
+            resolution_time,
+            total_yes_shares: 0,
+            total_no_shares: 0,
+            b: 0,
+            pool_balance: 0,
+            total_volume: 0,
+            resolved: false,
+            outcome: None,
+            total_fees_collected: 0,
+            token: 0,
+            kind: MARKET_KIND_CLOB,
+            parent_market_id: 0,
+            condition: 0,
+            series_id: 0,
+            fee_rate: MARKET_FEE_PROTOCOL,
+            last_yes_price: 0,
+            price_cumulative: 0,
+            price_updated_at: 0,
+            price_tracked_since: 0,
+            open_cost_basis: 0,
+            refund_funds: 0,
+            price_tracked: false,
         })
     }
 
+    fn validate_times(start_time: u64, end_time: u64, resolution_time: u64) -> Result<(), u32> {
+        if start_time >= end_time {
+            return Err(crate::error::ERROR_INVALID_MARKET_TIME);
+        }
+        if end_time > resolution_time {
+            return Err(crate::error::ERROR_INVALID_MARKET_TIME);
+        }
+        Ok(())
+    }
+
+    pub fn is_clob(&self) -> bool {
+        self.kind == MARKET_KIND_CLOB
+    }
+
+    // Trading fee on `amount`, at the market's own rate if it has one
+    pub fn fee(&self, amount: u64) -> Result<u64, u32> {
+        if self.fee_rate == MARKET_FEE_PROTOCOL {
+            calculate_fee_safe(amount)
+        } else {
+            calculate_fee_at_rate(amount, self.fee_rate)
+        }
+    }
+
+    // Accumulator value at `counter`: the last recorded price is assumed to hold
+    // since the last update. Trading time ends at end_time, so does accumulation.
+    pub fn price_cumulative_at(&self, counter: u64) -> Result<u64, u32> {
+        let until = counter.min(self.end_time);
+        if until <= self.price_updated_at {
+            return Ok(self.price_cumulative);
+        }
+        let elapsed = until - self.price_updated_at;
+        safe_add(self.price_cumulative, safe_mul(self.last_yes_price, elapsed)?)
+    }
+
+    // Brings the accumulator up to `counter` and records the current price; call
+    // after every trade so the previous price is weighted by how long it held
+    pub fn record_price(&mut self, counter: u64) -> Result<(), u32> {
+        if self.is_clob() {
+            return Ok(());
+        }
+        if !self.price_tracked {
+            // Markets stored before TWAP tracking start accumulating now
+            self.price_tracked = true;
+            self.price_tracked_since = counter;
+            self.price_updated_at = counter;
+        } else {
+            self.price_cumulative = self.price_cumulative_at(counter)?;
+            self.price_updated_at = self.price_updated_at.max(counter.min(self.end_time));
+        }
+        self.last_yes_price = self.get_yes_price()?;
+        Ok(())
+    }
+
+    pub fn is_conditional(&self) -> bool {
+        self.kind == MARKET_KIND_CONDITIONAL
+    }
+
+    // A voided market is resolved without an outcome, positions are refunded at cost
+    pub fn is_void(&self) -> bool {
+        self.resolved && self.outcome.is_none()
+    }
+
+    // Records `shares` complete YES/NO sets minted by matching two bids; the
+    // collateral for both halves goes into the pool
+    pub fn mint_complete_sets(&mut self, shares: u64) -> Result<(), u32> {
+        self.total_yes_shares = safe_add(self.total_yes_shares, shares)?;
+        self.total_no_shares = safe_add(self.total_no_shares, shares)?;
+        self.pool_balance = safe_add(self.pool_balance, shares)?;
+        self.total_volume = safe_add(self.total_volume, shares)?;
+        Ok(())
+    }
+
 
     pub fn is_active(&self, current_time: u64) -> bool {
         current_time >= self.start_time && current_time < self.end_time && !self.resolved
@@ -119,14 +270,14 @@ impl MarketData {
     pub fn calculate_shares(&self, bet_type: u64, bet_amount: u64) -> Result<u64, u32> {
         validate_bet_amount(bet_amount)?;
 
-        let fee = calculate_fee_safe(bet_amount)?;
+        let fee = self.fee(bet_amount)?;
         let net_amount = safe_sub(bet_amount, fee)?;
 
         let is_yes_bet = Self::validate_bet_type(bet_type)?;
 
-        // binary search Δ in [0, MAX_SHARES] for monotonic cost
+        // binary search Δ in [0, max_shares] for monotonic cost
         let mut lo: u64 = 0;
-        let mut hi: u64 = MAX_SHARES; // cap
+        let mut hi: u64 = crate::params::ProtocolParams::get().max_shares; // cap
 
         while lo < hi {
             let mid = lo + (hi - lo + 1) / 2;
@@ -199,7 +350,7 @@ impl MarketData {
             return Ok((0, 0));
         }
 
-        let fee = calculate_fee_safe(gross_tokens)?;
+        let fee = self.fee(gross_tokens)?;
         let net_payout = safe_sub(gross_tokens, fee)?;
         Ok((net_payout, fee))
     }
@@ -270,7 +421,7 @@ impl MarketData {
         }
 
         // recompute fee / net (tokens)
-        let fee_tokens = calculate_fee_safe(bet_amount)?;
+        let fee_tokens = self.fee(bet_amount)?;
         let net_tokens = safe_sub(bet_amount, fee_tokens)?;
 
         let is_yes_bet = bet_type == 1;
@@ -351,6 +502,51 @@ impl MarketData {
         Ok(())
     }
 
+    // Settles the market without a winner, used when a condition is not met
+    pub fn void(&mut self) -> Result<(), u32> {
+        if self.resolved {
+            return Err(ERROR_MARKET_ALREADY_RESOLVED);
+        }
+        self.resolved = true;
+        self.outcome = None;
+        self.refund_funds = safe_add(self.pool_balance, self.total_fees_collected)?;
+        Ok(())
+    }
+
+    // Trades moving cost basis into or out of positions
+    pub fn add_cost_basis(&mut self, cost: u64) -> Result<(), u32> {
+        self.open_cost_basis = safe_add(self.open_cost_basis, cost)?;
+        Ok(())
+    }
+
+    pub fn release_cost_basis(&mut self, cost: u64) {
+        // Positions opened before cost tracking are not counted
+        self.open_cost_basis = self.open_cost_basis.saturating_sub(cost);
+    }
+
+    // Refund owed on a voided market for a position with the given cost basis: its
+    // pro rata share of the funds on hand at void time, never more than the basis
+    pub fn refund_quote(&self, cost_basis: u64) -> Result<u64, u32> {
+        let available = safe_add(self.pool_balance, self.total_fees_collected)?;
+        if self.open_cost_basis == 0 {
+            return Ok(cost_basis.min(available));
+        }
+        if self.refund_funds == 0 {
+            return Ok(0);
+        }
+        let share = safe_div_high_precision(cost_basis, self.refund_funds, self.open_cost_basis)?;
+        Ok(share.min(cost_basis).min(available))
+    }
+
+    // Pays a refund out of the pool first and the collected fees second
+    pub fn take_refund(&mut self, cost_basis: u64) -> Result<u64, u32> {
+        let refund = self.refund_quote(cost_basis)?;
+        let from_pool = refund.min(self.pool_balance);
+        self.pool_balance = safe_sub(self.pool_balance, from_pool)?;
+        self.total_fees_collected = safe_sub(self.total_fees_collected, refund - from_pool)?;
+        Ok(refund)
+    }
+
     // 安全计算奖金
     pub fn calculate_payout(&self, yes_shares: u64, no_shares: u64) -> Result<u64, u32> {
         if !self.resolved || self.pool_balance == 0 {
@@ -376,6 +572,28 @@ impl MarketData {
         }
     }
 
+    // Current value of a position in tokens: claimable payout once resolved,
+    // otherwise shares valued at the LMSR marginal prices
+    pub fn mark_to_market(&self, position: &PlayerMarketPosition) -> Result<u64, u32> {
+        if self.resolved {
+            if position.claimed {
+                return Ok(0);
+            }
+            if self.is_void() {
+                return self.refund_quote(safe_add(position.yes_cost_basis, position.no_cost_basis)?);
+            }
+            return self.calculate_payout(position.yes_shares, position.no_shares);
+        }
+        // Order book markets have no reference price, open positions are held at cost
+        if self.is_clob() {
+            return safe_add(position.yes_cost_basis, position.no_cost_basis);
+        }
+
+        let yes_value = safe_div_high_precision(position.yes_shares, self.get_yes_price()?, PRICE_PRECISION)?;
+        let no_value = safe_div_high_precision(position.no_shares, self.get_no_price()?, PRICE_PRECISION)?;
+        safe_add(yes_value, no_value)
+    }
+
     pub fn withdraw_fees(&mut self, amount: u64) -> Result<u64, u32> {
         if amount == 0 || amount > self.total_fees_collected {
             return Err(ERROR_INVALID_BET_AMOUNT);
@@ -388,7 +606,7 @@ impl MarketData {
 
 impl StorageData for MarketData {
     fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
-        MarketData {
+        let mut market = MarketData {
             start_time: *u64data.next().unwrap(),
             end_time: *u64data.next().unwrap(),
             resolution_time: *u64data.next().unwrap(),
@@ -405,7 +623,25 @@ impl StorageData for MarketData {
                 else { Some(true) }
             },
             total_fees_collected: *u64data.next().unwrap(),
-        }
+            // Markets stored before multi-token support are denominated in token 0
+            token: u64data.next().map_or(0, |v| *v),
+            kind: u64data.next().map_or(MARKET_KIND_LMSR, |v| *v),
+            parent_market_id: u64data.next().map_or(0, |v| *v),
+            condition: u64data.next().map_or(0, |v| *v),
+            series_id: u64data.next().map_or(0, |v| *v),
+            fee_rate: u64data.next().map_or(MARKET_FEE_PROTOCOL, |v| *v),
+            last_yes_price: u64data.next().map_or(0, |v| *v),
+            price_cumulative: u64data.next().map_or(0, |v| *v),
+            price_updated_at: u64data.next().map_or(0, |v| *v),
+            price_tracked_since: u64data.next().map_or(0, |v| *v),
+            // Markets stored before cost tracking refund at cost while funds last
+            open_cost_basis: u64data.next().map_or(0, |v| *v),
+            refund_funds: u64data.next().map_or(0, |v| *v),
+            price_tracked: false,
+        };
+        // Markets stored before the flag are tracked once they have a recorded price
+        market.price_tracked = u64data.next().map_or(market.last_yes_price != 0, |v| *v != 0);
+        market
     }
 
     fn to_data(&self, data: &mut Vec<u64>) {
@@ -424,6 +660,19 @@ impl StorageData for MarketData {
             Some(true) => 2,
         });
         data.push(self.total_fees_collected);
+        data.push(self.token);
+        data.push(self.kind);
+        data.push(self.parent_market_id);
+        data.push(self.condition);
+        data.push(self.series_id);
+        data.push(self.fee_rate);
+        data.push(self.last_yes_price);
+        data.push(self.price_cumulative);
+        data.push(self.price_updated_at);
+        data.push(self.price_tracked_since);
+        data.push(self.open_cost_basis);
+        data.push(self.refund_funds);
+        data.push(if self.price_tracked { 1 } else { 0 });
     }
 }
 
@@ -436,7 +685,6 @@ impl IndexedObject<MarketData> for MarketData {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::PRICE_PRECISION;
 
     #[test]
     fn test_calculate_shares_lmsr_reasonable_for_large_liquidity() {
@@ -467,4 +715,22 @@ mod tests {
         let p_yes = market.get_yes_price().unwrap();
         assert!(p_yes > PRICE_PRECISION / 2);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_mark_to_market_values_open_and_resolved_positions() {
+        let mut market = MarketData::new_with_liquidity(0, 1_000, 1_000, 100_000, 100_000, 100_000).unwrap();
+        let shares = market.place_bet(1, 5_000).unwrap();
+        let position = PlayerMarketPosition { yes_shares: shares, ..Default::default() };
+
+        // Open market: shares valued at the marginal YES price
+        let value = market.mark_to_market(&position).unwrap();
+        let p_yes = market.get_yes_price().unwrap();
+        assert_eq!(value, (shares as u128 * p_yes as u128 / PRICE_PRECISION as u128) as u64);
+
+        // Resolved market: value is the claimable payout, zero once claimed
+        market.resolve(true).unwrap();
+        assert_eq!(market.mark_to_market(&position).unwrap(), market.calculate_payout(shares, 0).unwrap());
+        let claimed = PlayerMarketPosition { claimed: true, ..position };
+        assert_eq!(market.mark_to_market(&claimed).unwrap(), 0);
+    }
+}
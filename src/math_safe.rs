@@ -1,7 +1,8 @@
 use crate::error::*;
-use crate::config::{PRICE_PRECISION, FEE_BASIS_POINTS, PLATFORM_FEE_RATE};
+use crate::config::{PRICE_PRECISION, FEE_BASIS_POINTS, MAX_COLLATERAL_TOKENS};
+use crate::params::ProtocolParams;
 
-// 安全限制常量
+// 安全限制常量 (MAX_BET_AMOUNT, MIN_LIQUIDITY and MAX_SHARES are defaults, see ProtocolParams)
 pub const MAX_LIQUIDITY: u64 = 1_000_000_000_000;  // 1万亿 - 防止溢出
 pub const MAX_BET_AMOUNT: u64 = 100_000_000;       // 1亿 - 合理的最大投注
 pub const MIN_LIQUIDITY: u64 = 1000;               // 最小流动性 - 防止除零
@@ -290,14 +291,20 @@ pub fn calculate_no_price_lmsr(q_yes: u64, q_no: u64, b: u64) -> Result<u64, u32
 
 /// 安全计算平台费用（向上取整确保不丢失费用）
 pub fn calculate_fee_safe(amount: u64) -> Result<u64, u32> {
-    if amount > MAX_BET_AMOUNT {
+    calculate_fee_at_rate(amount, ProtocolParams::get().platform_fee_rate)
+}
+
+/// Fee at an explicit rate in basis points, rounded up like the platform fee
+pub fn calculate_fee_at_rate(amount: u64, fee_rate: u64) -> Result<u64, u32> {
+    let params = ProtocolParams::get();
+    if amount > params.max_bet_amount {
         return Err(ERROR_BET_TOO_LARGE);
     }
     
-    // 计算 (amount * PLATFORM_FEE_RATE + FEE_BASIS_POINTS - 1) / FEE_BASIS_POINTS
+    // 计算 (amount * fee_rate + FEE_BASIS_POINTS - 1) / FEE_BASIS_POINTS
     // 这样可以实现向上取整
     let numerator = (amount as u128)
-        .checked_mul(PLATFORM_FEE_RATE as u128)
+        .checked_mul(fee_rate as u128)
         .ok_or(ERROR_OVERFLOW)?;
     
     let rounded_numerator = numerator
@@ -343,7 +350,7 @@ pub fn validate_bet_amount(bet_amount: u64) -> Result<(), u32> {
         return Err(ERROR_INVALID_BET_AMOUNT);
     }
     
-    if bet_amount > MAX_BET_AMOUNT {
+    if bet_amount > ProtocolParams::get().max_bet_amount {
         return Err(ERROR_BET_TOO_LARGE);
     }
     
@@ -356,7 +363,7 @@ pub fn validate_shares(shares: u64) -> Result<(), u32> {
         return Err(ERROR_INVALID_BET_AMOUNT);
     }
     
-    if shares > MAX_SHARES {
+    if shares > ProtocolParams::get().max_shares {
         return Err(ERROR_BET_TOO_LARGE);
     }
     
@@ -365,7 +372,7 @@ pub fn validate_shares(shares: u64) -> Result<(), u32> {
 
 /// 验证流动性的安全性
 pub fn validate_liquidity(liquidity: u64) -> Result<(), u32> {
-    if liquidity < MIN_LIQUIDITY {
+    if liquidity < ProtocolParams::get().min_liquidity {
         return Err(ERROR_INVALID_CALCULATION);
     }
     
@@ -376,6 +383,15 @@ pub fn validate_liquidity(liquidity: u64) -> Result<(), u32> {
     Ok(())
 }
 
+/// Validate a collateral token index
+pub fn validate_token(token: u64) -> Result<(), u32> {
+    if token >= MAX_COLLATERAL_TOKENS {
+        return Err(ERROR_INVALID_TOKEN);
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
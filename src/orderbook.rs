@@ -0,0 +1,325 @@
+use serde::Serialize;
+use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
+use crate::config::{PRICE_PRECISION, MAX_ORDERS_PER_BOOK, MAX_ORDERS_PER_OWNER};
+use crate::error::*;
+use crate::event::{insert_event, emit_market_indexed_object, EVENT_ORDER_UPDATE};
+use crate::market::MarketData;
+use crate::math_safe::validate_bet_amount;
+use crate::player::{Player, PlayerMarketManager};
+use crate::state::{MarketManager, GLOBAL_STATE};
+
+// Order event status codes
+pub const ORDER_PLACED: u64 = 0;
+pub const ORDER_FILLED: u64 = 1;
+pub const ORDER_CANCELLED: u64 = 2;
+
+// Resting buy order: spend `amount` on `side` once the AMM fills it at an average
+// price at or below `price`. The amount is escrowed from the owner's balance.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct LimitOrder {
+    pub owner: [u64; 2],
+    pub market_id: u64,
+    pub side: u64,        // 0 = NO, 1 = YES
+    pub price: u64,       // limit price, scaled by PRICE_PRECISION
+    pub amount: u64,      // escrowed collateral
+    pub play_credit: u64, // part of the escrow taken from faucet play credit
+    pub counter: u64,     // counter at placement
+}
+
+impl StorageData for LimitOrder {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        LimitOrder {
+            owner: [*u64data.next().unwrap(), *u64data.next().unwrap()],
+            market_id: *u64data.next().unwrap(),
+            side: *u64data.next().unwrap(),
+            price: *u64data.next().unwrap(),
+            amount: *u64data.next().unwrap(),
+            play_credit: *u64data.next().unwrap(),
+            counter: *u64data.next().unwrap(),
+        }
+    }
+
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.owner[0]);
+        data.push(self.owner[1]);
+        data.push(self.market_id);
+        data.push(self.side);
+        data.push(self.price);
+        data.push(self.amount);
+        data.push(self.play_credit);
+        data.push(self.counter);
+    }
+}
+
+impl LimitOrder {
+    // Shares the AMM would give for this order now, if the average price respects the limit
+    pub fn fill_quote(&self, market: &MarketData) -> Option<(MarketData, u64)> {
+        let mut filled = market.clone();
+        let shares = filled.place_bet(self.side, self.amount).ok()?;
+        // amount / shares <= price / PRICE_PRECISION
+        let cost = self.amount as u128 * PRICE_PRECISION as u128;
+        let limit = self.price as u128 * shares as u128;
+        (cost <= limit).then_some((filled, shares))
+    }
+}
+
+pub struct OrderBook;
+
+impl OrderBook {
+    const ORDER_PREFIX: [u64; 2] = [10, 0]; // Prefix for order records
+    const BOOK_PREFIX: [u64; 2] = [11, 0];  // Prefix for per market and side order id lists
+
+    pub fn get_order(order_id: u64) -> Option<LimitOrder> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let mut data = kvpair.get(&[Self::ORDER_PREFIX[0], Self::ORDER_PREFIX[1], order_id, 0]);
+        if !data.is_empty() {
+            let mut u64data = data.iter_mut();
+            Some(LimitOrder::from_data(&mut u64data))
+        } else {
+            None
+        }
+    }
+
+    fn store_order(order_id: u64, order: &LimitOrder) {
+        let mut data = vec![];
+        order.to_data(&mut data);
+        crate::journal::set(&[Self::ORDER_PREFIX[0], Self::ORDER_PREFIX[1], order_id, 0], data.as_slice());
+    }
+
+    fn remove_order(order_id: u64) {
+        crate::journal::set(&[Self::ORDER_PREFIX[0], Self::ORDER_PREFIX[1], order_id, 0], &[]);
+    }
+
+    // Resting order ids of one side, best (highest) price first, oldest first within a price
+    pub fn get_book(market_id: u64, side: u64) -> Vec<u64> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        kvpair.get(&[Self::BOOK_PREFIX[0], Self::BOOK_PREFIX[1], market_id, side])
+    }
+
+    fn store_book(market_id: u64, side: u64, order_ids: &[u64]) {
+        crate::journal::set(&[Self::BOOK_PREFIX[0], Self::BOOK_PREFIX[1], market_id, side], order_ids);
+    }
+
+    // Position at which an order with `price` keeps the book sorted by price, then time
+    pub fn insert_position(prices: &[u64], price: u64) -> usize {
+        prices.iter().position(|p| *p < price).unwrap_or(prices.len())
+    }
+
+    pub fn place(player: &mut Player, market_id: u64, side: u64, price: u64, amount: u64, counter: u64) -> Result<u64, u32> {
+        if side > 1 {
+            return Err(ERROR_INVALID_BET_TYPE);
+        }
+        // An order too large for a single bet could never fill and would block its book
+        validate_bet_amount(amount)?;
+        if price == 0 || price >= PRICE_PRECISION {
+            return Err(ERROR_INVALID_PRICE);
+        }
+        GLOBAL_STATE.0.borrow().ensure_market_active(market_id)?;
+        let market = MarketManager::get_market(market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+        if market.is_clob() {
+            return Err(ERROR_INVALID_MARKET_KIND);
+        }
+
+        let mut book = Self::get_book(market_id, side);
+        if book.len() as u64 >= MAX_ORDERS_PER_BOOK {
+            return Err(ERROR_TOO_MANY_ORDERS);
+        }
+        let resting: Vec<LimitOrder> = book.iter().filter_map(|id| Self::get_order(*id)).collect();
+        // One player cannot take up the whole book
+        if resting.iter().filter(|o| o.owner == player.player_id).count() as u64 >= MAX_ORDERS_PER_OWNER {
+            return Err(ERROR_TOO_MANY_ORDERS);
+        }
+
+        // Escrow the amount, remembering how much play credit it consumed
+        let play_credit = player.data.spend_stake_of(market.token, amount)?;
+        let order = LimitOrder {
+            owner: player.player_id,
+            market_id,
+            side,
+            price,
+            amount,
+            play_credit,
+            counter,
+        };
+
+        let order_id = {
+            let mut global_state = GLOBAL_STATE.0.borrow_mut();
+            let order_id = global_state.next_order_id;
+            global_state.next_order_id += 1;
+            order_id
+        };
+        let prices: Vec<u64> = resting.iter().map(|o| o.price).collect();
+        book.insert(Self::insert_position(&prices, price), order_id);
+        Self::store_order(order_id, &order);
+        Self::store_book(market_id, side, &book);
+        player.store();
+        Self::emit_order_event(ORDER_PLACED, order_id, &order, 0, counter);
+        Ok(order_id)
+    }
+
+    // Refunds the escrow to the owner; the caller stores `player`
+    pub fn cancel(player: &mut Player, order_id: u64, counter: u64) -> Result<(), u32> {
+        let order = Self::get_order(order_id).ok_or(ERROR_ORDER_NOT_FOUND)?;
+        if order.owner != player.player_id {
+            return Err(ERROR_UNAUTHORIZED);
+        }
+        let market = MarketManager::get_market(order.market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+        player.data.add_balance_of(market.token, order.amount);
+        player.data.play_credit += order.play_credit;
+
+        let mut book = Self::get_book(order.market_id, order.side);
+        book.retain(|id| *id != order_id);
+        Self::store_book(order.market_id, order.side, &book);
+        Self::remove_order(order_id);
+        Self::emit_order_event(ORDER_CANCELLED, order_id, &order, 0, counter);
+        Ok(())
+    }
+
+    // Fills resting orders whose limit the current AMM price satisfies, best price first.
+    // An order too large for its limit is skipped, smaller ones behind it may still
+    // fill. Returns the number of fills, at most `max_fills`.
+    pub fn match_orders(market_id: u64, max_fills: u64, counter: u64) -> Result<u64, u32> {
+        let mut fills = 0;
+        let mut market = match MarketManager::get_market(market_id) {
+            Some(market) if MarketManager::is_tradable(&market, counter) => market,
+            _ => return Ok(0),
+        };
+        for side in [0, 1] {
+            let mut book = Self::get_book(market_id, side);
+            // A fill only raises the price of its side, so one pass over the book is enough
+            let mut index = 0;
+            while fills < max_fills && index < book.len() {
+                let order_id = book[index];
+                let order = Self::get_order(order_id).ok_or(ERROR_ORDER_NOT_FOUND)?;
+                let Some((filled, shares)) = order.fill_quote(&market) else {
+                    index += 1;
+                    continue;
+                };
+                market = filled;
+                market.add_cost_basis(order.amount)?;
+                let mut position = PlayerMarketManager::get_position(&order.owner, market_id);
+                position.record_buy(side == 1, shares, order.amount, order.play_credit)?;
+                PlayerMarketManager::store_position(&order.owner, market_id, &position);
+
+                book.remove(index);
+                Self::remove_order(order_id);
+                Self::emit_order_event(ORDER_FILLED, order_id, &order, shares, counter);
+                fills += 1;
+            }
+            Self::store_book(market_id, side, &book);
+        }
+        if fills > 0 {
+            market.record_price(counter)?;
+            MarketManager::update_market(market_id, &market);
+            emit_market_indexed_object(&market, market_id);
+        }
+        Ok(fills)
+    }
+
+    fn emit_order_event(status: u64, order_id: u64, order: &LimitOrder, shares: u64, counter: u64) {
+        let mut data = vec![
+            status,
+            order_id,
+            order.owner[0],
+            order.owner[1],
+            order.market_id,
+            order.side,
+            order.price,
+            order.amount,
+            shares,
+            counter,
+        ];
+        insert_event(EVENT_ORDER_UPDATE, &mut data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{Activity, CommandHandler};
+    use crate::test_support;
+
+    #[test]
+    fn test_book_keeps_price_time_priority() {
+        let prices = [600_000, 500_000, 500_000, 400_000];
+        assert_eq!(OrderBook::insert_position(&prices, 700_000), 0);
+        assert_eq!(OrderBook::insert_position(&prices, 500_000), 3); // behind equal prices
+        assert_eq!(OrderBook::insert_position(&prices, 100_000), 4);
+        assert_eq!(OrderBook::insert_position(&[], 100_000), 0);
+    }
+
+    #[test]
+    fn test_fill_quote_respects_limit() {
+        let market = MarketData::new_with_liquidity(0, 1_000, 1_000, 1_000, 1_000, 10_000).unwrap();
+        let price = market.get_yes_price().unwrap();
+        let order = LimitOrder { side: 1, price: price / 2, amount: 100, ..Default::default() };
+        assert!(order.fill_quote(&market).is_none());
+        let order = LimitOrder { side: 1, price: PRICE_PRECISION - 1, amount: 100, ..Default::default() };
+        let (filled, shares) = order.fill_quote(&market).unwrap();
+        assert!(shares > 0);
+        assert_eq!(filled.total_yes_shares, market.total_yes_shares + shares);
+    }
+
+    #[test]
+    fn test_order_fills_when_trade_crosses_limit() {
+        let _guard = test_support::lock();
+        let market_id = test_support::market(0);
+        let (maker, taker) = (test_support::player(10_000), test_support::player(10_000));
+
+        // 100 buys YES at an average of about 0.505, so a 0.48 bid rests with its amount escrowed
+        Activity::PlaceLimitOrder(market_id, 1, 480_000, 100).handle(&maker, 0, &[0; 4], 0).unwrap();
+        assert_eq!(Player::get_from_pid(&maker).unwrap().data.balance, 9_900);
+        assert_eq!(OrderBook::get_book(market_id, 1).len(), 1);
+        assert_eq!(PlayerMarketManager::get_position(&maker, market_id).yes_shares, 0);
+
+        // A large NO bet pushes YES below the limit and fills the order
+        Activity::Bet(market_id, 0, 5_000).handle(&taker, 0, &[0; 4], 0).unwrap();
+        assert!(OrderBook::get_book(market_id, 1).is_empty());
+        let position = PlayerMarketManager::get_position(&maker, market_id);
+        assert!(position.yes_shares > 0);
+        assert_eq!(position.yes_cost_basis, 100);
+        assert_eq!(Player::get_from_pid(&maker).unwrap().data.balance, 9_900);
+    }
+
+    #[test]
+    fn test_large_order_does_not_block_the_book() {
+        let _guard = test_support::lock();
+        let market_id = test_support::market(0);
+        let (whale, maker, taker) = (test_support::player(10_000), test_support::player(10_000), test_support::player(10_000));
+
+        // Too large to average below its limit after the trade, these sit at the top of the book
+        let place = |amount| Activity::PlaceLimitOrder(market_id, 1, 490_000, amount);
+        for nonce in 0..MAX_ORDERS_PER_OWNER {
+            place(1_000).handle(&whale, nonce, &[0; 4], 0).unwrap();
+        }
+        assert_eq!(place(1_000).handle(&whale, MAX_ORDERS_PER_OWNER, &[0; 4], 0), Err(ERROR_TOO_MANY_ORDERS));
+        Activity::PlaceLimitOrder(market_id, 1, 480_000, 100).handle(&maker, 0, &[0; 4], 0).unwrap();
+
+        Activity::Bet(market_id, 0, 5_000).handle(&taker, 0, &[0; 4], 0).unwrap();
+        assert!(PlayerMarketManager::get_position(&maker, market_id).yes_shares > 0);
+        assert_eq!(OrderBook::get_book(market_id, 1).len() as u64, MAX_ORDERS_PER_OWNER);
+        assert_eq!(PlayerMarketManager::get_position(&whale, market_id).yes_shares, 0);
+    }
+
+    #[test]
+    fn test_cancel_refunds_escrow_and_play_credit() {
+        let _guard = test_support::lock();
+        let market_id = test_support::market(0);
+        let (owner, other) = (test_support::new_pid(), test_support::player(0));
+        let mut player = Player::new_from_pid(owner);
+        player.data.add_faucet_grant(1_000, true);
+        player.store();
+
+        let order_id = OrderBook::place(&mut player, market_id, 0, 100_000, 600, 0).unwrap();
+        assert_eq!((player.data.balance, player.data.play_credit), (400, 400));
+        assert_eq!(OrderBook::get_order(order_id).unwrap().play_credit, 600);
+
+        assert_eq!(Activity::CancelOrder(order_id).handle(&other, 0, &[0; 4], 0), Err(ERROR_UNAUTHORIZED));
+        Activity::CancelOrder(order_id).handle(&owner, 0, &[0; 4], 0).unwrap();
+        let player = Player::get_from_pid(&owner).unwrap();
+        assert_eq!((player.data.balance, player.data.play_credit), (1_000, 1_000));
+        assert!(OrderBook::get_order(order_id).is_none());
+        assert!(OrderBook::get_book(market_id, 0).is_empty());
+        assert_eq!(Activity::CancelOrder(order_id).handle(&owner, 1, &[0; 4], 0), Err(ERROR_ORDER_NOT_FOUND));
+    }
+}
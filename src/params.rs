@@ -0,0 +1,230 @@
+use serde::Serialize;
+use std::cell::RefCell;
+use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
+use crate::config::{
+    PLATFORM_FEE_RATE, NEW_PLAYER_INITIAL_BALANCE, TICKS_PER_DAY,
+    WITHDRAW_EPOCH_TICKS, PLAYER_WITHDRAW_LIMIT_PER_EPOCH, GLOBAL_WITHDRAW_LIMIT_PER_EPOCH,
+    LARGE_WITHDRAW_THRESHOLD, LARGE_WITHDRAW_DELAY_TICKS, MAX_WITHDRAW_RELEASES_PER_TICK,
+    FAUCET_COOLDOWN_TICKS, FAUCET_PLAY_CREDIT, PARLAY_MARGIN_BPS,
+};
+use crate::error::ERROR_INVALID_PARAM;
+use crate::math_safe::{MAX_BET_AMOUNT, MIN_LIQUIDITY, MAX_SHARES, MAX_LIQUIDITY};
+
+// Parameter ids accepted by the SetParam command
+pub const PARAM_PLATFORM_FEE_RATE: u64 = 0;
+pub const PARAM_NEW_PLAYER_INITIAL_BALANCE: u64 = 1;
+pub const PARAM_MAX_BET_AMOUNT: u64 = 2;
+pub const PARAM_MIN_LIQUIDITY: u64 = 3;
+pub const PARAM_MAX_SHARES: u64 = 4;
+pub const PARAM_PREEMPT_TICK_INTERVAL: u64 = 5;
+pub const PARAM_PREEMPT_TX_THRESHOLD: u64 = 6;
+pub const PARAM_PREEMPT_WITHDRAW_THRESHOLD: u64 = 7;
+pub const PARAM_WITHDRAW_EPOCH_TICKS: u64 = 8;
+pub const PARAM_PLAYER_WITHDRAW_LIMIT: u64 = 9;
+pub const PARAM_GLOBAL_WITHDRAW_LIMIT: u64 = 10;
+pub const PARAM_LARGE_WITHDRAW_THRESHOLD: u64 = 11;
+pub const PARAM_LARGE_WITHDRAW_DELAY_TICKS: u64 = 12;
+pub const PARAM_MAX_WITHDRAW_RELEASES_PER_TICK: u64 = 13;
+pub const PARAM_FAUCET_COOLDOWN_TICKS: u64 = 14;
+pub const PARAM_FAUCET_PLAY_CREDIT: u64 = 15;
+pub const PARAM_PARLAY_MARGIN_BPS: u64 = 16;
+pub const PARAM_COUNT: u64 = 17;
+
+// Protocol parameters adjustable at runtime by the admin.
+// The compile-time constants are only used as defaults for a fresh state.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct ProtocolParams {
+    pub platform_fee_rate: u64, // basis points
+    pub new_player_initial_balance: u64, // faucet grant per claim, 0 disables grants
+    pub max_bet_amount: u64,
+    pub min_liquidity: u64,
+    pub max_shares: u64,
+    pub preempt_tick_interval: u64,
+    pub preempt_tx_threshold: u64,
+    pub preempt_withdraw_threshold: u64,
+    pub withdraw_epoch_ticks: u64,
+    pub player_withdraw_limit: u64,
+    pub global_withdraw_limit: u64,
+    pub large_withdraw_threshold: u64,
+    pub large_withdraw_delay_ticks: u64,
+    pub max_withdraw_releases_per_tick: u64,
+    pub faucet_cooldown_ticks: u64,
+    pub faucet_play_credit: u64, // 0 or 1
+    pub parlay_margin_bps: u64,  // taken off fair parlay odds
+}
+
+pub struct SafeParams(pub RefCell<ProtocolParams>);
+unsafe impl Sync for SafeParams {}
+
+lazy_static::lazy_static! {
+    pub static ref PROTOCOL_PARAMS: SafeParams = SafeParams(RefCell::new(ProtocolParams::DEFAULT));
+}
+
+impl ProtocolParams {
+    pub const DEFAULT: ProtocolParams = ProtocolParams {
+        platform_fee_rate: PLATFORM_FEE_RATE,
+        new_player_initial_balance: NEW_PLAYER_INITIAL_BALANCE,
+        max_bet_amount: MAX_BET_AMOUNT,
+        min_liquidity: MIN_LIQUIDITY,
+        max_shares: MAX_SHARES,
+        preempt_tick_interval: 600,
+        preempt_tx_threshold: 40,
+        preempt_withdraw_threshold: 40,
+        withdraw_epoch_ticks: WITHDRAW_EPOCH_TICKS,
+        player_withdraw_limit: PLAYER_WITHDRAW_LIMIT_PER_EPOCH,
+        global_withdraw_limit: GLOBAL_WITHDRAW_LIMIT_PER_EPOCH,
+        large_withdraw_threshold: LARGE_WITHDRAW_THRESHOLD,
+        large_withdraw_delay_ticks: LARGE_WITHDRAW_DELAY_TICKS,
+        max_withdraw_releases_per_tick: MAX_WITHDRAW_RELEASES_PER_TICK,
+        faucet_cooldown_ticks: FAUCET_COOLDOWN_TICKS,
+        faucet_play_credit: FAUCET_PLAY_CREDIT,
+        parlay_margin_bps: PARLAY_MARGIN_BPS,
+    };
+
+    const PARAMS_KEY: [u64; 4] = [7, 0, 0, 0]; // Storage key for the protocol parameter record
+
+    // Current parameters (a copy, so callers never hold on to the global)
+    pub fn get() -> ProtocolParams {
+        *PROTOCOL_PARAMS.0.borrow()
+    }
+
+    // Inclusive validation range for each parameter id
+    pub fn range(param_id: u64) -> Result<(u64, u64), u32> {
+        match param_id {
+            PARAM_PLATFORM_FEE_RATE => Ok((0, 1_000)), // at most 10%
+            PARAM_NEW_PLAYER_INITIAL_BALANCE => Ok((0, MAX_BET_AMOUNT)),
+            // The LMSR math is only checked against overflow up to the compile-time bounds,
+            // so these can be tightened but never loosened past them
+            PARAM_MAX_BET_AMOUNT => Ok((1, MAX_BET_AMOUNT)),
+            PARAM_MIN_LIQUIDITY => Ok((MIN_LIQUIDITY, MAX_LIQUIDITY)),
+            PARAM_MAX_SHARES => Ok((1, MAX_SHARES)),
+            PARAM_PREEMPT_TICK_INTERVAL => Ok((1, TICKS_PER_DAY)),
+            PARAM_PREEMPT_TX_THRESHOLD => Ok((1, 1_000)),
+            PARAM_PREEMPT_WITHDRAW_THRESHOLD => Ok((1, 1_000)),
+            PARAM_WITHDRAW_EPOCH_TICKS => Ok((1, TICKS_PER_DAY * 30)),
+            PARAM_PLAYER_WITHDRAW_LIMIT => Ok((0, u64::MAX)),
+            PARAM_GLOBAL_WITHDRAW_LIMIT => Ok((0, u64::MAX)),
+            PARAM_LARGE_WITHDRAW_THRESHOLD => Ok((1, u64::MAX)),
+            PARAM_LARGE_WITHDRAW_DELAY_TICKS => Ok((0, TICKS_PER_DAY * 30)),
+            PARAM_MAX_WITHDRAW_RELEASES_PER_TICK => Ok((1, 40)),
+            PARAM_FAUCET_COOLDOWN_TICKS => Ok((0, TICKS_PER_DAY * 30)),
+            PARAM_FAUCET_PLAY_CREDIT => Ok((0, 1)),
+            PARAM_PARLAY_MARGIN_BPS => Ok((0, 5_000)), // at most 50%
+            _ => Err(ERROR_INVALID_PARAM),
+        }
+    }
+
+    fn field_mut(&mut self, param_id: u64) -> Result<&mut u64, u32> {
+        match param_id {
+            PARAM_PLATFORM_FEE_RATE => Ok(&mut self.platform_fee_rate),
+            PARAM_NEW_PLAYER_INITIAL_BALANCE => Ok(&mut self.new_player_initial_balance),
+            PARAM_MAX_BET_AMOUNT => Ok(&mut self.max_bet_amount),
+            PARAM_MIN_LIQUIDITY => Ok(&mut self.min_liquidity),
+            PARAM_MAX_SHARES => Ok(&mut self.max_shares),
+            PARAM_PREEMPT_TICK_INTERVAL => Ok(&mut self.preempt_tick_interval),
+            PARAM_PREEMPT_TX_THRESHOLD => Ok(&mut self.preempt_tx_threshold),
+            PARAM_PREEMPT_WITHDRAW_THRESHOLD => Ok(&mut self.preempt_withdraw_threshold),
+            PARAM_WITHDRAW_EPOCH_TICKS => Ok(&mut self.withdraw_epoch_ticks),
+            PARAM_PLAYER_WITHDRAW_LIMIT => Ok(&mut self.player_withdraw_limit),
+            PARAM_GLOBAL_WITHDRAW_LIMIT => Ok(&mut self.global_withdraw_limit),
+            PARAM_LARGE_WITHDRAW_THRESHOLD => Ok(&mut self.large_withdraw_threshold),
+            PARAM_LARGE_WITHDRAW_DELAY_TICKS => Ok(&mut self.large_withdraw_delay_ticks),
+            PARAM_MAX_WITHDRAW_RELEASES_PER_TICK => Ok(&mut self.max_withdraw_releases_per_tick),
+            PARAM_FAUCET_COOLDOWN_TICKS => Ok(&mut self.faucet_cooldown_ticks),
+            PARAM_FAUCET_PLAY_CREDIT => Ok(&mut self.faucet_play_credit),
+            PARAM_PARLAY_MARGIN_BPS => Ok(&mut self.parlay_margin_bps),
+            _ => Err(ERROR_INVALID_PARAM),
+        }
+    }
+
+    // Validates and applies a single parameter change
+    pub fn set(&mut self, param_id: u64, value: u64) -> Result<(), u32> {
+        let (min, max) = Self::range(param_id)?;
+        if value < min || value > max {
+            return Err(ERROR_INVALID_PARAM);
+        }
+        *self.field_mut(param_id)? = value;
+        Ok(())
+    }
+
+    // Applies a change to the live parameters and persists them
+    pub fn update(param_id: u64, value: u64) -> Result<(), u32> {
+        let mut params = Self::get();
+        params.set(param_id, value)?;
+        *PROTOCOL_PARAMS.0.borrow_mut() = params;
+        Self::store();
+        Ok(())
+    }
+
+    pub fn store() {
+        let mut data = vec![];
+        Self::get().to_data(&mut data);
+        crate::journal::set(&Self::PARAMS_KEY, data.as_slice());
+    }
+
+    pub fn initialize() {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let mut data = kvpair.get(&Self::PARAMS_KEY);
+        if !data.is_empty() {
+            let mut u64data = data.iter_mut();
+            *PROTOCOL_PARAMS.0.borrow_mut() = Self::from_data(&mut u64data);
+        }
+    }
+}
+
+impl StorageData for ProtocolParams {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        // Parameters added after the record was stored keep their defaults
+        let mut params = ProtocolParams::DEFAULT;
+        for param_id in 0..PARAM_COUNT {
+            if let Some(value) = u64data.next() {
+                *params.field_mut(param_id).unwrap() = *value;
+            }
+        }
+        params
+    }
+
+    fn to_data(&self, data: &mut Vec<u64>) {
+        let mut params = *self;
+        for param_id in 0..PARAM_COUNT {
+            data.push(*params.field_mut(param_id).unwrap());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_param_validates_range() {
+        let mut params = ProtocolParams::DEFAULT;
+        params.set(PARAM_PLATFORM_FEE_RATE, 250).unwrap();
+        assert_eq!(params.platform_fee_rate, 250);
+        assert_eq!(params.set(PARAM_PLATFORM_FEE_RATE, 1_001), Err(ERROR_INVALID_PARAM));
+        assert_eq!(params.set(PARAM_MAX_BET_AMOUNT, 0), Err(ERROR_INVALID_PARAM));
+        assert_eq!(params.set(PARAM_MAX_BET_AMOUNT, MAX_BET_AMOUNT + 1), Err(ERROR_INVALID_PARAM));
+        assert_eq!(params.set(PARAM_MAX_SHARES, MAX_SHARES + 1), Err(ERROR_INVALID_PARAM));
+        assert_eq!(params.set(PARAM_MIN_LIQUIDITY, MIN_LIQUIDITY - 1), Err(ERROR_INVALID_PARAM));
+        assert_eq!(params.set(PARAM_COUNT, 1), Err(ERROR_INVALID_PARAM));
+        params.set(PARAM_NEW_PLAYER_INITIAL_BALANCE, 0).unwrap();
+        assert_eq!(params.new_player_initial_balance, 0);
+        assert_eq!(params.set(PARAM_FAUCET_PLAY_CREDIT, 2), Err(ERROR_INVALID_PARAM));
+    }
+
+    #[test]
+    fn test_params_storage_round_trip() {
+        let mut params = ProtocolParams::DEFAULT;
+        params.set(PARAM_PREEMPT_TX_THRESHOLD, 20).unwrap();
+        let mut data = vec![];
+        params.to_data(&mut data);
+        assert_eq!(data.len() as u64, PARAM_COUNT);
+        assert_eq!(ProtocolParams::from_data(&mut data.iter_mut()), params);
+
+        // A shorter record keeps defaults for the missing tail
+        let mut short = [500u64];
+        let decoded = ProtocolParams::from_data(&mut short.iter_mut());
+        assert_eq!(decoded.platform_fee_rate, 500);
+        assert_eq!(decoded.max_withdraw_releases_per_tick, MAX_WITHDRAW_RELEASES_PER_TICK);
+    }
+}
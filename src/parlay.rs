@@ -0,0 +1,371 @@
+use serde::Serialize;
+use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
+use crate::config::{PRICE_PRECISION, BASIS_POINTS_PRECISION, MAX_PARLAY_LEGS, MIN_PARLAY_LEG_PRICE};
+use crate::error::*;
+use crate::event::{insert_event, EVENT_PARLAY_UPDATE};
+use crate::market::MarketData;
+use crate::math_safe::validate_bet_amount;
+use crate::params::ProtocolParams;
+use crate::player::Player;
+use crate::state::{MarketManager, GLOBAL_STATE};
+use crate::twap::TwapOracle;
+
+// Parlay event status codes
+pub const PARLAY_PLACED: u64 = 0;
+pub const PARLAY_WON: u64 = 1;
+pub const PARLAY_LOST: u64 = 2;
+pub const PARLAY_REFUNDED: u64 = 3; // every leg was voided
+
+// Settlement of a single leg
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LegResult {
+    Pending,
+    Won,
+    Lost,
+    Void, // the market resolved without an outcome
+}
+
+// Combo bet paying only if every leg resolves the chosen way. The stake and the
+// bankroll funds reserved for the winnings stay in the record until it is settled.
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Parlay {
+    pub legs: Vec<(u64, u64, u64)>, // (market_id, side, price of the side at placement)
+    pub token: u64,
+    pub stake: u64,
+    pub reserved: u64, // bankroll funds covering payout - stake
+    pub counter: u64,  // counter at placement
+    pub play_credit: u64, // part of the stake paid with faucet play credit
+}
+
+impl StorageData for Parlay {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        let leg_count = *u64data.next().unwrap();
+        let mut legs = Vec::new();
+        for _ in 0..leg_count {
+            legs.push((*u64data.next().unwrap(), *u64data.next().unwrap(), *u64data.next().unwrap()));
+        }
+        Parlay {
+            legs,
+            token: *u64data.next().unwrap(),
+            stake: *u64data.next().unwrap(),
+            reserved: *u64data.next().unwrap(),
+            counter: *u64data.next().unwrap(),
+            // Parlays placed before credit tracking paid their stake in full
+            play_credit: u64data.next().map_or(0, |v| *v),
+        }
+    }
+
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.legs.len() as u64);
+        for (market_id, side, price) in self.legs.iter() {
+            data.push(*market_id);
+            data.push(*side);
+            data.push(*price);
+        }
+        data.push(self.token);
+        data.push(self.stake);
+        data.push(self.reserved);
+        data.push(self.counter);
+        data.push(self.play_credit);
+    }
+}
+
+impl Parlay {
+    // Stake multiplied by the inverse of every leg price, less the margin. Voided
+    // legs are left out of `prices` by the caller.
+    pub fn payout_for(stake: u64, prices: &[u64], margin_bps: u64) -> Result<u64, u32> {
+        let mut payout = stake as u128;
+        for price in prices {
+            if *price == 0 {
+                return Err(ERROR_DIVISION_BY_ZERO);
+            }
+            payout = payout * PRICE_PRECISION as u128 / *price as u128;
+        }
+        let payout = payout * (BASIS_POINTS_PRECISION - margin_bps) as u128 / BASIS_POINTS_PRECISION as u128;
+        u64::try_from(payout).map_err(|_| ERROR_OVERFLOW)
+    }
+
+    // Price a leg is sold at: the higher of the spot price and the quoted TWAP, so
+    // pushing the price down right before placing does not lengthen the odds
+    pub fn leg_price(market_id: u64, market: &MarketData, side: u64, counter: u64) -> Result<u64, u32> {
+        let spot = if side == 1 { market.get_yes_price()? } else { market.get_no_price()? };
+        let average = match TwapOracle::quote(market_id, market, counter)? {
+            Some(yes) if side == 1 => yes,
+            Some(yes) => PRICE_PRECISION.saturating_sub(yes),
+            None => spot,
+        };
+        let price = spot.max(average);
+        if price < MIN_PARLAY_LEG_PRICE {
+            return Err(ERROR_INVALID_PARLAY);
+        }
+        Ok(price)
+    }
+
+    pub fn leg_result(market: &MarketData, side: u64) -> LegResult {
+        if !market.resolved {
+            return LegResult::Pending;
+        }
+        match market.outcome {
+            None => LegResult::Void,
+            Some(outcome) if outcome == (side == 1) => LegResult::Won,
+            Some(_) => LegResult::Lost,
+        }
+    }
+
+    pub fn max_payout(&self) -> u64 {
+        self.stake + self.reserved
+    }
+}
+
+pub struct ParlayManager;
+
+impl ParlayManager {
+    const PARLAY_PREFIX: u64 = 15;   // Parlay records, keyed by owner and parlay id
+    const BANKROLL_PREFIX: u64 = 16; // Per-token funds backing parlay winnings
+
+    pub fn get_parlay(owner: &[u64; 2], parlay_id: u64) -> Option<Parlay> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let mut data = kvpair.get(&[Self::PARLAY_PREFIX, owner[0], owner[1], parlay_id]);
+        if !data.is_empty() {
+            let mut u64data = data.iter_mut();
+            Some(Parlay::from_data(&mut u64data))
+        } else {
+            None
+        }
+    }
+
+    fn store_parlay(owner: &[u64; 2], parlay_id: u64, parlay: &Parlay) {
+        let mut data = vec![];
+        parlay.to_data(&mut data);
+        crate::journal::set(&[Self::PARLAY_PREFIX, owner[0], owner[1], parlay_id], data.as_slice());
+    }
+
+    fn remove_parlay(owner: &[u64; 2], parlay_id: u64) {
+        crate::journal::set(&[Self::PARLAY_PREFIX, owner[0], owner[1], parlay_id], &[]);
+    }
+
+    pub fn bankroll(token: u64) -> u64 {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        kvpair.get(&[Self::BANKROLL_PREFIX, 0, token, 0]).first().copied().unwrap_or(0)
+    }
+
+    fn store_bankroll(token: u64, amount: u64) {
+        crate::journal::set(&[Self::BANKROLL_PREFIX, 0, token, 0], &[amount]);
+    }
+
+    // Treasurer deposit backing future parlay winnings, taken from their own balance
+    pub fn fund(player: &mut Player, token: u64, amount: u64) -> Result<(), u32> {
+        if amount == 0 {
+            return Err(ERROR_INVALID_BET_AMOUNT);
+        }
+        player.data.spend_balance_of(token, amount)?;
+        Self::store_bankroll(token, Self::bankroll(token).saturating_add(amount));
+        Ok(())
+    }
+
+    // The caller stores `player`
+    pub fn place(player: &mut Player, legs: &[(u64, u64)], stake: u64, counter: u64) -> Result<u64, u32> {
+        if legs.len() < 2 || legs.len() as u64 > MAX_PARLAY_LEGS {
+            return Err(ERROR_INVALID_PARLAY);
+        }
+        validate_bet_amount(stake)?;
+
+        let mut priced_legs = vec![];
+        let mut token = None;
+        for (index, (market_id, side)) in legs.iter().enumerate() {
+            if legs[..index].iter().any(|(other, _)| other == market_id) {
+                return Err(ERROR_INVALID_PARLAY);
+            }
+            GLOBAL_STATE.0.borrow().ensure_market_active(*market_id)?;
+            let market = MarketManager::get_market(*market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+            // Legs are priced off the LMSR and must share a collateral token
+            if market.is_clob() {
+                return Err(ERROR_INVALID_MARKET_KIND);
+            }
+            if *token.get_or_insert(market.token) != market.token {
+                return Err(ERROR_INVALID_TOKEN);
+            }
+            let price = Parlay::leg_price(*market_id, &market, *side, counter)?;
+            priced_legs.push((*market_id, *side, price));
+        }
+        let token = token.unwrap_or(0);
+
+        let prices: Vec<u64> = priced_legs.iter().map(|(_, _, price)| *price).collect();
+        let payout = Parlay::payout_for(stake, &prices, ProtocolParams::get().parlay_margin_bps)?;
+        let reserved = payout.saturating_sub(stake);
+        let bankroll = Self::bankroll(token);
+        if reserved > bankroll {
+            return Err(ERROR_PARLAY_BANKROLL_EXHAUSTED);
+        }
+
+        let play_credit = player.data.spend_stake_of(token, stake)?;
+        Self::store_bankroll(token, bankroll - reserved);
+        let parlay = Parlay {
+            legs: priced_legs,
+            token,
+            stake,
+            reserved,
+            counter,
+            play_credit,
+        };
+        let parlay_id = {
+            let mut global_state = GLOBAL_STATE.0.borrow_mut();
+            let parlay_id = global_state.next_parlay_id;
+            global_state.next_parlay_id += 1;
+            parlay_id
+        };
+        Self::store_parlay(&player.player_id, parlay_id, &parlay);
+        Self::emit_parlay_event(PARLAY_PLACED, parlay_id, &player.player_id, &parlay, payout, counter);
+        Ok(parlay_id)
+    }
+
+    // Settles a parlay once every leg is known. Anyone may settle, the payout always
+    // goes to the owner, so losing parlays can be closed to release their reserve.
+    // Returns the owner's payout; the caller stores `player`.
+    pub fn claim(player: &mut Player, owner: &[u64; 2], parlay_id: u64, counter: u64) -> Result<u64, u32> {
+        let parlay = Self::get_parlay(owner, parlay_id).ok_or(ERROR_PARLAY_NOT_FOUND)?;
+        let mut won_prices = vec![];
+        let mut lost = false;
+        for (market_id, side, price) in parlay.legs.iter() {
+            let mut market = MarketManager::get_market(*market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+            // A conditional leg whose condition failed counts as void
+            MarketManager::settle_condition(*market_id, &mut market)?;
+            match Parlay::leg_result(&market, *side) {
+                LegResult::Pending => return Err(ERROR_MARKET_NOT_RESOLVED),
+                LegResult::Won => won_prices.push(*price),
+                LegResult::Lost => lost = true,
+                LegResult::Void => (),
+            }
+        }
+
+        let (status, payout) = if lost {
+            (PARLAY_LOST, 0)
+        } else if won_prices.is_empty() {
+            (PARLAY_REFUNDED, parlay.stake)
+        } else {
+            // Voided legs drop out of the price, so the payout can only shrink
+            let payout = Parlay::payout_for(parlay.stake, &won_prices, ProtocolParams::get().parlay_margin_bps)?;
+            (PARLAY_WON, payout.min(parlay.max_payout()))
+        };
+
+        // Whatever the owner does not receive goes back to the bankroll
+        let bankroll = Self::bankroll(parlay.token);
+        Self::store_bankroll(parlay.token, bankroll.saturating_add(parlay.max_payout() - payout));
+        Self::remove_parlay(owner, parlay_id);
+        if payout > 0 {
+            if *owner == player.player_id {
+                player.data.add_proceeds_of(parlay.token, payout, parlay.play_credit);
+            } else {
+                crate::journal::track_player(owner);
+                let mut owner_player = Player::get_from_pid(owner).ok_or(ERROR_PLAYER_NOT_EXIST)?;
+                owner_player.data.add_proceeds_of(parlay.token, payout, parlay.play_credit);
+                owner_player.store();
+            }
+        }
+        Self::emit_parlay_event(status, parlay_id, owner, &parlay, payout, counter);
+        Ok(payout)
+    }
+
+    fn emit_parlay_event(status: u64, parlay_id: u64, owner: &[u64; 2], parlay: &Parlay, payout: u64, counter: u64) {
+        let mut data = vec![
+            status,
+            parlay_id,
+            owner[0],
+            owner[1],
+            parlay.token,
+            parlay.stake,
+            payout,
+            parlay.legs.len() as u64,
+            counter,
+        ];
+        insert_event(EVENT_PARLAY_UPDATE, &mut data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{Activity, CommandHandler};
+    use crate::test_support;
+
+    #[test]
+    fn test_parlay_payout_multiplies_inverse_prices() {
+        // 0.5 * 0.25 = 1/8 fair odds, 5% margin
+        assert_eq!(Parlay::payout_for(1_000, &[500_000, 250_000], 500), Ok(7_600));
+        assert_eq!(Parlay::payout_for(1_000, &[500_000], 0), Ok(2_000));
+        assert_eq!(Parlay::payout_for(1_000, &[], 500), Ok(950));
+        assert_eq!(Parlay::payout_for(u64::MAX, &[1, 1], 0), Err(ERROR_OVERFLOW));
+
+        let mut market = MarketData::new_with_liquidity(0, 1_000, 1_000, 1_000, 1_000, 10_000).unwrap();
+        assert_eq!(Parlay::leg_result(&market, 1), LegResult::Pending);
+        market.resolved = true;
+        assert_eq!(Parlay::leg_result(&market, 1), LegResult::Void);
+        market.outcome = Some(false);
+        assert_eq!(Parlay::leg_result(&market, 1), LegResult::Lost);
+        assert_eq!(Parlay::leg_result(&market, 0), LegResult::Won);
+    }
+
+    #[test]
+    fn test_leg_price_resists_spot_manipulation() {
+        let _guard = test_support::lock();
+        let market_id = test_support::market(0);
+        let mut market = MarketManager::get_market(market_id).unwrap();
+        let fair = market.get_yes_price().unwrap();
+
+        // A large NO bet just before placing drags the YES spot price down
+        market.place_bet(0, 20_000).unwrap();
+        market.record_price(499).unwrap();
+        MarketManager::update_market(market_id, &market);
+        let spot = market.get_yes_price().unwrap();
+        let price = Parlay::leg_price(market_id, &market, 1, 500).unwrap();
+        assert!(spot < price && price < fair);
+        assert_eq!(Parlay::leg_price(market_id, &market, 0, 500), Ok(market.get_no_price().unwrap()));
+
+        // Long shots beyond the per-leg bound are refused
+        let long_shot = MarketData::new_with_liquidity(0, 1_000, 1_000, 1_000, 60_000, 10_000).unwrap();
+        assert!(long_shot.get_yes_price().unwrap() < MIN_PARLAY_LEG_PRICE);
+        assert_eq!(Parlay::leg_price(0, &long_shot, 1, 0), Err(ERROR_INVALID_PARLAY));
+    }
+
+    fn resolve(market_id: u64, outcome: bool) {
+        let mut market = MarketManager::get_market(market_id).unwrap();
+        market.resolve(outcome).unwrap();
+        MarketManager::update_market(market_id, &market);
+    }
+
+    #[test]
+    fn test_parlay_settles_against_bankroll() {
+        let _guard = test_support::lock();
+        let token = 3;
+        let (first, second) = (test_support::market(token), test_support::market(token));
+        let (bettor, house) = (test_support::new_pid(), test_support::new_pid());
+        let mut player = Player::new_from_pid(bettor);
+        player.data.add_balance_of(token, 2_000);
+        player.store();
+        let mut treasurer = Player::new_from_pid(house);
+        treasurer.data.add_balance_of(token, 10_000);
+        ParlayManager::fund(&mut treasurer, token, 5_000).unwrap();
+        treasurer.store();
+
+        let place = Activity::PlaceParlay(1_000, vec![(first, 1), (second, 0)]);
+        place.handle(&bettor, 0, &[0; 4], 0).unwrap();
+        let parlay_id = GLOBAL_STATE.0.borrow().next_parlay_id - 1;
+        let parlay = ParlayManager::get_parlay(&bettor, parlay_id).unwrap();
+        assert_eq!(Player::get_from_pid(&bettor).unwrap().data.balance_of(token), 1_000);
+        assert_eq!(ParlayManager::bankroll(token), 5_000 - parlay.reserved);
+        // A second parlay cannot be covered by what is left in the bankroll
+        assert_eq!(place.handle(&bettor, 1, &[0; 4], 0), Err(ERROR_PARLAY_BANKROLL_EXHAUSTED));
+
+        let claim = Activity::ClaimParlay(bettor, parlay_id);
+        resolve(first, true);
+        assert_eq!(claim.handle(&house, 0, &[0; 4], 0), Err(ERROR_MARKET_NOT_RESOLVED));
+        resolve(second, false);
+
+        // Settled by the house, paid to the bettor
+        claim.handle(&house, 0, &[0; 4], 0).unwrap();
+        assert_eq!(Player::get_from_pid(&bettor).unwrap().data.balance_of(token), 1_000 + parlay.max_payout());
+        assert_eq!(ParlayManager::bankroll(token), 5_000 - parlay.reserved);
+        assert!(ParlayManager::get_parlay(&bettor, parlay_id).is_none());
+        assert_eq!(claim.handle(&house, 1, &[0; 4], 0), Err(ERROR_PARLAY_NOT_FOUND));
+    }
+}
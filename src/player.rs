@@ -1,20 +1,186 @@
 use serde::Serialize;
 use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
 use crate::error::*;
+use crate::market::MarketData;
+use crate::math_safe::{safe_add, safe_div_high_precision};
 
 #[derive(Serialize, Clone, Debug, Default)]
 pub struct PlayerMarketPosition {
     pub yes_shares: u64,
     pub no_shares: u64,
     pub claimed: bool,
+
+    // Cost basis of the shares currently held (tokens spent including fees)
+    pub yes_cost_basis: u64,
+    pub no_cost_basis: u64,
+
+    // Lifetime trade totals per side
+    pub yes_total_spent: u64,
+    pub no_total_spent: u64,
+    pub yes_total_received: u64,
+    pub no_total_received: u64,
+
+    // Realized PnL per side (sells and settlement against released cost basis)
+    pub yes_realized_pnl: i64,
+    pub no_realized_pnl: i64,
+
+    // Part of the cost basis paid with faucet play credit; proceeds up to this
+    // amount come back as play credit
+    pub yes_play_credit: u64,
+    pub no_play_credit: u64,
+}
+
+impl PlayerMarketPosition {
+    // (shares, cost_basis, total_spent, total_received, realized_pnl) for one side
+    fn side_mut(&mut self, is_yes: bool) -> (&mut u64, &mut u64, &mut u64, &mut u64, &mut i64) {
+        if is_yes {
+            (&mut self.yes_shares, &mut self.yes_cost_basis, &mut self.yes_total_spent, &mut self.yes_total_received, &mut self.yes_realized_pnl)
+        } else {
+            (&mut self.no_shares, &mut self.no_cost_basis, &mut self.no_total_spent, &mut self.no_total_received, &mut self.no_realized_pnl)
+        }
+    }
+
+    // Average cost per share scaled to PRICE_PRECISION, 0 if no shares are held
+    pub fn average_cost(&self, is_yes: bool) -> u64 {
+        let (shares, cost_basis) = if is_yes {
+            (self.yes_shares, self.yes_cost_basis)
+        } else {
+            (self.no_shares, self.no_cost_basis)
+        };
+        if shares == 0 {
+            return 0;
+        }
+        safe_div_high_precision(cost_basis, crate::config::PRICE_PRECISION, shares).unwrap_or(u64::MAX)
+    }
+
+    pub fn realized_pnl(&self) -> i64 {
+        self.yes_realized_pnl + self.no_realized_pnl
+    }
+
+    fn play_credit_mut(&mut self, is_yes: bool) -> &mut u64 {
+        if is_yes {
+            &mut self.yes_play_credit
+        } else {
+            &mut self.no_play_credit
+        }
+    }
+
+    // `credit` is the part of `cost` paid with play credit
+    pub fn record_buy(&mut self, is_yes: bool, shares: u64, cost: u64, credit: u64) -> Result<(), u32> {
+        let (held, cost_basis, total_spent, _, _) = self.side_mut(is_yes);
+        *held = safe_add(*held, shares)?;
+        *cost_basis = safe_add(*cost_basis, cost)?;
+        *total_spent = safe_add(*total_spent, cost)?;
+        let play_credit = self.play_credit_mut(is_yes);
+        *play_credit = safe_add(*play_credit, credit)?;
+        Ok(())
+    }
+
+    // Takes `shares` off one side with their pro rata cost basis and play credit
+    fn release(&mut self, is_yes: bool, shares: u64) -> Result<(u64, u64), u32> {
+        let credit = *self.play_credit_mut(is_yes);
+        let (held, cost_basis, _, _, _) = self.side_mut(is_yes);
+        if *held < shares {
+            return Err(ERROR_INSUFFICIENT_BALANCE);
+        }
+        let (released, released_credit) = if shares == *held {
+            (*cost_basis, credit)
+        } else {
+            (safe_div_high_precision(*cost_basis, shares, *held)?, safe_div_high_precision(credit, shares, *held)?)
+        };
+        *held -= shares;
+        *cost_basis -= released;
+        *self.play_credit_mut(is_yes) -= released_credit;
+        Ok((released, released_credit))
+    }
+
+    // Selling releases cost basis pro rata and realizes the difference to the payout.
+    // Returns the cost basis and play credit released with the shares.
+    pub fn record_sell(&mut self, is_yes: bool, shares: u64, payout: u64) -> Result<(u64, u64), u32> {
+        let (released, credit) = self.release(is_yes, shares)?;
+        let (_, _, _, total_received, realized_pnl) = self.side_mut(is_yes);
+        *total_received = safe_add(*total_received, payout)?;
+        *realized_pnl += payout as i64 - released as i64;
+        Ok((released, credit))
+    }
+
+    // Moving shares to another player carries their cost basis and play credit
+    // along, nothing is realized
+    pub fn transfer_out(&mut self, is_yes: bool, shares: u64) -> Result<(u64, u64), u32> {
+        self.release(is_yes, shares)
+    }
+
+    pub fn transfer_in(&mut self, is_yes: bool, shares: u64, cost: u64, credit: u64) -> Result<(), u32> {
+        let (held, cost_basis, _, _, _) = self.side_mut(is_yes);
+        *held = safe_add(*held, shares)?;
+        *cost_basis = safe_add(*cost_basis, cost)?;
+        let play_credit = self.play_credit_mut(is_yes);
+        *play_credit = safe_add(*play_credit, credit)?;
+        Ok(())
+    }
+
+    // Settlement closes out both sides: the winning side realizes the payout
+    // against its cost basis, the losing side writes its cost basis off.
+    // Returns the play credit of the winning side.
+    pub fn record_claim(&mut self, outcome: bool, payout: u64) -> Result<u64, u32> {
+        {
+            let (_, cost_basis, _, total_received, realized_pnl) = self.side_mut(outcome);
+            *total_received = safe_add(*total_received, payout)?;
+            *realized_pnl += payout as i64 - *cost_basis as i64;
+            *cost_basis = 0;
+        }
+        let (_, cost_basis, _, _, realized_pnl) = self.side_mut(!outcome);
+        *realized_pnl -= *cost_basis as i64;
+        *cost_basis = 0;
+        let credit = *self.play_credit_mut(outcome);
+        (self.yes_play_credit, self.no_play_credit) = (0, 0);
+        self.claimed = true;
+        Ok(credit)
+    }
+
+    // Voided market: the refund is split across both sides in proportion to
+    // their cost basis, which is then cleared. Returns the play credit of both sides.
+    pub fn record_refund(&mut self, refund: u64) -> Result<u64, u32> {
+        let total_cost = safe_add(self.yes_cost_basis, self.no_cost_basis)?;
+        let yes_refund = if self.yes_cost_basis == 0 {
+            0
+        } else {
+            safe_div_high_precision(refund, self.yes_cost_basis, total_cost)?
+        };
+        for (is_yes, side_refund) in [(true, yes_refund), (false, refund - yes_refund)] {
+            let (_, cost_basis, _, total_received, realized_pnl) = self.side_mut(is_yes);
+            *total_received = safe_add(*total_received, side_refund)?;
+            *realized_pnl += side_refund as i64 - *cost_basis as i64;
+            *cost_basis = 0;
+        }
+        let credit = safe_add(self.yes_play_credit, self.no_play_credit)?;
+        (self.yes_play_credit, self.no_play_credit) = (0, 0);
+        self.claimed = true;
+        Ok(credit)
+    }
 }
 
 impl StorageData for PlayerMarketPosition {
     fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        let yes_shares = *u64data.next().unwrap();
+        let no_shares = *u64data.next().unwrap();
+        let claimed = *u64data.next().unwrap() != 0;
+        // Positions stored before cost tracking only carry the first three words
+        let mut next = || u64data.next().map_or(0, |v| *v);
         PlayerMarketPosition {
-            yes_shares: *u64data.next().unwrap(),
-            no_shares: *u64data.next().unwrap(),
-            claimed: *u64data.next().unwrap() != 0,
+            yes_shares,
+            no_shares,
+            claimed,
+            yes_cost_basis: next(),
+            no_cost_basis: next(),
+            yes_total_spent: next(),
+            no_total_spent: next(),
+            yes_total_received: next(),
+            no_total_received: next(),
+            yes_realized_pnl: next() as i64,
+            no_realized_pnl: next() as i64,
+            yes_play_credit: next(),
+            no_play_credit: next(),
         }
     }
 
@@ -22,6 +188,16 @@ impl StorageData for PlayerMarketPosition {
         data.push(self.yes_shares);
         data.push(self.no_shares);
         data.push(if self.claimed { 1 } else { 0 });
+        data.push(self.yes_cost_basis);
+        data.push(self.no_cost_basis);
+        data.push(self.yes_total_spent);
+        data.push(self.no_total_spent);
+        data.push(self.yes_total_received);
+        data.push(self.no_total_received);
+        data.push(self.yes_realized_pnl as u64);
+        data.push(self.no_realized_pnl as u64);
+        data.push(self.yes_play_credit);
+        data.push(self.no_play_credit);
     }
 }
 
@@ -29,6 +205,7 @@ pub struct PlayerMarketManager;
 
 impl PlayerMarketManager {
     const POSITION_PREFIX: [u64; 2] = [2, 0]; // Prefix for player market position storage
+    const POSITION_INDEX_PREFIX: [u64; 2] = [3, 0]; // Prefix for per-player market id index
     
     // 安全的 player_id 组合方法
     fn combine_player_id_safe(player_id: &[u64; 2]) -> u64 {
@@ -55,16 +232,87 @@ impl PlayerMarketManager {
     pub fn store_position(player_id: &[u64; 2], market_id: u64, position: &PlayerMarketPosition) {
         let mut data = vec![];
         position.to_data(&mut data);
-        let kvpair = unsafe { &mut MERKLE_MAP };
         let combined_player_id = Self::combine_player_id_safe(player_id);
         let key = [Self::POSITION_PREFIX[0], Self::POSITION_PREFIX[1], combined_player_id, market_id];
-        kvpair.set(&key, data.as_slice());
+        crate::journal::set(&key, data.as_slice());
+
+        // Keep the per-player market index in sync so open positions can be enumerated;
+        // claimed and emptied positions drop out so the index does not keep growing
+        let mut market_ids = Self::get_market_ids(player_id);
+        let open = !position.claimed && (position.yes_shares > 0 || position.no_shares > 0);
+        let indexed = market_ids.contains(&market_id);
+        if open && !indexed {
+            market_ids.push(market_id);
+            Self::store_market_ids(player_id, &market_ids);
+        } else if !open && indexed {
+            market_ids.retain(|id| *id != market_id);
+            Self::store_market_ids(player_id, &market_ids);
+        }
+    }
+
+    // List of market ids the player holds an open position in (in first-touch order)
+    pub fn get_market_ids(player_id: &[u64; 2]) -> Vec<u64> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let combined_player_id = Self::combine_player_id_safe(player_id);
+        let key = [Self::POSITION_INDEX_PREFIX[0], Self::POSITION_INDEX_PREFIX[1], combined_player_id, 0];
+        kvpair.get(&key)
     }
+
+    fn store_market_ids(player_id: &[u64; 2], market_ids: &[u64]) {
+        let combined_player_id = Self::combine_player_id_safe(player_id);
+        let key = [Self::POSITION_INDEX_PREFIX[0], Self::POSITION_INDEX_PREFIX[1], combined_player_id, 0];
+        crate::journal::set(&key, market_ids);
+    }
+
+    // All non-empty positions of a player, valued at current market prices
+    pub fn get_position_views(player_id: &[u64; 2]) -> Vec<PlayerPositionView> {
+        let mut views = vec![];
+        for market_id in Self::get_market_ids(player_id) {
+            let position = Self::get_position(player_id, market_id);
+            if position.yes_shares == 0 && position.no_shares == 0 {
+                continue;
+            }
+            let market = crate::state::MarketManager::get_market(market_id);
+            let token = market.as_ref().map_or(0, |market| market.token);
+            let value = market.map_or(0, |market| market.mark_to_market(&position).unwrap_or(0));
+            let open_cost_basis = position.yes_cost_basis + position.no_cost_basis;
+            views.push(PlayerPositionView {
+                market_id,
+                token,
+                value,
+                unrealized_pnl: value as i64 - open_cost_basis as i64,
+                realized_pnl: position.realized_pnl(),
+                yes_average_cost: position.average_cost(true),
+                no_average_cost: position.average_cost(false),
+                position,
+            });
+        }
+        views
+    }
+}
+
+// Position as reported by the player state query
+#[derive(Serialize, Clone, Debug)]
+pub struct PlayerPositionView {
+    pub market_id: u64,
+    pub token: u64, // collateral token of the market
+    #[serde(flatten)]
+    pub position: PlayerMarketPosition,
+    pub value: u64, // mark-to-market value in tokens
+    pub unrealized_pnl: i64, // value minus cost basis of the shares held
+    pub realized_pnl: i64,
+    pub yes_average_cost: u64, // scaled to PRICE_PRECISION
+    pub no_average_cost: u64,
 }
 
 #[derive(Serialize, Clone, Debug, Default)]
 pub struct PlayerData {
-    pub balance: u64,
+    pub balance: u64,             // balance of collateral token 0
+    pub token_balances: Vec<u64>, // balances of tokens 1.., token_balances[i] holds token i + 1
+    pub withdraw_epoch: u64,      // epoch of the withdrawal rate-limit window
+    pub withdrawn_in_epoch: u64,  // amount withdrawn during withdraw_epoch
+    pub play_credit: u64,         // part of the token 0 balance granted by the faucet, not withdrawable
+    pub last_faucet_claim: u64,   // counter of the last faucet grant
 }
 
 #[derive(Serialize, Clone, Debug)]
@@ -72,6 +320,7 @@ pub struct PredictionMarketPlayer {
     pub player_id: [u64; 2],
     pub nonce: u64,
     pub data: PlayerData,
+    pub positions: Vec<PlayerPositionView>,
 }
 
 impl PredictionMarketPlayer {
@@ -84,6 +333,7 @@ impl PredictionMarketPlayer {
                 player_id,
                 nonce: player.nonce,
                 data: player.data,
+                positions: PlayerMarketManager::get_position_views(&player_id),
             }),
             None => {
                 // Return default player with global info
@@ -91,6 +341,7 @@ impl PredictionMarketPlayer {
                     player_id,
                     nonce: 0,
                     data: PlayerData::default(),
+                    positions: vec![],
                 })
             }
         }
@@ -107,6 +358,73 @@ impl PlayerData {
             return Err(ERROR_INSUFFICIENT_BALANCE);
         }
         self.balance -= amount;
+        // Withdrawable balance goes first, play credit only covers the rest
+        self.play_credit = self.play_credit.min(self.balance);
+        Ok(())
+    }
+
+    // Takes a stake for a trade and returns the part of it paid with play credit
+    pub fn spend_stake_of(&mut self, token: u64, amount: u64) -> Result<u64, u32> {
+        let credit_before = self.play_credit;
+        self.spend_balance_of(token, amount)?;
+        Ok(credit_before - self.play_credit)
+    }
+
+    // Pays out trade proceeds. Up to `credit` of them stand in for play credit the
+    // trade consumed and stay non-withdrawable.
+    pub fn add_proceeds_of(&mut self, token: u64, amount: u64, credit: u64) {
+        self.add_balance_of(token, amount);
+        if token == 0 {
+            self.play_credit += credit.min(amount);
+        }
+    }
+
+    pub fn add_faucet_grant(&mut self, amount: u64, play_credit: bool) {
+        self.balance += amount;
+        if play_credit {
+            self.play_credit += amount;
+        }
+    }
+
+    // Balance that may leave the player's account through withdraw or transfer
+    pub fn withdrawable_balance_of(&self, token: u64) -> u64 {
+        if token == 0 {
+            return self.balance.saturating_sub(self.play_credit);
+        }
+        self.balance_of(token)
+    }
+
+    // Per-token balances; token 0 is the legacy `balance` field
+    pub fn balance_of(&self, token: u64) -> u64 {
+        if token == 0 {
+            return self.balance;
+        }
+        self.token_balances.get(token as usize - 1).copied().unwrap_or(0)
+    }
+
+    fn balance_of_mut(&mut self, token: u64) -> &mut u64 {
+        if token == 0 {
+            return &mut self.balance;
+        }
+        let index = token as usize - 1;
+        if self.token_balances.len() <= index {
+            self.token_balances.resize(index + 1, 0);
+        }
+        &mut self.token_balances[index]
+    }
+
+    pub fn add_balance_of(&mut self, token: u64, amount: u64) {
+        *self.balance_of_mut(token) += amount;
+    }
+
+    pub fn spend_balance_of(&mut self, token: u64, amount: u64) -> Result<(), u32> {
+        if token == 0 {
+            return self.spend_balance(amount);
+        }
+        if self.balance_of(token) < amount {
+            return Err(ERROR_INSUFFICIENT_BALANCE);
+        }
+        *self.balance_of_mut(token) -= amount;
         Ok(())
     }
 
@@ -121,59 +439,218 @@ impl PlayerData {
         position.no_shares
     }
 
-    pub fn add_yes_shares_for_market(&mut self, player_id: &[u64; 2], market_id: u64, shares: u64) {
+    pub fn add_yes_shares_for_market(&mut self, player_id: &[u64; 2], market_id: u64, shares: u64, cost: u64, credit: u64) -> Result<(), u32> {
         let mut position = PlayerMarketManager::get_position(player_id, market_id);
-        position.yes_shares += shares;
+        position.record_buy(true, shares, cost, credit)?;
         PlayerMarketManager::store_position(player_id, market_id, &position);
+        Ok(())
     }
 
-    pub fn add_no_shares_for_market(&mut self, player_id: &[u64; 2], market_id: u64, shares: u64) {
+    pub fn add_no_shares_for_market(&mut self, player_id: &[u64; 2], market_id: u64, shares: u64, cost: u64, credit: u64) -> Result<(), u32> {
         let mut position = PlayerMarketManager::get_position(player_id, market_id);
-        position.no_shares += shares;
+        position.record_buy(false, shares, cost, credit)?;
         PlayerMarketManager::store_position(player_id, market_id, &position);
+        Ok(())
     }
 
-    pub fn subtract_yes_shares_for_market(&mut self, player_id: &[u64; 2], market_id: u64, shares: u64) -> Result<(), u32> {
+    // Returns the cost basis and play credit released with the shares
+    pub fn subtract_yes_shares_for_market(&mut self, player_id: &[u64; 2], market_id: u64, shares: u64, payout: u64) -> Result<(u64, u64), u32> {
         let mut position = PlayerMarketManager::get_position(player_id, market_id);
-        if position.yes_shares < shares {
-            return Err(ERROR_INSUFFICIENT_BALANCE);
-        }
-        position.yes_shares -= shares;
+        let released = position.record_sell(true, shares, payout)?;
         PlayerMarketManager::store_position(player_id, market_id, &position);
-        Ok(())
+        Ok(released)
     }
 
-    pub fn subtract_no_shares_for_market(&mut self, player_id: &[u64; 2], market_id: u64, shares: u64) -> Result<(), u32> {
+    // Returns the cost basis and play credit released with the shares
+    pub fn subtract_no_shares_for_market(&mut self, player_id: &[u64; 2], market_id: u64, shares: u64, payout: u64) -> Result<(u64, u64), u32> {
         let mut position = PlayerMarketManager::get_position(player_id, market_id);
-        if position.no_shares < shares {
-            return Err(ERROR_INSUFFICIENT_BALANCE);
+        let released = position.record_sell(false, shares, payout)?;
+        PlayerMarketManager::store_position(player_id, market_id, &position);
+        Ok(released)
+    }
+
+    // Marks the position claimed, settles its cost basis and returns the payout
+    // with the play credit it stands in for
+    pub fn claim_winnings_for_market(&mut self, player_id: &[u64; 2], market_id: u64, market: &MarketData) -> Result<(u64, u64), u32> {
+        let mut position = PlayerMarketManager::get_position(player_id, market_id);
+        if position.claimed {
+            return Err(ERROR_ALREADY_CLAIMED);
         }
-        position.no_shares -= shares;
+        let payout = market.calculate_payout(position.yes_shares, position.no_shares)?;
+        let credit = position.record_claim(market.outcome == Some(true), payout)?;
         PlayerMarketManager::store_position(player_id, market_id, &position);
-        Ok(())
+        Ok((payout, credit))
     }
 
-    pub fn claim_winnings_for_market(&mut self, player_id: &[u64; 2], market_id: u64) -> Result<(), u32> {
+    // Marks the position on a voided market claimed and takes its cost basis
+    // back out of the market, the caller stores the market. Returns the refund with
+    // the play credit it stands in for.
+    pub fn refund_position_for_market(&mut self, player_id: &[u64; 2], market_id: u64, market: &mut MarketData) -> Result<(u64, u64), u32> {
         let mut position = PlayerMarketManager::get_position(player_id, market_id);
         if position.claimed {
             return Err(ERROR_ALREADY_CLAIMED);
         }
-        position.claimed = true;
+        let refund = market.take_refund(safe_add(position.yes_cost_basis, position.no_cost_basis)?)?;
+        let credit = position.record_refund(refund)?;
         PlayerMarketManager::store_position(player_id, market_id, &position);
-        Ok(())
+        Ok((refund, credit))
     }
 }
 
 impl StorageData for PlayerData {
     fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        let balance = *u64data.next().unwrap();
+        // Players stored before multi-token support only carry the token 0 balance
+        let token_count = u64data.next().map_or(0, |v| *v);
+        let mut token_balances = Vec::new();
+        for _ in 0..token_count {
+            if let Some(b) = u64data.next() {
+                token_balances.push(*b);
+            }
+        }
         PlayerData {
-            balance: *u64data.next().unwrap(),
+            balance,
+            token_balances,
+            withdraw_epoch: u64data.next().map_or(0, |v| *v),
+            withdrawn_in_epoch: u64data.next().map_or(0, |v| *v),
+            // Balances granted before play credit existed stay fully withdrawable
+            play_credit: u64data.next().map_or(0, |v| *v),
+            last_faucet_claim: u64data.next().map_or(0, |v| *v),
         }
     }
 
     fn to_data(&self, data: &mut Vec<u64>) {
         data.push(self.balance);
+        data.push(self.token_balances.len() as u64);
+        data.extend(self.token_balances.iter().cloned());
+        data.push(self.withdraw_epoch);
+        data.push(self.withdrawn_in_epoch);
+        data.push(self.play_credit);
+        data.push(self.last_faucet_claim);
     }
 }
 
-pub type Player = zkwasm_rest_abi::Player<PlayerData>; 
\ No newline at end of file
+pub type Player = zkwasm_rest_abi::Player<PlayerData>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_position_cost_basis_and_realized_pnl() {
+        let mut position = PlayerMarketPosition::default();
+        position.record_buy(true, 1_000, 600, 0).unwrap();
+        position.record_buy(true, 1_000, 400, 0).unwrap();
+        assert_eq!(position.yes_cost_basis, 1_000);
+        assert_eq!(position.average_cost(true), 500_000); // 0.5 per share
+
+        // Selling half releases half of the basis
+        position.record_sell(true, 1_000, 700).unwrap();
+        assert_eq!(position.yes_shares, 1_000);
+        assert_eq!(position.yes_cost_basis, 500);
+        assert_eq!(position.yes_total_received, 700);
+        assert_eq!(position.yes_realized_pnl, 200);
+        assert_eq!(position.record_sell(true, 1_001, 1), Err(ERROR_INSUFFICIENT_BALANCE));
+
+        // NO side loses at settlement and writes off its basis
+        position.record_buy(false, 500, 300, 0).unwrap();
+        position.record_claim(true, 900).unwrap();
+        assert!(position.claimed);
+        assert_eq!(position.yes_cost_basis, 0);
+        assert_eq!(position.no_cost_basis, 0);
+        assert_eq!(position.yes_realized_pnl, 600);
+        assert_eq!(position.no_realized_pnl, -300);
+        assert_eq!(position.realized_pnl(), 300);
+    }
+
+    #[test]
+    fn test_position_transfer_moves_cost_basis() {
+        let mut from = PlayerMarketPosition::default();
+        let mut to = PlayerMarketPosition::default();
+        from.record_buy(false, 300, 150, 90).unwrap();
+
+        let (cost, credit) = from.transfer_out(false, 100).unwrap();
+        to.transfer_in(false, 100, cost, credit).unwrap();
+        assert_eq!((from.no_shares, from.no_cost_basis, from.no_play_credit), (200, 100, 60));
+        assert_eq!((to.no_shares, to.no_cost_basis, to.no_play_credit), (100, 50, 30));
+        assert_eq!(to.no_total_spent, 0);
+        assert_eq!(from.no_realized_pnl, 0);
+        assert_eq!(from.transfer_out(false, 201), Err(ERROR_INSUFFICIENT_BALANCE));
+    }
+
+    #[test]
+    fn test_player_token_balances() {
+        let mut data = PlayerData::default();
+        data.add_balance_of(0, 100);
+        data.add_balance_of(3, 50);
+        assert_eq!(data.balance, 100);
+        assert_eq!(data.balance_of(3), 50);
+        assert_eq!(data.balance_of(2), 0);
+        assert_eq!(data.spend_balance_of(3, 51), Err(ERROR_INSUFFICIENT_BALANCE));
+        data.spend_balance_of(3, 20).unwrap();
+
+        let mut stored = vec![];
+        data.to_data(&mut stored);
+        let decoded = PlayerData::from_data(&mut stored.iter_mut());
+        assert_eq!(decoded.token_balances, vec![0, 0, 30]);
+
+        let mut legacy = [7];
+        let decoded = PlayerData::from_data(&mut legacy.iter_mut());
+        assert_eq!((decoded.balance, decoded.token_balances.len()), (7, 0));
+    }
+
+    #[test]
+    fn test_play_credit_is_not_withdrawable() {
+        let mut data = PlayerData::default();
+        data.add_faucet_grant(1_000, true);
+        data.add_balance(300); // deposit
+        assert_eq!(data.withdrawable_balance_of(0), 300);
+
+        // Spending below the granted amount shrinks the locked credit with it
+        data.spend_balance(800).unwrap();
+        assert_eq!((data.balance, data.play_credit), (500, 500));
+        assert_eq!(data.withdrawable_balance_of(0), 0);
+        data.add_balance(200); // winnings
+        assert_eq!(data.withdrawable_balance_of(0), 200);
+
+        data.add_faucet_grant(50, false);
+        assert_eq!(data.withdrawable_balance_of(0), 250);
+    }
+
+    #[test]
+    fn test_position_storage_round_trip_and_legacy_layout() {
+        let mut position = PlayerMarketPosition::default();
+        position.record_buy(false, 10, 20, 0).unwrap();
+        position.record_sell(false, 10, 5).unwrap();
+        let mut data = vec![];
+        position.to_data(&mut data);
+        let decoded = PlayerMarketPosition::from_data(&mut data.iter_mut());
+        assert_eq!(decoded.no_realized_pnl, -15);
+        assert_eq!(decoded.no_total_spent, 20);
+
+        let mut legacy = [3, 4, 1];
+        let decoded = PlayerMarketPosition::from_data(&mut legacy.iter_mut());
+        assert_eq!((decoded.yes_shares, decoded.no_shares, decoded.claimed), (3, 4, true));
+        assert_eq!(decoded.yes_cost_basis, 0);
+    }
+
+    #[test]
+    fn test_market_index_drops_closed_positions() {
+        let _guard = crate::test_support::lock();
+        let pid = crate::test_support::new_pid();
+        let mut position = PlayerMarketPosition::default();
+        position.record_buy(true, 10, 5, 0).unwrap();
+        PlayerMarketManager::store_position(&pid, 1, &position);
+        PlayerMarketManager::store_position(&pid, 2, &position);
+        assert_eq!(PlayerMarketManager::get_market_ids(&pid), vec![1, 2]);
+
+        position.record_sell(true, 10, 6).unwrap();
+        PlayerMarketManager::store_position(&pid, 1, &position);
+        assert_eq!(PlayerMarketManager::get_market_ids(&pid), vec![2]);
+
+        position.record_buy(false, 10, 5, 0).unwrap();
+        position.record_claim(true, 0).unwrap();
+        PlayerMarketManager::store_position(&pid, 2, &position);
+        assert!(PlayerMarketManager::get_market_ids(&pid).is_empty());
+    }
+}
@@ -0,0 +1,131 @@
+use zkwasm_rest_abi::MERKLE_MAP;
+use crate::config::ADMIN_PUBKEY;
+use crate::error::ERROR_INVALID_ROLE;
+use crate::event::{insert_event, EVENT_ROLE_UPDATE};
+use crate::player::Player;
+
+// Role bits; a player's roles are stored as a mask
+pub const ROLE_SUPER_ADMIN: u64 = 1;
+pub const ROLE_MARKET_CREATOR: u64 = 1 << 1;
+pub const ROLE_RESOLVER: u64 = 1 << 2;
+pub const ROLE_TREASURER: u64 = 1 << 3;
+pub const ROLE_DEPOSITOR: u64 = 1 << 4;
+pub const GRANTABLE_ROLES: u64 = ROLE_MARKET_CREATOR | ROLE_RESOLVER | ROLE_TREASURER | ROLE_DEPOSITOR;
+
+// Registry of privileged players. The super-admin holds every role; until it is
+// rotated the super-admin is the player behind the deployed ADMIN_PUBKEY.
+pub struct RoleManager;
+
+impl RoleManager {
+    const SUPER_ADMIN_KEY: [u64; 4] = [8, 0, 0, 0]; // Storage key for the current super-admin
+    const ROLE_PREFIX: u64 = 9;                       // Storage prefix for per-player role masks
+
+    pub fn super_admin() -> [u64; 2] {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let data = kvpair.get(&Self::SUPER_ADMIN_KEY);
+        if data.len() >= 2 {
+            [data[0], data[1]]
+        } else {
+            Player::pkey_to_pid(&ADMIN_PUBKEY)
+        }
+    }
+
+    fn role_key(player_id: &[u64; 2]) -> [u64; 4] {
+        [Self::ROLE_PREFIX, 0, player_id[0], player_id[1]]
+    }
+
+    pub fn get_roles(player_id: &[u64; 2]) -> u64 {
+        if *player_id == Self::super_admin() {
+            return ROLE_SUPER_ADMIN | GRANTABLE_ROLES;
+        }
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        kvpair.get(&Self::role_key(player_id)).first().copied().unwrap_or(0)
+    }
+
+    pub fn has_role(player_id: &[u64; 2], role: u64) -> bool {
+        Self::get_roles(player_id) & role == role
+    }
+
+    fn store_roles(player_id: &[u64; 2], roles: u64) {
+        crate::journal::set(&Self::role_key(player_id), &[roles]);
+    }
+
+    // Super-admin cannot be granted or revoked, only rotated
+    pub fn validate_roles(roles: u64) -> Result<(), u32> {
+        if roles == 0 || roles & !GRANTABLE_ROLES != 0 {
+            return Err(ERROR_INVALID_ROLE);
+        }
+        Ok(())
+    }
+
+    pub fn grant(player_id: &[u64; 2], roles: u64, counter: u64) -> Result<(), u32> {
+        Self::validate_roles(roles)?;
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let current = kvpair.get(&Self::role_key(player_id)).first().copied().unwrap_or(0);
+        Self::store_roles(player_id, current | roles);
+        Self::emit_role_event(player_id, current | roles, counter);
+        Ok(())
+    }
+
+    pub fn revoke(player_id: &[u64; 2], roles: u64, counter: u64) -> Result<(), u32> {
+        Self::validate_roles(roles)?;
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let current = kvpair.get(&Self::role_key(player_id)).first().copied().unwrap_or(0);
+        Self::store_roles(player_id, current & !roles);
+        Self::emit_role_event(player_id, current & !roles, counter);
+        Ok(())
+    }
+
+    // Hands the super-admin role to another player; the old key loses it immediately
+    pub fn rotate_super_admin(new_admin: &[u64; 2], counter: u64) -> Result<(), u32> {
+        if *new_admin == [0, 0] {
+            return Err(ERROR_INVALID_ROLE);
+        }
+        crate::journal::set(&Self::SUPER_ADMIN_KEY, new_admin);
+        Self::emit_role_event(new_admin, ROLE_SUPER_ADMIN, counter);
+        Ok(())
+    }
+
+    fn emit_role_event(player_id: &[u64; 2], roles: u64, counter: u64) {
+        let mut data = vec![player_id[0], player_id[1], roles, counter];
+        insert_event(EVENT_ROLE_UPDATE, &mut data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_roles() {
+        assert!(RoleManager::validate_roles(ROLE_RESOLVER | ROLE_TREASURER).is_ok());
+        assert_eq!(RoleManager::validate_roles(0), Err(ERROR_INVALID_ROLE));
+        assert_eq!(RoleManager::validate_roles(ROLE_SUPER_ADMIN), Err(ERROR_INVALID_ROLE));
+        assert_eq!(RoleManager::validate_roles(1 << 10), Err(ERROR_INVALID_ROLE));
+    }
+
+    #[test]
+    fn test_rotate_then_grant_and_revoke() {
+        let _guard = crate::test_support::lock();
+        let new_admin = [21, 22];
+        RoleManager::rotate_super_admin(&new_admin, 0).unwrap();
+        assert_eq!(RoleManager::super_admin(), new_admin);
+        assert!(RoleManager::has_role(&new_admin, ROLE_SUPER_ADMIN | ROLE_TREASURER));
+        assert_eq!(RoleManager::rotate_super_admin(&[0, 0], 0), Err(ERROR_INVALID_ROLE));
+
+        let operator = [11, 12];
+        RoleManager::grant(&operator, ROLE_RESOLVER | ROLE_DEPOSITOR, 0).unwrap();
+        assert!(RoleManager::has_role(&operator, ROLE_RESOLVER));
+        assert!(!RoleManager::has_role(&operator, ROLE_TREASURER));
+        RoleManager::revoke(&operator, ROLE_RESOLVER, 0).unwrap();
+        assert!(!RoleManager::has_role(&operator, ROLE_RESOLVER));
+        assert!(RoleManager::has_role(&operator, ROLE_DEPOSITOR));
+        assert!(!RoleManager::has_role(&operator, ROLE_SUPER_ADMIN));
+
+        // Hand the role back so other tests still see the deployed admin
+        let admin = Player::pkey_to_pid(&ADMIN_PUBKEY);
+        RoleManager::rotate_super_admin(&admin, 0).unwrap();
+        assert_eq!(RoleManager::super_admin(), admin);
+        assert!(!RoleManager::has_role(&new_admin, ROLE_SUPER_ADMIN));
+    }
+}
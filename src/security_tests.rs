@@ -510,4 +510,65 @@ mod market_safe_tests {
         }
         // If prices can't be calculated, test passes (approximation limitation)
     }
-} 
\ No newline at end of file
+} 
+#[cfg(test)]
+mod access_control_tests {
+    use crate::error::ERROR_UNAUTHORIZED;
+    use crate::roles::{RoleManager, ROLE_RESOLVER};
+    use crate::state::{GlobalState, Transaction};
+    use crate::test_support;
+
+    const NON_ADMIN_PKEY: [u64; 4] = [0, 901, 902, 0];
+
+    fn command(id: u64, args: &[u64]) -> Vec<u64> {
+        let mut params = vec![id];
+        params.extend_from_slice(args);
+        params
+    }
+
+    fn privileged_commands() -> Vec<Vec<u64>> {
+        vec![
+            command(0, &[]),                             // TICK
+            command(3, &[1, 2, 0, 100, 9, 9, 9, 9]),     // DEPOSIT
+            command(6, &[1, 1]),                         // RESOLVE
+            command(8, &[1]),                            // WITHDRAW_FEES
+            command(9, &[0, 0, 100, 0, 1000, 1000]),     // CREATE_MARKET
+            command(13, &[1]),                           // CANCEL_WITHDRAWAL
+            command(14, &[0, 200]),                      // SET_PARAM
+            command(15, &[1000]),                        // FUND_FAUCET
+            command(17, &[901, 902, ROLE_RESOLVER]),     // GRANT_ROLE
+            command(18, &[901, 902, ROLE_RESOLVER]),     // REVOKE_ROLE
+            command(19, &[901, 902]),                    // ROTATE_ADMIN
+            command(27, &[0, 1000]),                     // FUND_PARLAY_BANKROLL
+            command(28, &[0, 0, 100, 1000, 1000, 10_000, 1, 1]), // CREATE_CONDITIONAL_MARKET
+            command(29, &[720, 5, 1000, 1000, 10_000, 250, 0, 1, 2, 3, 4]), // CREATE_SERIES
+            command(30, &[1]),                           // STOP_SERIES
+        ]
+    }
+
+    #[test]
+    fn test_privileged_commands_reject_non_admin() {
+        let _guard = test_support::lock();
+        for params in privileged_commands() {
+            let counter = GlobalState::get_counter();
+            let result = Transaction::decode(&params).process(&NON_ADMIN_PKEY, &[0; 4]);
+            assert_eq!(result[0], ERROR_UNAUTHORIZED as u64, "command {}", params[0]);
+            assert_eq!(GlobalState::get_counter(), counter);
+        }
+    }
+
+    #[test]
+    fn test_granted_role_authorizes_only_its_commands() {
+        let _guard = test_support::lock();
+        let resolver_pkey = [0, 911, 912, 0];
+        RoleManager::grant(&[911, 912], ROLE_RESOLVER, 0).unwrap();
+        assert!(Transaction::decode(&command(6, &[1, 1])).authorize(&resolver_pkey).is_ok());
+        assert_eq!(
+            Transaction::decode(&command(8, &[1])).authorize(&resolver_pkey),
+            Err(ERROR_UNAUTHORIZED)
+        );
+        // Player commands need no role
+        assert!(Transaction::decode(&command(4, &[1, 1, 100])).authorize(&resolver_pkey).is_ok());
+        RoleManager::revoke(&[911, 912], ROLE_RESOLVER, 0).unwrap();
+    }
+}
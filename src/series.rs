@@ -0,0 +1,287 @@
+use serde::Serialize;
+use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
+use crate::config::{MarketTemplate, MARKET_FEE_PROTOCOL, MAX_ACTIVE_SERIES, MIN_SERIES_DURATION};
+use crate::error::*;
+use crate::event::{insert_event, EVENT_SERIES_UPDATE};
+use crate::market::MarketData;
+use crate::math_safe::{safe_add, validate_token};
+use crate::params::{ProtocolParams, PARAM_PLATFORM_FEE_RATE};
+use crate::state::{MarketManager, GLOBAL_STATE};
+
+// Series event status codes
+pub const SERIES_CREATED: u64 = 0;
+pub const SERIES_INSTANCE: u64 = 1; // next market of the series was instantiated
+pub const SERIES_STOPPED: u64 = 2;
+pub const SERIES_FAILED: u64 = 3;   // the template no longer instantiates, the series was stopped
+
+impl StorageData for MarketTemplate {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        MarketTemplate {
+            duration: *u64data.next().unwrap(),
+            resolution_delay: *u64data.next().unwrap(),
+            initial_yes_liquidity: *u64data.next().unwrap(),
+            initial_no_liquidity: *u64data.next().unwrap(),
+            b: *u64data.next().unwrap(),
+            fee_rate: *u64data.next().unwrap(),
+            token: *u64data.next().unwrap(),
+            metadata_hash: [
+                *u64data.next().unwrap(),
+                *u64data.next().unwrap(),
+                *u64data.next().unwrap(),
+                *u64data.next().unwrap(),
+            ],
+        }
+    }
+
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.duration);
+        data.push(self.resolution_delay);
+        data.push(self.initial_yes_liquidity);
+        data.push(self.initial_no_liquidity);
+        data.push(self.b);
+        data.push(self.fee_rate);
+        data.push(self.token);
+        data.extend(self.metadata_hash.iter().cloned());
+    }
+}
+
+impl MarketTemplate {
+    // Builds the LMSR market of one instance trading from `start_time`
+    pub fn instantiate(&self, series_id: u64, start_time: u64) -> Result<MarketData, u32> {
+        validate_token(self.token)?;
+        if self.duration < MIN_SERIES_DURATION {
+            return Err(ERROR_INVALID_MARKET_TIME);
+        }
+        let (_, max_fee_rate) = ProtocolParams::range(PARAM_PLATFORM_FEE_RATE)?;
+        if self.fee_rate != MARKET_FEE_PROTOCOL && self.fee_rate > max_fee_rate {
+            return Err(ERROR_INVALID_PARAM);
+        }
+        let end_time = safe_add(start_time, self.duration)?;
+        let mut market = MarketData::new_with_liquidity(
+            start_time,
+            end_time,
+            safe_add(end_time, self.resolution_delay)?,
+            self.initial_yes_liquidity,
+            self.initial_no_liquidity,
+            self.b
+        )?;
+        market.token = self.token;
+        market.series_id = series_id;
+        market.fee_rate = self.fee_rate;
+        Ok(market)
+    }
+}
+
+// A question asked again and again: whenever the current instance stops
+// trading, the next tick opens a new market from the template
+#[derive(Serialize, Clone, Debug)]
+pub struct MarketSeries {
+    pub template: MarketTemplate,
+    pub current_market_id: u64,
+    pub instances: u64, // markets created so far
+    pub active: bool,   // false once stopped, the last instance runs to completion
+}
+
+impl StorageData for MarketSeries {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        MarketSeries {
+            template: MarketTemplate::from_data(u64data),
+            current_market_id: *u64data.next().unwrap(),
+            instances: *u64data.next().unwrap(),
+            active: *u64data.next().unwrap() != 0,
+        }
+    }
+
+    fn to_data(&self, data: &mut Vec<u64>) {
+        self.template.to_data(data);
+        data.push(self.current_market_id);
+        data.push(self.instances);
+        data.push(if self.active { 1 } else { 0 });
+    }
+}
+
+pub struct SeriesManager;
+
+impl SeriesManager {
+    const SERIES_PREFIX: [u64; 2] = [17, 0]; // Prefix for market series storage
+
+    pub fn get_series(id: u64) -> Option<MarketSeries> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let mut data = kvpair.get(&[Self::SERIES_PREFIX[0], Self::SERIES_PREFIX[1], id, 0]);
+        if !data.is_empty() {
+            let mut u64data = data.iter_mut();
+            Some(MarketSeries::from_data(&mut u64data))
+        } else {
+            None
+        }
+    }
+
+    fn store_series(id: u64, series: &MarketSeries) {
+        let mut data = vec![];
+        series.to_data(&mut data);
+        crate::journal::set(&[Self::SERIES_PREFIX[0], Self::SERIES_PREFIX[1], id, 0], data.as_slice());
+    }
+
+    // Registers the series and opens its first market right away
+    pub fn create(template: &MarketTemplate, counter: u64) -> Result<u64, u32> {
+        let id = {
+            let global_state = GLOBAL_STATE.0.borrow();
+            if global_state.series.len() as u64 >= MAX_ACTIVE_SERIES {
+                return Err(ERROR_TOO_MANY_SERIES);
+            }
+            global_state.next_series_id
+        };
+        let market = template.instantiate(id, counter)?;
+        {
+            let mut global_state = GLOBAL_STATE.0.borrow_mut();
+            global_state.next_series_id += 1;
+            global_state.series.push(id);
+        }
+        let series = MarketSeries {
+            template: *template,
+            current_market_id: MarketManager::insert_market(&market),
+            instances: 1,
+            active: true,
+        };
+        Self::store_series(id, &series);
+        Self::emit_series_event(SERIES_CREATED, id, &series, counter);
+        Ok(id)
+    }
+
+    // Stops instantiating new markets; the current one is left untouched
+    pub fn stop(id: u64, counter: u64) -> Result<(), u32> {
+        let mut series = Self::get_series(id).ok_or(ERROR_SERIES_NOT_FOUND)?;
+        if !series.active {
+            return Err(ERROR_SERIES_NOT_FOUND);
+        }
+        Self::deactivate(id, &mut series, SERIES_STOPPED, counter);
+        Ok(())
+    }
+
+    fn deactivate(id: u64, series: &mut MarketSeries, status: u64, counter: u64) {
+        series.active = false;
+        Self::store_series(id, series);
+        GLOBAL_STATE.0.borrow_mut().series.retain(|series_id| *series_id != id);
+        Self::emit_series_event(status, id, series, counter);
+    }
+
+    // Called from tick: opens the next instance of every series whose current
+    // market has stopped trading
+    pub fn roll(counter: u64) {
+        let series_ids = GLOBAL_STATE.0.borrow().series.clone();
+        for id in series_ids {
+            // A series whose template no longer instantiates would fail on every
+            // tick, so it is stopped instead of retried
+            if crate::journal::atomic(|| Self::roll_series(id, counter)).is_err() {
+                if let Some(mut series) = Self::get_series(id) {
+                    Self::deactivate(id, &mut series, SERIES_FAILED, counter);
+                }
+            }
+        }
+    }
+
+    fn roll_series(id: u64, counter: u64) -> Result<(), u32> {
+        let mut series = Self::get_series(id).ok_or(ERROR_SERIES_NOT_FOUND)?;
+        let closed = MarketManager::get_market(series.current_market_id)
+            .is_none_or(|market| counter >= market.end_time);
+        if !closed {
+            return Ok(());
+        }
+        let market = series.template.instantiate(id, counter)?;
+        series.current_market_id = MarketManager::insert_market(&market);
+        series.instances = safe_add(series.instances, 1)?;
+        Self::store_series(id, &series);
+        Self::emit_series_event(SERIES_INSTANCE, id, &series, counter);
+        Ok(())
+    }
+
+    fn emit_series_event(status: u64, id: u64, series: &MarketSeries, counter: u64) {
+        let mut data = vec![
+            status,
+            id,
+            series.current_market_id,
+            series.instances,
+            counter,
+        ];
+        series.template.to_data(&mut data);
+        insert_event(EVENT_SERIES_UPDATE, &mut data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DEFAULT_MARKET;
+    use crate::params::PARAM_MIN_LIQUIDITY;
+    use crate::test_support;
+
+    fn template() -> MarketTemplate {
+        MarketTemplate {
+            duration: MIN_SERIES_DURATION,
+            resolution_delay: 5,
+            initial_yes_liquidity: 1_000,
+            initial_no_liquidity: 1_000,
+            b: 10_000,
+            fee_rate: 250,
+            token: 2,
+            metadata_hash: [1, 2, 3, 4],
+        }
+    }
+
+    #[test]
+    fn test_series_rolls_over_when_instance_closes() {
+        let _guard = test_support::lock();
+        let template = template();
+        let duration = MIN_SERIES_DURATION;
+        let counter = GLOBAL_STATE.0.borrow().counter;
+        let id = SeriesManager::create(&template, counter).unwrap();
+        let first = SeriesManager::get_series(id).unwrap().current_market_id;
+        let market = MarketManager::get_market(first).unwrap();
+        assert_eq!((market.end_time, market.resolution_time), (counter + duration, counter + duration + 5));
+        assert_eq!((market.series_id, market.fee_rate, market.token), (id, 250, 2));
+        assert_eq!(market.fee(1_000), Ok(25));
+
+        // Still trading: nothing happens
+        SeriesManager::roll(counter + duration - 1);
+        assert_eq!(SeriesManager::get_series(id).unwrap().current_market_id, first);
+
+        SeriesManager::roll(counter + duration);
+        let series = SeriesManager::get_series(id).unwrap();
+        assert_eq!(series.instances, 2);
+        assert_ne!(series.current_market_id, first);
+        let next = MarketManager::get_market(series.current_market_id).unwrap();
+        assert_eq!((next.start_time, next.end_time, next.series_id), (counter + duration, counter + 2 * duration, id));
+
+        SeriesManager::stop(id, counter + duration).unwrap();
+        SeriesManager::roll(counter + 2 * duration);
+        assert_eq!(SeriesManager::get_series(id).unwrap().instances, 2);
+        assert_eq!(SeriesManager::stop(id, counter + 2 * duration), Err(ERROR_SERIES_NOT_FOUND));
+    }
+
+    #[test]
+    fn test_series_rejects_invalid_template() {
+        let _guard = test_support::lock();
+        let template = MarketTemplate { fee_rate: 5_000, ..DEFAULT_MARKET };
+        assert_eq!(SeriesManager::create(&template, 0), Err(ERROR_INVALID_PARAM));
+        let template = MarketTemplate { duration: MIN_SERIES_DURATION - 1, ..DEFAULT_MARKET };
+        assert_eq!(SeriesManager::create(&template, 0), Err(ERROR_INVALID_MARKET_TIME));
+    }
+
+    #[test]
+    fn test_series_stops_when_template_fails() {
+        let _guard = test_support::lock();
+        let counter = GLOBAL_STATE.0.borrow().counter;
+        let id = SeriesManager::create(&template(), counter).unwrap();
+
+        // The template's liquidity falls below a raised minimum
+        let min_liquidity = ProtocolParams::get().min_liquidity;
+        ProtocolParams::update(PARAM_MIN_LIQUIDITY, 2_000).unwrap();
+        SeriesManager::roll(counter + MIN_SERIES_DURATION);
+        ProtocolParams::update(PARAM_MIN_LIQUIDITY, min_liquidity).unwrap();
+
+        let series = SeriesManager::get_series(id).unwrap();
+        assert!(!series.active);
+        assert_eq!(series.instances, 1);
+        assert!(!GLOBAL_STATE.0.borrow().series.contains(&id));
+    }
+}
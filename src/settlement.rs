@@ -7,6 +7,10 @@ impl SettlementInfo {
     pub fn append_settlement(info: WithdrawInfo) {
         unsafe { SETTLEMENT.0.push(info) };
     }
+    // Drops settlements appended after `len`, used when a journal rolls back
+    pub fn truncate(len: usize) {
+        unsafe { SETTLEMENT.0.truncate(len) };
+    }
     pub fn settlement_size() -> usize {
         let sinfo = unsafe { &mut SETTLEMENT };
         return sinfo.0.len()
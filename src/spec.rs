@@ -0,0 +1,433 @@
+use crate::command::{Command, Activity, Withdraw, Deposit};
+use crate::config::{MAX_COLLATERAL_TOKENS, MAX_BATCH_COMMANDS, MAX_PARLAY_LEGS, MarketTemplate};
+use crate::error::DecodeError;
+use crate::market::{MARKET_KIND_LMSR, MARKET_KIND_CLOB};
+
+// Command ids (low byte of the header word)
+pub const TICK: u64 = 0;
+pub const INSTALL_PLAYER: u64 = 1;
+pub const WITHDRAW: u64 = 2;
+pub const DEPOSIT: u64 = 3;
+pub const BET: u64 = 4;
+pub const SELL: u64 = 5;
+pub const RESOLVE: u64 = 6;
+pub const CLAIM: u64 = 7;
+pub const WITHDRAW_FEES: u64 = 8;
+pub const CREATE_MARKET: u64 = 9;
+pub const CLAIM_ALL: u64 = 10;
+pub const TRANSFER_SHARES: u64 = 11;
+pub const TRANSFER: u64 = 12;
+pub const CANCEL_WITHDRAWAL: u64 = 13;
+pub const SET_PARAM: u64 = 14;
+pub const FUND_FAUCET: u64 = 15;
+pub const CLAIM_FAUCET: u64 = 16;
+pub const GRANT_ROLE: u64 = 17;
+pub const REVOKE_ROLE: u64 = 18;
+pub const ROTATE_ADMIN: u64 = 19;
+pub const BATCH: u64 = 20;
+pub const PLACE_LIMIT_ORDER: u64 = 21;
+pub const CANCEL_ORDER: u64 = 22;
+pub const PLACE_TRIGGER: u64 = 23;
+pub const CANCEL_TRIGGER: u64 = 24;
+pub const PLACE_PARLAY: u64 = 25;
+pub const CLAIM_PARLAY: u64 = 26;
+pub const FUND_PARLAY_BANKROLL: u64 = 27;
+pub const CREATE_CONDITIONAL_MARKET: u64 = 28;
+pub const CREATE_SERIES: u64 = 29;
+pub const STOP_SERIES: u64 = 30;
+
+// Reserved id of the extended encoding, never a command of its own
+pub const EXTENDED: u64 = 0xff;
+
+// Header word: bits 0-7 command id, bits 8-15 word count (written by the RPC layer,
+// not read here), bits 16.. nonce. Positional args follow the header.
+//
+// Extended encoding: the header carries EXTENDED, then come a word holding the
+// command id (bits 0-7) and encoding version (bits 8-15), a flags word, the
+// positional args and one word per set flag in ascending bit order.
+pub const VERSION_EXTENDED: u64 = 1;
+
+// Extension flags
+pub const EXT_VALID_UNTIL: u64 = 1; // last counter at which the command may still execute
+
+// Trading commands that accept a deadline
+const TRADING_EXTENSIONS: u64 = EXT_VALID_UNTIL;
+
+// Optional trailing args a command accepts in the extended encoding
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Extensions(Vec<(u64, u64)>); // (flag, value)
+
+impl Extensions {
+    pub fn get(&self, flag: u64) -> Option<u64> {
+        self.0.iter().find(|(f, _)| *f == flag).map(|(_, v)| *v)
+    }
+}
+
+// Positional args of a single command. Indices are positions in the legacy
+// layout (1 = first arg after the header) whatever the encoding version.
+pub struct Params<'a> {
+    pub command: u64,
+    args: &'a [u64],
+}
+
+impl Params<'_> {
+    pub fn arg_count(&self) -> usize {
+        self.args.len()
+    }
+
+    pub fn get(&self, index: usize) -> u64 {
+        self.args[index - 1]
+    }
+
+    pub fn get_or(&self, index: usize, default: u64) -> u64 {
+        self.args.get(index - 1).copied().unwrap_or(default)
+    }
+
+    pub fn pid(&self, index: usize) -> [u64; 2] {
+        [self.get(index), self.get(index + 1)]
+    }
+
+    // 0/1 fields such as bet side or resolution outcome
+    pub fn flag(&self, index: usize) -> Result<u64, DecodeError> {
+        self.bounded(index, self.get(index), 1)
+    }
+
+    pub fn token(&self, index: usize) -> Result<u64, DecodeError> {
+        self.bounded(index, self.get_or(index, 0), MAX_COLLATERAL_TOKENS - 1)
+    }
+
+    fn bounded(&self, index: usize, value: u64, max: u64) -> Result<u64, DecodeError> {
+        if value > max {
+            return Err(DecodeError::OutOfRange { command: self.command, index });
+        }
+        Ok(value)
+    }
+}
+
+// Declarative description of one command; `decode` is driven entirely by this table
+pub struct CommandSpec {
+    pub id: u64,
+    pub name: &'static str,
+    pub arities: &'static [usize], // accepted positional arg counts, header excluded; empty = checked by `build`
+    pub extensions: u64,           // extension flags the command understands
+    pub build: fn(&Params) -> Result<Command, DecodeError>,
+}
+
+pub const COMMAND_SPECS: &[CommandSpec] = &[
+    CommandSpec { id: TICK, name: "tick", arities: &[0], extensions: 0,
+        build: |_| Ok(Command::Tick) },
+    CommandSpec { id: INSTALL_PLAYER, name: "install_player", arities: &[0], extensions: 0,
+        build: |_| Ok(Command::InstallPlayer) },
+    // 4 args: legacy packed layout [token, amount | address_high << 32, address_mid, address_low]
+    // 5 args: [token, amount, address_high, address_mid, address_low] with a full 64-bit amount
+    CommandSpec { id: WITHDRAW, name: "withdraw", arities: &[4, 5], extensions: 0,
+        build: |p| {
+            let token = p.token(1)?;
+            if p.arg_count() == 4 {
+                Ok(Command::Withdraw(Withdraw::from_packed(token, [p.get(2), p.get(3), p.get(4)])))
+            } else {
+                Ok(Command::Withdraw(Withdraw { token, amount: p.get(2), address: [p.get(3), p.get(4), p.get(5)] }))
+            }
+        } },
+    // [pid_1, pid_2, token, amount] followed by the L1 tx hash (4 words) for replay protection
+    CommandSpec { id: DEPOSIT, name: "deposit", arities: &[8], extensions: 0,
+        build: |p| Ok(Command::Deposit(Deposit {
+            token: p.token(3)?,
+            data: [p.get(1), p.get(2), p.get(4)],
+            tx_hash: [p.get(5), p.get(6), p.get(7), p.get(8)],
+        })) },
+    CommandSpec { id: BET, name: "bet", arities: &[3], extensions: TRADING_EXTENSIONS,
+        build: |p| Ok(Command::Activity(Activity::Bet(p.get(1), p.flag(2)?, p.get(3)))) },
+    CommandSpec { id: SELL, name: "sell", arities: &[3], extensions: TRADING_EXTENSIONS,
+        build: |p| Ok(Command::Activity(Activity::Sell(p.get(1), p.flag(2)?, p.get(3)))) },
+    CommandSpec { id: RESOLVE, name: "resolve", arities: &[2], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::Resolve(p.get(1), p.flag(2)?))) },
+    CommandSpec { id: CLAIM, name: "claim", arities: &[1], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::Claim(p.get(1)))) },
+    CommandSpec { id: WITHDRAW_FEES, name: "withdraw_fees", arities: &[1], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::WithdrawFees(p.get(1)))) },
+    // start, end, resolution offsets, yes/no liquidity, b, optional collateral token, optional market kind
+    CommandSpec { id: CREATE_MARKET, name: "create_market", arities: &[6, 7, 8], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::CreateMarket(
+            p.get(1), p.get(2), p.get(3), p.get(4), p.get(5), p.get(6), p.token(7)?,
+            p.bounded(8, p.get_or(8, MARKET_KIND_LMSR), MARKET_KIND_CLOB)?))) },
+    CommandSpec { id: CLAIM_ALL, name: "claim_all", arities: &[0], extensions: 0,
+        build: |_| Ok(Command::Activity(Activity::ClaimAll)) },
+    CommandSpec { id: TRANSFER_SHARES, name: "transfer_shares", arities: &[5], extensions: TRADING_EXTENSIONS,
+        build: |p| Ok(Command::Activity(Activity::TransferShares(p.get(1), p.flag(2)?, p.get(3), p.pid(4)))) },
+    CommandSpec { id: TRANSFER, name: "transfer", arities: &[3, 4], extensions: TRADING_EXTENSIONS,
+        build: |p| Ok(Command::Activity(Activity::Transfer(p.pid(1), p.get(3), p.token(4)?))) },
+    CommandSpec { id: CANCEL_WITHDRAWAL, name: "cancel_withdrawal", arities: &[1], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::CancelWithdrawal(p.get(1)))) },
+    CommandSpec { id: SET_PARAM, name: "set_param", arities: &[2], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::SetParam(p.get(1), p.get(2)))) },
+    CommandSpec { id: FUND_FAUCET, name: "fund_faucet", arities: &[1], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::FundFaucet(p.get(1)))) },
+    CommandSpec { id: CLAIM_FAUCET, name: "claim_faucet", arities: &[0], extensions: 0,
+        build: |_| Ok(Command::Activity(Activity::ClaimFaucet)) },
+    CommandSpec { id: GRANT_ROLE, name: "grant_role", arities: &[3], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::GrantRole(p.pid(1), p.get(3)))) },
+    CommandSpec { id: REVOKE_ROLE, name: "revoke_role", arities: &[3], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::RevokeRole(p.pid(1), p.get(3)))) },
+    CommandSpec { id: ROTATE_ADMIN, name: "rotate_admin", arities: &[2], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::RotateAdmin(p.pid(1)))) },
+    // [count, then per sub-command: (command id | arg count << 8), args...]
+    CommandSpec { id: BATCH, name: "batch", arities: &[], extensions: TRADING_EXTENSIONS,
+        build: decode_batch },
+    // market_id, side, limit price (PRICE_PRECISION units), amount
+    CommandSpec { id: PLACE_LIMIT_ORDER, name: "place_limit_order", arities: &[4], extensions: TRADING_EXTENSIONS,
+        build: |p| Ok(Command::Activity(Activity::PlaceLimitOrder(p.get(1), p.flag(2)?, p.get(3), p.get(4)))) },
+    CommandSpec { id: CANCEL_ORDER, name: "cancel_order", arities: &[1], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::CancelOrder(p.get(1)))) },
+    // market_id, side, kind (0 = stop-loss, 1 = take-profit), trigger price, shares
+    CommandSpec { id: PLACE_TRIGGER, name: "place_trigger", arities: &[5], extensions: TRADING_EXTENSIONS,
+        build: |p| Ok(Command::Activity(Activity::PlaceTrigger(p.get(1), p.flag(2)?, p.flag(3)?, p.get(4), p.get(5)))) },
+    CommandSpec { id: CANCEL_TRIGGER, name: "cancel_trigger", arities: &[1], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::CancelTrigger(p.get(1)))) },
+    // [stake, then per leg: market_id, side]
+    CommandSpec { id: PLACE_PARLAY, name: "place_parlay", arities: &[], extensions: TRADING_EXTENSIONS,
+        build: decode_parlay },
+    // owner pid, parlay id
+    CommandSpec { id: CLAIM_PARLAY, name: "claim_parlay", arities: &[3], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::ClaimParlay(p.pid(1), p.get(3)))) },
+    CommandSpec { id: FUND_PARLAY_BANKROLL, name: "fund_parlay_bankroll", arities: &[2], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::FundParlayBankroll(p.token(1)?, p.get(2)))) },
+    // start, end, resolution offsets, yes/no liquidity, b, parent market id, required parent outcome
+    CommandSpec { id: CREATE_CONDITIONAL_MARKET, name: "create_conditional_market", arities: &[8], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::CreateConditionalMarket(
+            p.get(1), p.get(2), p.get(3), p.get(4), p.get(5), p.get(6), p.get(7), p.flag(8)?))) },
+    // duration, resolution delay, yes/no liquidity, b, fee rate (bps, u64::MAX = platform fee), token, metadata hash (4 words)
+    CommandSpec { id: CREATE_SERIES, name: "create_series", arities: &[11], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::CreateSeries(MarketTemplate {
+            duration: p.get(1),
+            resolution_delay: p.get(2),
+            initial_yes_liquidity: p.get(3),
+            initial_no_liquidity: p.get(4),
+            b: p.get(5),
+            fee_rate: p.get(6),
+            token: p.token(7)?,
+            metadata_hash: [p.get(8), p.get(9), p.get(10), p.get(11)],
+        }))) },
+    CommandSpec { id: STOP_SERIES, name: "stop_series", arities: &[1], extensions: 0,
+        build: |p| Ok(Command::Activity(Activity::StopSeries(p.get(1)))) },
+];
+
+fn decode_parlay(p: &Params) -> Result<Command, DecodeError> {
+    let leg_count = p.arg_count().saturating_sub(1) / 2;
+    if p.arg_count() % 2 == 0 || leg_count < 2 || leg_count as u64 > MAX_PARLAY_LEGS {
+        return Err(DecodeError::WrongArity { command: PLACE_PARLAY, len: p.arg_count() + 1 });
+    }
+    let mut legs = vec![];
+    for leg in 0..leg_count {
+        legs.push((p.get(2 + leg * 2), p.flag(3 + leg * 2)?));
+    }
+    Ok(Command::Activity(Activity::PlaceParlay(p.get(1), legs)))
+}
+
+// Player trading commands that may appear inside a batch
+const BATCHABLE: [u64; 6] = [BET, SELL, CLAIM, CLAIM_ALL, TRANSFER_SHARES, TRANSFER];
+
+fn decode_batch(p: &Params) -> Result<Command, DecodeError> {
+    let wrong_arity = DecodeError::WrongArity { command: BATCH, len: p.arg_count() + 1 };
+    if p.arg_count() == 0 {
+        return Err(wrong_arity);
+    }
+    let count = p.bounded(1, p.get(1), MAX_BATCH_COMMANDS)?;
+    if count == 0 {
+        return Err(DecodeError::OutOfRange { command: BATCH, index: 1 });
+    }
+
+    let mut activities = vec![];
+    let mut index = 2;
+    for _ in 0..count {
+        if index > p.arg_count() {
+            return Err(wrong_arity);
+        }
+        let sub_header = p.get(index);
+        let sub_command = sub_header & 0xff;
+        let sub_args = ((sub_header >> 8) & 0xff) as usize;
+        if !BATCHABLE.contains(&sub_command) {
+            return Err(DecodeError::OutOfRange { command: BATCH, index });
+        }
+        let spec = find_spec(sub_command).ok_or(DecodeError::UnknownCommand(sub_command))?;
+        if !spec.arities.contains(&sub_args) || index + sub_args > p.arg_count() {
+            return Err(DecodeError::WrongArity { command: sub_command, len: sub_args + 1 });
+        }
+        let args = &p.args[index..index + sub_args];
+        match (spec.build)(&Params { command: sub_command, args })? {
+            Command::Activity(activity) => activities.push(activity),
+            _ => return Err(DecodeError::OutOfRange { command: BATCH, index }),
+        }
+        index += sub_args + 1;
+    }
+    if index != p.arg_count() + 1 {
+        return Err(wrong_arity);
+    }
+    Ok(Command::Activity(Activity::Batch(activities)))
+}
+
+pub fn find_spec(command: u64) -> Option<&'static CommandSpec> {
+    COMMAND_SPECS.iter().find(|spec| spec.id == command)
+}
+
+pub fn decode(params: &[u64]) -> Result<(Command, Extensions), DecodeError> {
+    let header = *params.first().ok_or(DecodeError::WrongArity { command: 0, len: 0 })?;
+    if header & 0xff == EXTENDED {
+        return decode_extended(params);
+    }
+    decode_body(header & 0xff, 0, &params[1..], params.len())
+}
+
+fn decode_extended(params: &[u64]) -> Result<(Command, Extensions), DecodeError> {
+    let wrong_arity = DecodeError::WrongArity { command: EXTENDED, len: params.len() };
+    let (tag, flags) = match params {
+        [_, tag, flags, ..] => (*tag, *flags),
+        _ => return Err(wrong_arity),
+    };
+    let version = (tag >> 8) & 0xff;
+    if version != VERSION_EXTENDED {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    decode_body(tag & 0xff, flags, &params[3..], params.len())
+}
+
+// `len` is the length of the whole transaction, for error reports
+fn decode_body(command: u64, flags: u64, body: &[u64], len: usize) -> Result<(Command, Extensions), DecodeError> {
+    let spec = find_spec(command).ok_or(DecodeError::UnknownCommand(command))?;
+    let wrong_arity = DecodeError::WrongArity { command, len };
+    if flags & !spec.extensions != 0 {
+        return Err(DecodeError::UnsupportedExtension { command, flags });
+    }
+
+    let ext_count = flags.count_ones() as usize;
+    if body.len() < ext_count {
+        return Err(wrong_arity);
+    }
+    let (args, ext_values) = body.split_at(body.len() - ext_count);
+    if !spec.arities.is_empty() && !spec.arities.contains(&args.len()) {
+        return Err(wrong_arity);
+    }
+
+    let set_flags = (0..64).map(|bit| 1u64 << bit).filter(|flag| flags & flag != 0);
+    let extensions = Extensions(set_flags.zip(ext_values.iter().copied()).collect());
+    let cmd = (spec.build)(&Params { command, args })?;
+    Ok((cmd, extensions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Header as written by zkwasm-minirollup-rpc createCommand
+    fn header(command: u64, param_count: u64) -> u64 {
+        command | ((param_count + 1) << 8) | (3 << 16)
+    }
+
+    // Extended encoding of `command` with `flags`, args and extension values in `rest`
+    fn extended(command: u64, flags: u64, rest: &[u64]) -> Vec<u64> {
+        let mut params = vec![header(EXTENDED, rest.len() as u64 + 2), command | (VERSION_EXTENDED << 8), flags];
+        params.extend_from_slice(rest);
+        params
+    }
+
+    #[test]
+    fn test_spec_ids_are_unique() {
+        for (i, spec) in COMMAND_SPECS.iter().enumerate() {
+            assert!(COMMAND_SPECS[i + 1..].iter().all(|other| other.id != spec.id), "{}", spec.name);
+            assert!(spec.id < EXTENDED);
+        }
+    }
+
+    #[test]
+    fn test_decodes_rpc_command_words() {
+        // createCommand(nonce, BET, [market_id, side, amount])
+        let (cmd, ext) = decode(&[BET | (4 << 8) | (9 << 16), 2, 1, 700]).unwrap();
+        assert!(matches!(cmd, Command::Activity(Activity::Bet(2, 1, 700))));
+        assert_eq!(ext, Extensions::default());
+        let (cmd, _) = decode(&[header(CLAIM_ALL, 0)]).unwrap();
+        assert!(matches!(cmd, Command::Activity(Activity::ClaimAll)));
+        let (cmd, _) = decode(&[header(CREATE_SERIES, 11), 10, 5, 1, 1, 100, 250, 0, 1, 2, 3, 4]).unwrap();
+        assert!(matches!(cmd, Command::Activity(Activity::CreateSeries(_))));
+    }
+
+    #[test]
+    fn test_extended_encoding_matches_legacy() {
+        let extended_bet = decode(&extended(BET, 0, &[2, 1, 700])).unwrap();
+        assert!(matches!(extended_bet.0, Command::Activity(Activity::Bet(2, 1, 700))));
+        assert_eq!(extended_bet.1, Extensions::default());
+
+        assert_eq!(
+            decode(&extended(BET, 0, &[2, 1])).err(),
+            Some(DecodeError::WrongArity { command: BET, len: 5 })
+        );
+        assert_eq!(
+            decode(&[header(EXTENDED, 1), BET | (VERSION_EXTENDED << 8)]).err(),
+            Some(DecodeError::WrongArity { command: EXTENDED, len: 2 })
+        );
+        assert_eq!(decode(&extended(EXTENDED, 0, &[])).err(), Some(DecodeError::UnknownCommand(EXTENDED)));
+    }
+
+    #[test]
+    fn test_valid_until_extension() {
+        let (cmd, ext) = decode(&extended(SELL, EXT_VALID_UNTIL, &[2, 0, 50, 120])).unwrap();
+        assert!(matches!(cmd, Command::Activity(Activity::Sell(2, 0, 50))));
+        assert_eq!(ext.get(EXT_VALID_UNTIL), Some(120));
+
+        // Deadline word missing
+        assert_eq!(
+            decode(&extended(SELL, EXT_VALID_UNTIL, &[2, 0, 50])).err(),
+            Some(DecodeError::WrongArity { command: SELL, len: 6 })
+        );
+        // Only trading commands take a deadline
+        assert_eq!(
+            decode(&extended(CLAIM, EXT_VALID_UNTIL, &[2, 120])).err(),
+            Some(DecodeError::UnsupportedExtension { command: CLAIM, flags: EXT_VALID_UNTIL })
+        );
+    }
+
+    #[test]
+    fn test_decode_batch() {
+        let params = [BATCH, 2, BET | (3 << 8), 1, 1, 100, CLAIM | (1 << 8), 4];
+        match decode(&params).unwrap().0 {
+            Command::Activity(Activity::Batch(activities)) => {
+                assert_eq!(activities.len(), 2);
+                assert!(matches!(activities[0], Activity::Bet(1, 1, 100)));
+                assert!(matches!(activities[1], Activity::Claim(4)));
+            },
+            _ => panic!("expected batch"),
+        }
+
+        // Privileged or nested commands are not batchable
+        assert_eq!(
+            decode(&[BATCH, 1, RESOLVE | (2 << 8), 1, 1]).err(),
+            Some(DecodeError::OutOfRange { command: BATCH, index: 2 })
+        );
+        assert_eq!(
+            decode(&[BATCH, 1, BATCH | (1 << 8), 0]).err(),
+            Some(DecodeError::OutOfRange { command: BATCH, index: 2 })
+        );
+        // Count limits, sub-command arity and trailing words
+        assert_eq!(decode(&[BATCH, 0]).err(), Some(DecodeError::OutOfRange { command: BATCH, index: 1 }));
+        assert_eq!(
+            decode(&[BATCH, MAX_BATCH_COMMANDS + 1]).err(),
+            Some(DecodeError::OutOfRange { command: BATCH, index: 1 })
+        );
+        assert_eq!(
+            decode(&[BATCH, 1, BET | (2 << 8), 1, 1]).err(),
+            Some(DecodeError::WrongArity { command: BET, len: 3 })
+        );
+        assert_eq!(
+            decode(&[BATCH, 1, CLAIM | (1 << 8), 4, 9]).err(),
+            Some(DecodeError::WrongArity { command: BATCH, len: 5 })
+        );
+    }
+
+    #[test]
+    fn test_rejects_unknown_version_and_extensions() {
+        assert_eq!(decode(&[header(EXTENDED, 5), BET | (2 << 8), 0, 2, 1, 700]).err(), Some(DecodeError::UnsupportedVersion(2)));
+        assert_eq!(
+            decode(&extended(BET, 1 << 5, &[2, 1, 700, 9])).err(),
+            Some(DecodeError::UnsupportedExtension { command: BET, flags: 1 << 5 })
+        );
+    }
+}
@@ -2,7 +2,10 @@ use serde::Serialize;
 use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
 use std::cell::RefCell;
 use crate::market::MarketData;
-use crate::error::ERROR_MARKET_NOT_ACTIVE;
+use crate::math_safe::validate_token;
+use crate::error::{ERROR_MARKET_NOT_ACTIVE, ERROR_TRANSACTION_EXPIRED, ERROR_INVALID_CONDITION, DecodeError};
+use crate::config::{INITIAL_FAUCET_BUDGET, MAX_ORDER_FILLS_PER_TICK};
+use crate::spec::{Extensions, EXT_VALID_UNTIL};
 use crate::event::{emit_market_indexed_object, emit_liquidity_history};
 
 
@@ -21,6 +24,18 @@ pub struct GlobalState {
     pub total_players: u64,
     pub txsize: u64,
     pub txcounter: u64,
+    pub withdraw_epoch: u64,           // epoch of the global withdrawal rate-limit window
+    pub withdrawn_in_epoch: u64,       // total withdrawn during withdraw_epoch
+    pub next_withdrawal_id: u64,       // Auto-incrementing pending withdrawal ID
+    pub pending_withdrawals: Vec<u64>, // IDs of delayed withdrawals awaiting release
+    pub faucet_budget: u64,            // remaining funds the faucet can grant
+    pub next_order_id: u64,            // Auto-incrementing limit order ID
+    pub next_trigger_id: u64,          // Auto-incrementing stop-loss / take-profit ID
+    pub triggers: Vec<u64>,            // IDs of triggers waiting to fire
+    pub trigger_cursor: u64,           // trigger id the next tick resumes evaluating at
+    pub next_parlay_id: u64,           // Auto-incrementing parlay ID
+    pub next_series_id: u64,           // Auto-incrementing market series ID
+    pub series: Vec<u64>,              // IDs of series that keep instantiating markets
 }
 
 impl GlobalState {
@@ -32,12 +47,27 @@ impl GlobalState {
             total_players: 0,
             txsize: 0,
             txcounter: 0,
+            withdraw_epoch: 0,
+            withdrawn_in_epoch: 0,
+            next_withdrawal_id: 1,
+            pending_withdrawals: vec![],
+            faucet_budget: INITIAL_FAUCET_BUDGET,
+            next_order_id: 1,
+            next_trigger_id: 1,
+            triggers: vec![],
+            trigger_cursor: 0,
+            next_parlay_id: 1,
+            next_series_id: 1,
+            series: vec![],
         }
     }
 
+    // Global state plus the live protocol parameters clients quote trades with
     pub fn snapshot() -> String {
         let state = GLOBAL_STATE.0.borrow();
-        serde_json::to_string(&*state).unwrap()
+        let mut json = serde_json::to_value(&*state).unwrap();
+        json["params"] = serde_json::to_value(crate::params::ProtocolParams::get()).unwrap();
+        serde_json::to_string(&json).unwrap()
     }
 
     pub fn get_state(pid: Vec<u64>) -> String {
@@ -49,7 +79,7 @@ impl GlobalState {
     pub fn ensure_market_active(&self, market_id: u64) -> Result <u64, u32> {
         let current_time = self.counter;
         if let Some(market) = MarketManager::get_market(market_id) {
-            if !market.is_active(current_time) {
+            if !MarketManager::is_tradable(&market, current_time) {
                 return Err(ERROR_MARKET_NOT_ACTIVE);
             } else {
                 Ok(current_time)
@@ -63,8 +93,11 @@ impl GlobalState {
         let mut state = GLOBAL_STATE.0.borrow_mut();
         let counter = state.counter;
         let txsize = state.txsize;
-        let withdraw_size = crate::settlement::SettlementInfo::settlement_size();
-        if counter % 600 == 0 || txsize >= 40 || withdraw_size > 40 {
+        let withdraw_size = crate::settlement::SettlementInfo::settlement_size() as u64;
+        let params = crate::params::ProtocolParams::get();
+        if counter % params.preempt_tick_interval == 0
+            || txsize >= params.preempt_tx_threshold
+            || withdraw_size > params.preempt_withdraw_threshold {
             state.txsize = 0;
             return true;
         } else {
@@ -94,6 +127,7 @@ impl GlobalState {
             let mut u64data = data.iter_mut();
             *GLOBAL_STATE.0.borrow_mut() = Self::from_data(&mut u64data);
         }
+        crate::params::ProtocolParams::initialize();
     }
 
     pub fn get_counter() -> u64 {
@@ -117,6 +151,38 @@ impl StorageData for GlobalState {
             }
         }
         
+        // Withdrawal limit state is absent in snapshots taken before it was introduced
+        let withdraw_epoch = u64data.next().map_or(0, |v| *v);
+        let withdrawn_in_epoch = u64data.next().map_or(0, |v| *v);
+        let next_withdrawal_id = u64data.next().map_or(1, |v| *v);
+        let pending_count = u64data.next().map_or(0, |v| *v);
+        let mut pending_withdrawals = Vec::new();
+        for _ in 0..pending_count {
+            if let Some(id) = u64data.next() {
+                pending_withdrawals.push(*id);
+            }
+        }
+        let faucet_budget = u64data.next().map_or(INITIAL_FAUCET_BUDGET, |v| *v);
+        let next_order_id = u64data.next().map_or(1, |v| *v);
+        let next_trigger_id = u64data.next().map_or(1, |v| *v);
+        let trigger_count = u64data.next().map_or(0, |v| *v);
+        let mut triggers = Vec::new();
+        for _ in 0..trigger_count {
+            if let Some(id) = u64data.next() {
+                triggers.push(*id);
+            }
+        }
+        let trigger_cursor = u64data.next().map_or(0, |v| *v);
+        let next_parlay_id = u64data.next().map_or(1, |v| *v);
+        let next_series_id = u64data.next().map_or(1, |v| *v);
+        let series_count = u64data.next().map_or(0, |v| *v);
+        let mut series = Vec::new();
+        for _ in 0..series_count {
+            if let Some(id) = u64data.next() {
+                series.push(*id);
+            }
+        }
+
         GlobalState {
             counter,
             total_players,
@@ -124,6 +190,18 @@ impl StorageData for GlobalState {
             txcounter,
             next_market_id,
             market_ids,
+            withdraw_epoch,
+            withdrawn_in_epoch,
+            next_withdrawal_id,
+            pending_withdrawals,
+            faucet_budget,
+            next_order_id,
+            next_trigger_id,
+            triggers,
+            trigger_cursor,
+            next_parlay_id,
+            next_series_id,
+            series,
         }
     }
 
@@ -135,6 +213,21 @@ impl StorageData for GlobalState {
         data.push(self.next_market_id);
         data.push(self.market_ids.len() as u64);
         data.extend(self.market_ids.iter().cloned());
+        data.push(self.withdraw_epoch);
+        data.push(self.withdrawn_in_epoch);
+        data.push(self.next_withdrawal_id);
+        data.push(self.pending_withdrawals.len() as u64);
+        data.extend(self.pending_withdrawals.iter().cloned());
+        data.push(self.faucet_budget);
+        data.push(self.next_order_id);
+        data.push(self.next_trigger_id);
+        data.push(self.triggers.len() as u64);
+        data.extend(self.triggers.iter().cloned());
+        data.push(self.trigger_cursor);
+        data.push(self.next_parlay_id);
+        data.push(self.next_series_id);
+        data.push(self.series.len() as u64);
+        data.extend(self.series.iter().cloned());
     }
 }
 
@@ -146,19 +239,9 @@ lazy_static::lazy_static! {
 }
 
 // Transaction constants
-const TICK: u64 = 0;
-const INSTALL_PLAYER: u64 = 1;
-const WITHDRAW: u64 = 2;
-const DEPOSIT: u64 = 3;
-const BET: u64 = 4;
-const SELL: u64 = 5;
-const RESOLVE: u64 = 6;
-const CLAIM: u64 = 7;
-const WITHDRAW_FEES: u64 = 8;
-const CREATE_MARKET: u64 = 9;
-
 pub struct Transaction {
-    command: crate::command::Command,
+    command: Result<crate::command::Command, DecodeError>,
+    extensions: Extensions,
     nonce: u64,
 }
 
@@ -167,63 +250,27 @@ impl Transaction {
         crate::command::decode_error(e)
     }
 
+    // Malformed input is kept as a decode error and reported by `process`
     pub fn decode(params: &[u64]) -> Self {
-        use crate::command::{Command, Activity, Withdraw, Deposit};
-        use zkwasm_rest_abi::enforce;
-        
-        let command = params[0] & 0xff;
-        let nonce = params[0] >> 16;
-        
-        let command = if command == WITHDRAW {
-            enforce(params.len() == 5, "withdraw needs 5 params");
-            Command::Withdraw(Withdraw {
-                data: [params[2], params[3], params[4]]
-            })
-        } else if command == DEPOSIT {
-            enforce(params.len() == 5, "deposit needs 5 params");
-            enforce(params[3] == 0, "check deposit index"); // only token index 0 is supported
-            Command::Deposit(Deposit {
-                data: [params[1], params[2], params[4]]
-            })
-        } else if command == BET {
-            enforce(params.len() == 4, "bet needs 4 params");
-            Command::Activity(Activity::Bet(params[1], params[2], params[3]))
-        } else if command == SELL {
-            enforce(params.len() == 4, "sell needs 4 params");
-            Command::Activity(Activity::Sell(params[1], params[2], params[3]))
-        } else if command == RESOLVE {
-            enforce(params.len() == 3, "resolve needs 3 params");
-            Command::Activity(Activity::Resolve(params[1], params[2]))
-        } else if command == CLAIM {
-            enforce(params.len() == 2, "claim needs 2 params");
-            Command::Activity(Activity::Claim(params[1]))
-        } else if command == WITHDRAW_FEES {
-            enforce(params.len() == 2, "withdraw_fees needs 2 params");
-            Command::Activity(Activity::WithdrawFees(params[1]))
-        } else if command == CREATE_MARKET {
-            enforce(params.len() == 7, "create_market needs exactly 7 params");
-            // params[0] = command byte, params[1-6] = actual parameters
-            let start_time = params[1];
-            let end_time = params[2];
-            let resolution_time = params[3];
-            let yes_liquidity = params[4];
-            let no_liquidity = params[5];
-            let b = params[6];
-            Command::Activity(Activity::CreateMarket(start_time, end_time, resolution_time, yes_liquidity, no_liquidity, b))
-        } else if command == INSTALL_PLAYER {
-            Command::InstallPlayer
-        } else {
-            unsafe { zkwasm_rust_sdk::require(command == TICK) };
-            Command::Tick
-        };
-        
-        Transaction { command, nonce }
+        let nonce = params.first().map_or(0, |header| header >> 16);
+        match crate::spec::decode(params) {
+            Ok((command, extensions)) => Transaction { command: Ok(command), extensions, nonce },
+            Err(e) => Transaction { command: Err(e), extensions: Extensions::default(), nonce },
+        }
+    }
+
+    pub fn try_decode(params: &[u64]) -> Result<crate::command::Command, DecodeError> {
+        crate::spec::decode(params).map(|(command, _)| command)
+    }
+
+    // Optional argument supplied through the extended encoding
+    pub fn extension(&self, flag: u64) -> Option<u64> {
+        self.extensions.get(flag)
     }
 
     pub fn create_player(&self, pkey: &[u64; 4]) -> Result<(), u32> {
         use crate::player::Player;
         use crate::error::{ERROR_PLAYER_ALREADY_EXISTS};
-        use crate::config::NEW_PLAYER_INITIAL_BALANCE;
         
         let player_id = Player::pkey_to_pid(pkey);
         let player = Player::get_from_pid(&player_id);
@@ -231,8 +278,9 @@ impl Transaction {
             Some(_) => Err(ERROR_PLAYER_ALREADY_EXISTS), // Player already exists
             None => {
                 let mut player = Player::new_from_pid(player_id);
-                // Set initial balance for new player
-                player.data.balance = NEW_PLAYER_INITIAL_BALANCE;
+                // Initial balance comes out of the faucet budget and may be zero
+                let counter = GLOBAL_STATE.0.borrow().counter;
+                crate::faucet::FaucetManager::grant(&mut player, counter);
                 player.store();
                 Ok(())
             }
@@ -250,6 +298,30 @@ impl Transaction {
             (new_counter, market_ids)
         }; // global_state is dropped here
         
+        // Release delayed withdrawals that have served their waiting period
+        crate::withdrawal::WithdrawalManager::release_due(new_counter);
+
+        // Open the next market of every series whose current one has closed
+        crate::series::SeriesManager::roll(new_counter);
+
+        // Fire stop-loss / take-profit triggers before orders react to the new prices
+        crate::trigger::TriggerManager::evaluate(new_counter);
+
+        // Fill resting limit orders whose price was crossed, within a per tick budget
+        let mut fill_budget = MAX_ORDER_FILLS_PER_TICK;
+        for market_id in market_ids.iter() {
+            if fill_budget == 0 {
+                break;
+            }
+            // A market whose matching fails is skipped without partial fills
+            fill_budget -= crate::journal::atomic(|| {
+                crate::orderbook::OrderBook::match_orders(*market_id, fill_budget, new_counter)
+            }).unwrap_or(0);
+        }
+
+        // Checkpoint the price accumulators that TWAP queries read from
+        crate::twap::TwapOracle::observe_all(&market_ids, new_counter);
+
         // Emit shares history for each market at this counter
         // Note: Market IndexedObject events are emitted directly during operations (bet, sell, resolve)
         for market_id in market_ids {
@@ -262,6 +334,13 @@ impl Transaction {
                 );
             }
         }
+
+        // Markets that stopped trading leave the tick set; they stay reachable by id
+        // for resolution, claims and cancelling their resting orders
+        GLOBAL_STATE.0.borrow_mut().market_ids.retain(|market_id| {
+            MarketManager::get_market(*market_id)
+                .is_some_and(|market| new_counter < market.end_time && !market.resolved)
+        });
     }
 
     pub fn inc_tx_number(&self) {
@@ -270,50 +349,72 @@ impl Transaction {
         global_state.txcounter += 1;
     }
 
-    pub fn process(&self, pkey: &[u64; 4], rand: &[u64; 4]) -> Vec<u64> {
-        use crate::command::{Activity, CommandHandler};
+    // Checks that the signer may run this command; privileged commands need a role
+    pub fn authorize(&self, pkey: &[u64; 4]) -> Result<(), u32> {
+        use crate::command::Command;
+
+        let command = self.command.as_ref().map_err(|e| e.code())?;
         use crate::config::ADMIN_PUBKEY;
+        use crate::error::ERROR_UNAUTHORIZED;
+        use crate::player::Player;
+        use crate::roles::{RoleManager, ROLE_DEPOSITOR};
+
+        let pid = Player::pkey_to_pid(pkey);
+        let authorized = match command {
+            // Ticks are driven by the sequencer's deployed key, not by a role
+            Command::Tick => *pkey == *ADMIN_PUBKEY,
+            Command::Deposit(_) => RoleManager::has_role(&pid, ROLE_DEPOSITOR),
+            Command::Activity(cmd) => cmd.required_role()
+                .is_none_or(|role| RoleManager::has_role(&pid, role)),
+            Command::InstallPlayer | Command::Withdraw(_) => true,
+        };
+        if authorized {
+            Ok(())
+        } else {
+            Err(ERROR_UNAUTHORIZED)
+        }
+    }
+
+    // Rejects commands sequenced after their signed `valid_until` counter
+    pub fn check_deadline(&self, counter: u64) -> Result<(), u32> {
+        match self.extension(EXT_VALID_UNTIL) {
+            Some(valid_until) if counter > valid_until => Err(ERROR_TRANSACTION_EXPIRED),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn process(&self, pkey: &[u64; 4], rand: &[u64; 4]) -> Vec<u64> {
+        use crate::command::CommandHandler;
         use crate::event::clear_events;
         use crate::player::Player;
-        use zkwasm_rust_sdk::require;
         
         let pid = Player::pkey_to_pid(pkey);
         let counter = GLOBAL_STATE.0.borrow().counter;
         
-        let e = match &self.command {
-            crate::command::Command::Tick => {
-                unsafe { require(*pkey == *ADMIN_PUBKEY) };
+        // Decode errors are reported by authorize like any other rejection.
+        // A failing handler leaves no writes or events behind. The tick cannot fail
+        // as a whole; it journals each of its fallible steps on its own instead.
+        let e = match self.authorize(pkey).and_then(|_| self.check_deadline(counter)) {
+            Err(e) => e,
+            Ok(()) if matches!(self.command, Ok(crate::command::Command::Tick)) => {
                 self.tick();
                 0
             },
-            crate::command::Command::InstallPlayer => self.create_player(pkey)
-                .map_or_else(|e| e, |_| 0),
-            crate::command::Command::Withdraw(cmd) => cmd.handle(&pid, self.nonce, rand, counter)
-                .map_or_else(|e| e, |_| 0),
-            crate::command::Command::Activity(cmd) => {
-                // Check admin permissions for resolve, withdraw fees, and create market commands
-                if let Activity::Resolve(_, _) = cmd {
-                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
-                }
-                if let Activity::WithdrawFees(_) = cmd {
-                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
+            Ok(()) => crate::journal::atomic(|| {
+                crate::journal::track_player(&pid);
+                match self.command.as_ref().unwrap() {
+                    crate::command::Command::Tick => unreachable!(),
+                    crate::command::Command::InstallPlayer => self.create_player(pkey),
+                    crate::command::Command::Withdraw(cmd) => cmd.handle(&pid, self.nonce, rand, counter),
+                    crate::command::Command::Activity(cmd) => cmd.handle(&pid, self.nonce, rand, counter),
+                    crate::command::Command::Deposit(cmd) => cmd.handle(&pid, self.nonce, rand, counter),
                 }
-                if let Activity::CreateMarket(_, _, _, _, _, _) = cmd {
-                    unsafe { require(*pkey == *ADMIN_PUBKEY) };
-                }
-                cmd.handle(&pid, self.nonce, rand, counter)
-                    .map_or_else(|e| e, |_| 0)
-            },
-            crate::command::Command::Deposit(cmd) => {
-                unsafe { require(*pkey == *ADMIN_PUBKEY) };
-                cmd.handle(&pid, self.nonce, rand, counter)
-                    .map_or_else(|e| e, |_| 0)
-            },
+            }).map_or_else(|e| e, |_| 0),
         };
 
         if e == 0 {
             match self.command {
-                crate::command::Command::Tick => (),
+                Ok(crate::command::Command::Tick) => (),
                 _ => {
                     self.inc_tx_number();
                 }
@@ -336,9 +437,8 @@ impl MarketManager {
     pub fn store_market(market_id: u64, market: &MarketData) {
         let mut data = vec![];
         market.to_data(&mut data);
-        let kvpair = unsafe { &mut MERKLE_MAP };
         let key = [Self::MARKET_PREFIX[0], Self::MARKET_PREFIX[1], market_id, 0];
-        kvpair.set(&key, data.as_slice());
+        crate::journal::set(&key, data.as_slice());
     }
     
     pub fn get_market(market_id: u64) -> Option<MarketData> {
@@ -363,9 +463,11 @@ impl MarketManager {
         resolution_time: u64,
         initial_yes_liquidity: u64,
         initial_no_liquidity: u64,
-        b: u64
+        b: u64,
+        token: u64
     ) -> Result<u64, u32> {
-        let market = MarketData::new_with_liquidity(
+        validate_token(token)?;
+        let mut market = MarketData::new_with_liquidity(
             start_time,
             end_time,
             resolution_time,
@@ -373,25 +475,236 @@ impl MarketManager {
             initial_no_liquidity,
             b
         )?;
-        
+        market.token = token;
+        Ok(Self::insert_market(&market))
+    }
+
+    pub fn create_clob_market(start_time: u64, end_time: u64, resolution_time: u64, token: u64) -> Result<u64, u32> {
+        validate_token(token)?;
+        let mut market = MarketData::new_clob(start_time, end_time, resolution_time)?;
+        market.token = token;
+        Ok(Self::insert_market(&market))
+    }
+
+    // Allocates an id for an already built market, stores it and announces it
+    pub fn insert_market(market: &MarketData) -> u64 {
         let market_id = {
             let mut global_state = GLOBAL_STATE.0.borrow_mut();
             let market_id = global_state.next_market_id;
             global_state.next_market_id += 1;
             global_state.market_ids.push(market_id);
-            
             market_id
-        }; // global_state is automatically dropped here
-        
-        Self::store_market(market_id, &market);
-        
-        // Emit IndexedObject event for new market
-        emit_market_indexed_object(&market, market_id);
-        
-        Ok(market_id)
+        };
+        Self::store_market(market_id, market);
+        emit_market_indexed_object(market, market_id);
+        market_id
+    }
+
+    // Conditional market on the LMSR that inherits its parent's token; the parent
+    // has to exist and still be open to resolution
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_conditional_market(
+        start_time: u64,
+        end_time: u64,
+        resolution_time: u64,
+        initial_yes_liquidity: u64,
+        initial_no_liquidity: u64,
+        b: u64,
+        parent_market_id: u64,
+        condition: u64
+    ) -> Result<u64, u32> {
+        let parent = Self::get_market(parent_market_id).ok_or(ERROR_INVALID_CONDITION)?;
+        if parent.resolved || condition > 1 {
+            return Err(ERROR_INVALID_CONDITION);
+        }
+        let mut market = MarketData::new_with_liquidity(
+            start_time,
+            end_time,
+            resolution_time,
+            initial_yes_liquidity,
+            initial_no_liquidity,
+            b
+        )?;
+        market.token = parent.token;
+        market.kind = crate::market::MARKET_KIND_CONDITIONAL;
+        market.parent_market_id = parent_market_id;
+        market.condition = condition;
+        Ok(Self::insert_market(&market))
+    }
+
+    // None while a conditional market's parent is unresolved, otherwise whether the
+    // parent resolved the required way. A voided parent fails the condition and
+    // unconditional markets always hold.
+    pub fn condition_met(market: &MarketData) -> Option<bool> {
+        if !market.is_conditional() {
+            return Some(true);
+        }
+        let parent = Self::get_market(market.parent_market_id)?;
+        if !parent.resolved {
+            return None;
+        }
+        Some(parent.outcome == Some(market.condition == 1))
+    }
+
+    // Trading stops once the condition has failed, the market can only be refunded
+    pub fn is_tradable(market: &MarketData, current_time: u64) -> bool {
+        market.is_active(current_time) && Self::condition_met(market) != Some(false)
+    }
+
+    // Voids an unresolved conditional market whose condition has failed, so that
+    // positions can be refunded without waiting for the resolver
+    pub fn settle_condition(market_id: u64, market: &mut MarketData) -> Result<(), u32> {
+        if market.resolved || Self::condition_met(market) != Some(false) {
+            return Ok(());
+        }
+        market.void()?;
+        Self::store_market(market_id, market);
+        emit_market_indexed_object(market, market_id);
+        Ok(())
     }
 
     pub fn get_active_market_ids() -> Vec<u64> {
         GLOBAL_STATE.0.borrow().market_ids.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{Command, Activity};
+    use crate::config::MAX_COLLATERAL_TOKENS;
+    use crate::spec::*;
+
+    // (command id, accepted param counts including the header word)
+    const ARITIES: [(u64, &[usize]); 30] = [
+        (TICK, &[1]),
+        (INSTALL_PLAYER, &[1]),
+        (WITHDRAW, &[5, 6]),
+        (DEPOSIT, &[9]),
+        (BET, &[4]),
+        (SELL, &[4]),
+        (RESOLVE, &[3]),
+        (CLAIM, &[2]),
+        (WITHDRAW_FEES, &[2]),
+        (CREATE_MARKET, &[7, 8, 9]),
+        (CLAIM_ALL, &[1]),
+        (TRANSFER_SHARES, &[6]),
+        (TRANSFER, &[4, 5]),
+        (CANCEL_WITHDRAWAL, &[2]),
+        (SET_PARAM, &[3]),
+        (FUND_FAUCET, &[2]),
+        (CLAIM_FAUCET, &[1]),
+        (GRANT_ROLE, &[4]),
+        (REVOKE_ROLE, &[4]),
+        (ROTATE_ADMIN, &[3]),
+        (PLACE_LIMIT_ORDER, &[5]),
+        (CANCEL_ORDER, &[2]),
+        (PLACE_TRIGGER, &[6]),
+        (CANCEL_TRIGGER, &[2]),
+        (PLACE_PARLAY, &[6, 8, 10]),
+        (CLAIM_PARLAY, &[4]),
+        (FUND_PARLAY_BANKROLL, &[3]),
+        (CREATE_CONDITIONAL_MARKET, &[9]),
+        (CREATE_SERIES, &[12]),
+        (STOP_SERIES, &[2]),
+    ];
+
+    fn params(command: u64, len: usize) -> Vec<u64> {
+        let mut params = vec![0; len.max(1)];
+        params[0] = command | (7 << 16);
+        params
+    }
+
+    #[test]
+    fn test_decode_arity_for_every_command() {
+        for (command, accepted) in ARITIES {
+            for len in 1..=10 {
+                let result = Transaction::try_decode(&params(command, len));
+                if accepted.contains(&len) {
+                    assert!(result.is_ok(), "command {} len {}", command, len);
+                } else {
+                    assert_eq!(
+                        result.err(),
+                        Some(DecodeError::WrongArity { command, len }),
+                        "command {} len {}", command, len
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_unknown_command() {
+        for command in (STOP_SERIES + 1)..EXTENDED {
+            assert_eq!(Transaction::try_decode(&params(command, 1)).err(), Some(DecodeError::UnknownCommand(command)));
+        }
+        assert_eq!(Transaction::try_decode(&[]).err(), Some(DecodeError::WrongArity { command: 0, len: 0 }));
+
+        let tx = Transaction::decode(&params(0x42, 3));
+        assert_eq!(tx.nonce, 7);
+        assert_eq!(tx.authorize(&[0; 4]), Err(crate::error::ERROR_UNKNOWN_COMMAND));
+    }
+
+    #[test]
+    fn test_expired_trade_is_rejected() {
+        let tx = Transaction::decode(&[EXTENDED, BET | (VERSION_EXTENDED << 8), EXT_VALID_UNTIL, 1, 1, 100, 10]);
+        assert_eq!(tx.check_deadline(10), Ok(()));
+        assert_eq!(tx.check_deadline(11), Err(ERROR_TRANSACTION_EXPIRED));
+
+        // Without the extension there is no deadline
+        let tx = Transaction::decode(&[BET, 1, 1, 100]);
+        assert_eq!(tx.check_deadline(u64::MAX), Ok(()));
+    }
+
+    #[test]
+    fn test_tick_drops_closed_markets() {
+        let _guard = crate::test_support::lock();
+        let counter = GLOBAL_STATE.0.borrow().counter;
+        let closing = MarketManager::create_market_with_liquidity(counter, counter + 2, counter + 2, 1_000, 1_000, 10_000, 0).unwrap();
+        let resolved = crate::test_support::market(0);
+        let mut market = MarketManager::get_market(resolved).unwrap();
+        market.resolve(true).unwrap();
+        MarketManager::update_market(resolved, &market);
+        let tick = Transaction::decode(&params(TICK, 1));
+
+        tick.tick();
+        let market_ids = MarketManager::get_active_market_ids();
+        assert!(market_ids.contains(&closing) && !market_ids.contains(&resolved));
+        tick.tick();
+        assert!(!MarketManager::get_active_market_ids().contains(&closing));
+        assert!(MarketManager::get_market(closing).is_some());
+    }
+
+    #[test]
+    fn test_decode_out_of_range_fields() {
+        let cases: [(u64, usize, usize); 14] = [
+            (BET, 4, 2),
+            (SELL, 4, 2),
+            (RESOLVE, 3, 2),
+            (TRANSFER_SHARES, 6, 2),
+            (WITHDRAW, 6, 1),
+            (DEPOSIT, 9, 3),
+            (TRANSFER, 5, 4),
+            (CREATE_MARKET, 8, 7),
+            (CREATE_MARKET, 9, 8),
+            (WITHDRAW, 5, 1),
+            (PLACE_LIMIT_ORDER, 5, 2),
+            (PLACE_TRIGGER, 6, 3),
+            (CREATE_CONDITIONAL_MARKET, 9, 8),
+            (CREATE_SERIES, 12, 7),
+        ];
+        for (command, len, index) in cases {
+            let mut p = params(command, len);
+            p[index] = MAX_COLLATERAL_TOKENS.max(2);
+            assert_eq!(
+                Transaction::try_decode(&p).err(),
+                Some(DecodeError::OutOfRange { command, index }),
+                "command {}", command
+            );
+        }
+
+        let mut bet = params(BET, 4);
+        bet[1..].copy_from_slice(&[3, 1, 500]);
+        assert!(matches!(Transaction::try_decode(&bet), Ok(Command::Activity(Activity::Bet(3, 1, 500)))));
+    }
+}
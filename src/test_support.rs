@@ -0,0 +1,34 @@
+// Shared setup for tests that touch process wide state. The merkle map, the global
+// state, events, settlements and open journals are shared by every test thread, so
+// a test that reads or writes them holds `lock()` for its whole body.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use crate::player::Player;
+use crate::state::MarketManager;
+
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+static NEXT_PID: AtomicU64 = AtomicU64::new(1);
+
+// A failed test poisons the lock; the tests after it still run
+pub fn lock() -> MutexGuard<'static, ()> {
+    STATE_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Player id no other test uses
+pub fn new_pid() -> [u64; 2] {
+    [1_000_000, NEXT_PID.fetch_add(1, Ordering::Relaxed)]
+}
+
+// Stored player holding `balance` of the default token
+pub fn player(balance: u64) -> [u64; 2] {
+    let pid = new_pid();
+    let mut player = Player::new_from_pid(pid);
+    player.data.balance = balance;
+    player.store();
+    pid
+}
+
+// LMSR market trading from 0 to 1_000 with 1_000 YES / 1_000 NO liquidity and b = 10_000
+pub fn market(token: u64) -> u64 {
+    MarketManager::create_market_with_liquidity(0, 1_000, 1_000, 1_000, 1_000, 10_000, token).unwrap()
+}
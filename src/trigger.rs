@@ -0,0 +1,333 @@
+use serde::Serialize;
+use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
+use crate::config::{PRICE_PRECISION, MAX_ACTIVE_TRIGGERS, MAX_TRIGGERS_PER_OWNER, MAX_TRIGGER_CHECKS_PER_TICK};
+use crate::error::*;
+use crate::event::{insert_event, emit_market_indexed_object, EVENT_TRIGGER_UPDATE};
+use crate::market::MarketData;
+use crate::player::{Player, PlayerMarketManager};
+use crate::state::{MarketManager, GLOBAL_STATE};
+
+// Trigger kinds
+pub const STOP_LOSS: u64 = 0;   // sell once the side's price falls to or below the trigger price
+pub const TAKE_PROFIT: u64 = 1; // sell once the side's price rises to or above the trigger price
+
+// Trigger event status codes
+pub const TRIGGER_PLACED: u64 = 0;
+pub const TRIGGER_EXECUTED: u64 = 1;
+pub const TRIGGER_CANCELLED: u64 = 2;
+pub const TRIGGER_EXPIRED: u64 = 3; // market closed or the sell could not be executed
+
+// Conditional sell of up to `shares` of a position. Shares are not escrowed, the
+// trigger sells whatever part of them the owner still holds when it fires.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Trigger {
+    pub owner: [u64; 2],
+    pub market_id: u64,
+    pub side: u64,  // 0 = NO, 1 = YES
+    pub kind: u64,  // STOP_LOSS or TAKE_PROFIT
+    pub price: u64, // trigger price of `side`, scaled by PRICE_PRECISION
+    pub shares: u64,
+    pub counter: u64, // counter at placement
+}
+
+impl StorageData for Trigger {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        Trigger {
+            owner: [*u64data.next().unwrap(), *u64data.next().unwrap()],
+            market_id: *u64data.next().unwrap(),
+            side: *u64data.next().unwrap(),
+            kind: *u64data.next().unwrap(),
+            price: *u64data.next().unwrap(),
+            shares: *u64data.next().unwrap(),
+            counter: *u64data.next().unwrap(),
+        }
+    }
+
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.owner[0]);
+        data.push(self.owner[1]);
+        data.push(self.market_id);
+        data.push(self.side);
+        data.push(self.kind);
+        data.push(self.price);
+        data.push(self.shares);
+        data.push(self.counter);
+    }
+}
+
+impl Trigger {
+    pub fn is_triggered(&self, market: &MarketData) -> Result<bool, u32> {
+        let price = if self.side == 1 { market.get_yes_price()? } else { market.get_no_price()? };
+        Ok(match self.kind {
+            STOP_LOSS => price <= self.price,
+            _ => price >= self.price,
+        })
+    }
+}
+
+pub struct TriggerManager;
+
+impl TriggerManager {
+    const TRIGGER_PREFIX: [u64; 2] = [12, 0]; // Prefix for trigger storage
+
+    pub fn get_trigger(id: u64) -> Option<Trigger> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let mut data = kvpair.get(&[Self::TRIGGER_PREFIX[0], Self::TRIGGER_PREFIX[1], id, 0]);
+        if !data.is_empty() {
+            let mut u64data = data.iter_mut();
+            Some(Trigger::from_data(&mut u64data))
+        } else {
+            None
+        }
+    }
+
+    fn store_trigger(id: u64, trigger: &Trigger) {
+        let mut data = vec![];
+        trigger.to_data(&mut data);
+        crate::journal::set(&[Self::TRIGGER_PREFIX[0], Self::TRIGGER_PREFIX[1], id, 0], data.as_slice());
+    }
+
+    fn remove_trigger(id: u64) {
+        crate::journal::set(&[Self::TRIGGER_PREFIX[0], Self::TRIGGER_PREFIX[1], id, 0], &[]);
+        GLOBAL_STATE.0.borrow_mut().triggers.retain(|trigger_id| *trigger_id != id);
+    }
+
+    pub fn place(player: &Player, market_id: u64, side: u64, kind: u64, price: u64, shares: u64, counter: u64) -> Result<u64, u32> {
+        if side > 1 {
+            return Err(ERROR_INVALID_BET_TYPE);
+        }
+        if kind > TAKE_PROFIT {
+            return Err(ERROR_INVALID_PARAM);
+        }
+        if price == 0 || price >= PRICE_PRECISION {
+            return Err(ERROR_INVALID_PRICE);
+        }
+        GLOBAL_STATE.0.borrow().ensure_market_active(market_id)?;
+        // Triggers watch the LMSR price, which order book markets do not have
+        if MarketManager::get_market(market_id).is_some_and(|market| market.is_clob()) {
+            return Err(ERROR_INVALID_MARKET_KIND);
+        }
+        let position = PlayerMarketManager::get_position(&player.player_id, market_id);
+        let held = if side == 1 { position.yes_shares } else { position.no_shares };
+        if shares == 0 || shares > held {
+            return Err(ERROR_INSUFFICIENT_BALANCE);
+        }
+
+        // One player cannot fill up the shared trigger list
+        let trigger_ids = GLOBAL_STATE.0.borrow().triggers.clone();
+        let owned = trigger_ids.iter()
+            .filter(|id| Self::get_trigger(**id).is_some_and(|trigger| trigger.owner == player.player_id))
+            .count();
+        if owned as u64 >= MAX_TRIGGERS_PER_OWNER {
+            return Err(ERROR_TOO_MANY_TRIGGERS);
+        }
+
+        let trigger = Trigger {
+            owner: player.player_id,
+            market_id,
+            side,
+            kind,
+            price,
+            shares,
+            counter,
+        };
+        let id = {
+            let mut global_state = GLOBAL_STATE.0.borrow_mut();
+            if global_state.triggers.len() as u64 >= MAX_ACTIVE_TRIGGERS {
+                return Err(ERROR_TOO_MANY_TRIGGERS);
+            }
+            let id = global_state.next_trigger_id;
+            global_state.next_trigger_id += 1;
+            global_state.triggers.push(id);
+            id
+        };
+        Self::store_trigger(id, &trigger);
+        Self::emit_trigger_event(TRIGGER_PLACED, id, &trigger, 0, counter);
+        Ok(id)
+    }
+
+    pub fn cancel(player: &Player, id: u64, counter: u64) -> Result<(), u32> {
+        let trigger = Self::get_trigger(id).ok_or(ERROR_TRIGGER_NOT_FOUND)?;
+        if trigger.owner != player.player_id {
+            return Err(ERROR_UNAUTHORIZED);
+        }
+        Self::remove_trigger(id);
+        Self::emit_trigger_event(TRIGGER_CANCELLED, id, &trigger, 0, counter);
+        Ok(())
+    }
+
+    // Called from tick: checks a bounded window of triggers, resuming at the id after
+    // the last one the previous tick looked at so every trigger is eventually checked
+    pub fn evaluate(counter: u64) {
+        let (trigger_ids, cursor) = {
+            let global_state = GLOBAL_STATE.0.borrow();
+            (global_state.triggers.clone(), global_state.trigger_cursor)
+        };
+        if trigger_ids.is_empty() {
+            return;
+        }
+        // Ids are kept in ascending order; past the last one evaluation wraps around
+        let start = trigger_ids.iter().position(|id| *id >= cursor).unwrap_or(0);
+        let checks = (MAX_TRIGGER_CHECKS_PER_TICK as usize).min(trigger_ids.len());
+        let last = trigger_ids[(start + checks - 1) % trigger_ids.len()];
+        GLOBAL_STATE.0.borrow_mut().trigger_cursor = last + 1;
+        for offset in 0..checks {
+            let id = trigger_ids[(start + offset) % trigger_ids.len()];
+            let Some(trigger) = Self::get_trigger(id) else { continue };
+            let market = MarketManager::get_market(trigger.market_id)
+                .filter(|market| MarketManager::is_tradable(market, counter));
+            let status = match market {
+                None => TRIGGER_EXPIRED,
+                Some(market) => match trigger.is_triggered(&market) {
+                    Ok(false) => continue,
+                    Ok(true) => TRIGGER_EXECUTED,
+                    Err(_) => TRIGGER_EXPIRED,
+                },
+            };
+            // A failed sell leaves no partial writes and retires the trigger
+            let (status, payout) = if status == TRIGGER_EXECUTED {
+                crate::journal::atomic(|| Self::execute(&trigger, counter))
+                    .map_or((TRIGGER_EXPIRED, 0), |payout| (TRIGGER_EXECUTED, payout))
+            } else {
+                (status, 0)
+            };
+            Self::remove_trigger(id);
+            Self::emit_trigger_event(status, id, &trigger, payout, counter);
+        }
+    }
+
+    // Sells the owner's shares through the AMM and returns the payout
+    fn execute(trigger: &Trigger, counter: u64) -> Result<u64, u32> {
+        crate::journal::track_player(&trigger.owner);
+        let mut player = Player::get_from_pid(&trigger.owner).ok_or(ERROR_PLAYER_NOT_EXIST)?;
+        let mut market = MarketManager::get_market(trigger.market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+        let position = PlayerMarketManager::get_position(&trigger.owner, trigger.market_id);
+        let held = if trigger.side == 1 { position.yes_shares } else { position.no_shares };
+        let shares = trigger.shares.min(held);
+        if shares == 0 {
+            return Err(ERROR_INSUFFICIENT_BALANCE);
+        }
+
+        let payout = market.sell_shares(trigger.side, shares)?;
+        market.record_price(counter)?;
+        let (released, credit) = if trigger.side == 1 {
+            player.data.subtract_yes_shares_for_market(&trigger.owner, trigger.market_id, shares, payout)?
+        } else {
+            player.data.subtract_no_shares_for_market(&trigger.owner, trigger.market_id, shares, payout)?
+        };
+        market.release_cost_basis(released);
+        MarketManager::update_market(trigger.market_id, &market);
+        player.data.add_proceeds_of(market.token, payout, credit);
+        player.store();
+        emit_market_indexed_object(&market, trigger.market_id);
+        Ok(payout)
+    }
+
+    fn emit_trigger_event(status: u64, id: u64, trigger: &Trigger, payout: u64, counter: u64) {
+        let mut data = vec![
+            status,
+            id,
+            trigger.owner[0],
+            trigger.owner[1],
+            trigger.market_id,
+            trigger.side,
+            trigger.kind,
+            trigger.price,
+            trigger.shares,
+            payout,
+            counter,
+        ];
+        insert_event(EVENT_TRIGGER_UPDATE, &mut data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{Activity, CommandHandler};
+    use crate::test_support;
+
+    #[test]
+    fn test_trigger_direction() {
+        let market = MarketData::new_with_liquidity(0, 1_000, 1_000, 1_000, 1_000, 10_000).unwrap();
+        let price = market.get_yes_price().unwrap();
+        let trigger = |kind, price| Trigger { side: 1, kind, price, ..Default::default() };
+        assert!(trigger(STOP_LOSS, price).is_triggered(&market).unwrap());
+        assert!(!trigger(STOP_LOSS, price - 1).is_triggered(&market).unwrap());
+        assert!(trigger(TAKE_PROFIT, price).is_triggered(&market).unwrap());
+        assert!(!trigger(TAKE_PROFIT, price + 1).is_triggered(&market).unwrap());
+    }
+
+    #[test]
+    fn test_stop_loss_sells_at_tick() {
+        let _guard = test_support::lock();
+        let market_id = test_support::market(0);
+        let (owner, whale) = (test_support::player(10_000), test_support::player(10_000));
+        Activity::Bet(market_id, 1, 1_000).handle(&owner, 0, &[0; 4], 0).unwrap();
+        let shares = PlayerMarketManager::get_position(&owner, market_id).yes_shares;
+        Activity::PlaceTrigger(market_id, 1, STOP_LOSS, 400_000, shares).handle(&owner, 1, &[0; 4], 0).unwrap();
+
+        // Nothing happens while YES stays above the stop
+        TriggerManager::evaluate(0);
+        assert_eq!(PlayerMarketManager::get_position(&owner, market_id).yes_shares, shares);
+
+        Activity::Bet(market_id, 0, 5_000).handle(&whale, 0, &[0; 4], 0).unwrap();
+        TriggerManager::evaluate(0);
+        let player = Player::get_from_pid(&owner).unwrap();
+        assert_eq!(PlayerMarketManager::get_position(&owner, market_id).yes_shares, 0);
+        assert!(player.data.balance > 9_000);
+        assert!(!GLOBAL_STATE.0.borrow().triggers.iter().any(|id| TriggerManager::get_trigger(*id)
+            .is_some_and(|trigger| trigger.owner == owner)));
+    }
+
+    #[test]
+    fn test_trigger_requires_held_shares_and_owner_to_cancel() {
+        let _guard = test_support::lock();
+        let market_id = test_support::market(0);
+        let (owner, other) = (test_support::player(10_000), test_support::player(0));
+        let player = Player::get_from_pid(&owner).unwrap();
+
+        let place = Activity::PlaceTrigger(market_id, 1, TAKE_PROFIT, 900_000, 1);
+        assert_eq!(place.handle(&owner, 0, &[0; 4], 0), Err(ERROR_INSUFFICIENT_BALANCE));
+        Activity::Bet(market_id, 1, 1_000).handle(&owner, 0, &[0; 4], 0).unwrap();
+        let id = TriggerManager::place(&player, market_id, 1, TAKE_PROFIT, 900_000, 1, 0).unwrap();
+        assert!(GLOBAL_STATE.0.borrow().triggers.contains(&id));
+
+        assert_eq!(Activity::CancelTrigger(id).handle(&other, 0, &[0; 4], 0), Err(ERROR_UNAUTHORIZED));
+        Activity::CancelTrigger(id).handle(&owner, 1, &[0; 4], 0).unwrap();
+        assert!(TriggerManager::get_trigger(id).is_none());
+        assert!(!GLOBAL_STATE.0.borrow().triggers.contains(&id));
+    }
+
+    #[test]
+    fn test_evaluation_resumes_after_the_last_checked_id() {
+        let _guard = test_support::lock();
+        GLOBAL_STATE.0.borrow_mut().triggers.clear();
+        let (open, closing) = (test_support::market(0), test_support::market(0));
+        let owners = [test_support::player(10_000), test_support::player(10_000), test_support::player(10_000)];
+        let mut ids = vec![];
+        for (owner, market_id, count) in [(owners[0], open, 8), (owners[1], open, 8), (owners[2], closing, 4)] {
+            let player = Player::get_from_pid(&owner).unwrap();
+            Activity::Bet(market_id, 1, 1_000).handle(&owner, 0, &[0; 4], 0).unwrap();
+            for _ in 0..count {
+                ids.push(TriggerManager::place(&player, market_id, 1, TAKE_PROFIT, 999_999, 1, 0).unwrap());
+            }
+        }
+        let player = Player::get_from_pid(&owners[0]).unwrap();
+        assert_eq!(TriggerManager::place(&player, open, 1, TAKE_PROFIT, 999_999, 1, 0), Err(ERROR_TOO_MANY_TRIGGERS));
+
+        TriggerManager::evaluate(0);
+        assert_eq!(GLOBAL_STATE.0.borrow().trigger_cursor, ids[15] + 1);
+
+        // The last four expire as they are checked, then evaluation wraps to the front
+        let mut market = MarketManager::get_market(closing).unwrap();
+        market.resolve(true).unwrap();
+        MarketManager::update_market(closing, &market);
+        TriggerManager::evaluate(0);
+        assert_eq!(GLOBAL_STATE.0.borrow().triggers, ids[..16]);
+        assert_eq!(GLOBAL_STATE.0.borrow().trigger_cursor, ids[11] + 1);
+        TriggerManager::evaluate(0);
+        assert_eq!(GLOBAL_STATE.0.borrow().trigger_cursor, ids[11] + 1);
+        GLOBAL_STATE.0.borrow_mut().triggers.clear();
+    }
+}
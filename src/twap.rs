@@ -0,0 +1,173 @@
+use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
+use crate::config::{TWAP_OBSERVATION_INTERVAL, MAX_TWAP_OBSERVATIONS, TWAP_QUOTE_WINDOW};
+use crate::error::*;
+use crate::event::emit_twap_checkpoint;
+use crate::market::MarketData;
+use crate::state::MarketManager;
+
+// Checkpoints of a market's price accumulator, oldest first
+#[derive(Clone, Debug, Default)]
+pub struct PriceObservations(pub Vec<(u64, u64)>); // (counter, price_cumulative)
+
+impl StorageData for PriceObservations {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        let count = *u64data.next().unwrap();
+        let mut observations = Vec::new();
+        for _ in 0..count {
+            observations.push((*u64data.next().unwrap(), *u64data.next().unwrap()));
+        }
+        PriceObservations(observations)
+    }
+
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.0.len() as u64);
+        for (counter, cumulative) in self.0.iter() {
+            data.push(*counter);
+            data.push(*cumulative);
+        }
+    }
+}
+
+pub struct TwapOracle;
+
+impl TwapOracle {
+    const OBSERVATION_PREFIX: [u64; 2] = [18, 0]; // Prefix for price observations
+
+    pub fn get_observations(market_id: u64) -> PriceObservations {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let mut data = kvpair.get(&[Self::OBSERVATION_PREFIX[0], Self::OBSERVATION_PREFIX[1], market_id, 0]);
+        if !data.is_empty() {
+            let mut u64data = data.iter_mut();
+            PriceObservations::from_data(&mut u64data)
+        } else {
+            PriceObservations::default()
+        }
+    }
+
+    fn store_observations(market_id: u64, observations: &PriceObservations) {
+        let mut data = vec![];
+        observations.to_data(&mut data);
+        crate::journal::set(&[Self::OBSERVATION_PREFIX[0], Self::OBSERVATION_PREFIX[1], market_id, 0], data.as_slice());
+    }
+
+    // Called from tick: checkpoints every trading LMSR market once per interval
+    pub fn observe_all(market_ids: &[u64], counter: u64) {
+        if counter % TWAP_OBSERVATION_INTERVAL != 0 {
+            return;
+        }
+        for market_id in market_ids {
+            let _ = Self::observe(*market_id, counter);
+        }
+    }
+
+    // Appends the accumulator value at `counter`, dropping the oldest checkpoint
+    // once MAX_TWAP_OBSERVATIONS are kept, and publishes the quoted TWAP
+    pub fn observe(market_id: u64, counter: u64) -> Result<(), u32> {
+        let market = MarketManager::get_market(market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+        if market.is_clob() || !market.price_tracked || !market.is_active(counter) {
+            return Ok(());
+        }
+        let cumulative = market.price_cumulative_at(counter)?;
+        let mut observations = Self::get_observations(market_id);
+        observations.0.push((counter, cumulative));
+        if observations.0.len() as u64 > MAX_TWAP_OBSERVATIONS {
+            observations.0.remove(0);
+        }
+        Self::store_observations(market_id, &observations);
+        if let Some(twap) = Self::quote(market_id, &market, counter)? {
+            emit_twap_checkpoint(market_id, counter, cumulative, twap);
+        }
+        Ok(())
+    }
+
+    // YES TWAP over the last TWAP_QUOTE_WINDOW ticks, or over as much of it as the
+    // market has been tracked; None before any tracked trading time
+    pub fn quote(market_id: u64, market: &MarketData, counter: u64) -> Result<Option<u64>, u32> {
+        if market.is_clob() || !market.price_tracked {
+            return Ok(None);
+        }
+        let tracked = counter.min(market.end_time).saturating_sub(market.price_tracked_since);
+        match TWAP_QUOTE_WINDOW.min(tracked) {
+            0 => Ok(None),
+            window => Self::twap(market_id, window, counter).map(Some),
+        }
+    }
+
+    // Time-weighted average YES price (PRICE_PRECISION units) over the last `window`
+    // ticks of trading. The window starts at the latest checkpoint at or before
+    // `counter - window`, so it stretches back to the nearest earlier checkpoint.
+    pub fn twap(market_id: u64, window: u64, counter: u64) -> Result<u64, u32> {
+        let market = MarketManager::get_market(market_id).ok_or(ERROR_MARKET_NOT_ACTIVE)?;
+        if market.is_clob() {
+            return Err(ERROR_INVALID_MARKET_KIND);
+        }
+        let end = counter.min(market.end_time);
+        if !market.price_tracked || window == 0 || window > end.saturating_sub(market.price_tracked_since) {
+            return Err(ERROR_INVALID_TWAP_WINDOW);
+        }
+        let target = end - window;
+        // The tick tracking started at is an implicit checkpoint with an empty accumulator
+        let (start, start_cumulative) = Self::get_observations(market_id).0.iter().rev()
+            .find(|(observed_at, _)| *observed_at <= target)
+            .copied()
+            .unwrap_or((market.price_tracked_since, 0));
+        let cumulative = market.price_cumulative_at(end)?;
+        Ok((cumulative - start_cumulative) / (end - start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn test_twap_weights_prices_by_time() {
+        let _guard = test_support::lock();
+        let market_id = test_support::market(0);
+        let mut market = MarketManager::get_market(market_id).unwrap();
+        let initial = market.get_yes_price().unwrap();
+        assert_eq!(market.last_yes_price, initial);
+
+        market.place_bet(1, 5_000).unwrap();
+        market.record_price(100).unwrap();
+        MarketManager::update_market(market_id, &market);
+        let moved = market.get_yes_price().unwrap();
+        assert!(moved > initial);
+
+        assert_eq!(TwapOracle::twap(market_id, 200, 200), Ok((initial + moved) / 2));
+        assert_eq!(TwapOracle::twap(market_id, 201, 200), Err(ERROR_INVALID_TWAP_WINDOW));
+        assert_eq!(TwapOracle::twap(market_id, 0, 200), Err(ERROR_INVALID_TWAP_WINDOW));
+
+        // Without a checkpoint inside the history the window reaches back to the start
+        assert_eq!(TwapOracle::twap(market_id, 50, 200), Ok((initial + moved) / 2));
+        TwapOracle::observe(market_id, 150).unwrap();
+        assert_eq!(TwapOracle::twap(market_id, 50, 200), Ok(moved));
+
+        // Accumulation stops when trading ends
+        assert_eq!(TwapOracle::twap(market_id, 850, 5_000), Ok(moved));
+    }
+    #[test]
+    fn test_quote_needs_tracked_trading_time() {
+        let _guard = test_support::lock();
+        let market_id = test_support::market(0);
+        let market = MarketManager::get_market(market_id).unwrap();
+        assert!(market.price_tracked);
+        let since = market.price_tracked_since;
+        assert_eq!(TwapOracle::quote(market_id, &market, since), Ok(None));
+        assert_eq!(TwapOracle::quote(market_id, &market, since + 10), Ok(Some(market.last_yes_price)));
+
+        // A market stored before TWAP tracking starts accumulating at its next trade
+        let mut untracked = MarketData { price_tracked: false, ..market.clone() };
+        assert_eq!(TwapOracle::quote(market_id, &untracked, 500), Ok(None));
+        untracked.record_price(400).unwrap();
+        assert!(untracked.price_tracked);
+        assert_eq!(untracked.price_tracked_since, 400);
+
+        // Markets stored before the flag count as tracked once they have a price
+        let mut data = vec![];
+        market.to_data(&mut data);
+        data.pop();
+        assert!(MarketData::from_data(&mut data.iter_mut()).price_tracked);
+    }
+}
@@ -0,0 +1,268 @@
+use serde::Serialize;
+use zkwasm_rest_abi::{StorageData, MERKLE_MAP};
+use crate::command::Withdraw;
+use crate::error::*;
+use crate::event::{insert_event, EVENT_WITHDRAWAL_UPDATE};
+use crate::math_safe::safe_add;
+use crate::params::ProtocolParams;
+use crate::player::{Player, PlayerData};
+use crate::settlement::SettlementInfo;
+use crate::state::GLOBAL_STATE;
+
+// Withdrawal event status codes
+pub const WITHDRAWAL_SETTLED: u64 = 0;
+pub const WITHDRAWAL_QUEUED: u64 = 1;
+pub const WITHDRAWAL_CANCELLED: u64 = 2;
+
+// A large withdrawal whose balance has been debited but whose settlement is held back
+#[derive(Serialize, Clone, Debug)]
+pub struct PendingWithdrawal {
+    pub player_id: [u64; 2],
+    pub token: u64,
+    pub amount: u64,
+    pub address: [u64; 3],
+    pub release_counter: u64,
+    pub usage_epoch: u64, // rate-limit epoch the amount was counted against
+}
+
+impl PendingWithdrawal {
+    pub fn to_withdraw(&self) -> Withdraw {
+        Withdraw {
+            token: self.token,
+            amount: self.amount,
+            address: self.address,
+        }
+    }
+}
+
+impl StorageData for PendingWithdrawal {
+    fn from_data(u64data: &mut std::slice::IterMut<u64>) -> Self {
+        PendingWithdrawal {
+            player_id: [*u64data.next().unwrap(), *u64data.next().unwrap()],
+            token: *u64data.next().unwrap(),
+            amount: *u64data.next().unwrap(),
+            address: [*u64data.next().unwrap(), *u64data.next().unwrap(), *u64data.next().unwrap()],
+            release_counter: *u64data.next().unwrap(),
+            // Queued before the epoch was recorded: no usage is given back on refund
+            usage_epoch: u64data.next().map_or(u64::MAX, |v| *v),
+        }
+    }
+
+    fn to_data(&self, data: &mut Vec<u64>) {
+        data.push(self.player_id[0]);
+        data.push(self.player_id[1]);
+        data.push(self.token);
+        data.push(self.amount);
+        data.extend(self.address.iter().cloned());
+        data.push(self.release_counter);
+        data.push(self.usage_epoch);
+    }
+}
+
+pub struct WithdrawalManager;
+
+impl WithdrawalManager {
+    const PENDING_PREFIX: [u64; 2] = [6, 0]; // Prefix for pending withdrawal storage
+
+    pub fn epoch(counter: u64) -> u64 {
+        counter / ProtocolParams::get().withdraw_epoch_ticks
+    }
+
+    // Rolls the (epoch, used) window forward and adds `amount`, failing if `limit` would be exceeded
+    pub fn apply_epoch_usage(window: (u64, u64), counter: u64, amount: u64, limit: u64) -> Result<(u64, u64), u32> {
+        let epoch = Self::epoch(counter);
+        let used = if window.0 == epoch { window.1 } else { 0 };
+        let used = safe_add(used, amount)?;
+        if used > limit {
+            return Err(ERROR_WITHDRAW_LIMIT_EXCEEDED);
+        }
+        Ok((epoch, used))
+    }
+
+    // Checks the per-player and global epoch limits and records the usage when both pass
+    pub fn record_usage(player: &mut PlayerData, amount: u64, counter: u64) -> Result<(), u32> {
+        let params = ProtocolParams::get();
+        let mut global_state = GLOBAL_STATE.0.borrow_mut();
+        let player_window = Self::apply_epoch_usage(
+            (player.withdraw_epoch, player.withdrawn_in_epoch), counter, amount, params.player_withdraw_limit)?;
+        let global_window = Self::apply_epoch_usage(
+            (global_state.withdraw_epoch, global_state.withdrawn_in_epoch), counter, amount, params.global_withdraw_limit)?;
+        (player.withdraw_epoch, player.withdrawn_in_epoch) = player_window;
+        (global_state.withdraw_epoch, global_state.withdrawn_in_epoch) = global_window;
+        Ok(())
+    }
+
+    // Gives back the usage of a withdrawal that never settled. Windows that have
+    // moved on to a later epoch no longer count it, so there is nothing to give back.
+    pub fn release_usage(player: &mut PlayerData, epoch: u64, amount: u64) {
+        let mut global_state = GLOBAL_STATE.0.borrow_mut();
+        if player.withdraw_epoch == epoch {
+            player.withdrawn_in_epoch = player.withdrawn_in_epoch.saturating_sub(amount);
+        }
+        if global_state.withdraw_epoch == epoch {
+            global_state.withdrawn_in_epoch = global_state.withdrawn_in_epoch.saturating_sub(amount);
+        }
+    }
+
+    pub fn is_large(amount: u64) -> bool {
+        amount >= ProtocolParams::get().large_withdraw_threshold
+    }
+
+    pub fn get_pending(id: u64) -> Option<PendingWithdrawal> {
+        let kvpair = unsafe { &mut MERKLE_MAP };
+        let key = [Self::PENDING_PREFIX[0], Self::PENDING_PREFIX[1], id, 0];
+        let mut data = kvpair.get(&key);
+        if !data.is_empty() {
+            let mut u64data = data.iter_mut();
+            Some(PendingWithdrawal::from_data(&mut u64data))
+        } else {
+            None
+        }
+    }
+
+    fn store_pending(id: u64, pending: &PendingWithdrawal) {
+        let mut data = vec![];
+        pending.to_data(&mut data);
+        let key = [Self::PENDING_PREFIX[0], Self::PENDING_PREFIX[1], id, 0];
+        crate::journal::set(&key, data.as_slice());
+    }
+
+    fn remove_pending(id: u64) {
+        let key = [Self::PENDING_PREFIX[0], Self::PENDING_PREFIX[1], id, 0];
+        crate::journal::set(&key, &[]);
+        GLOBAL_STATE.0.borrow_mut().pending_withdrawals.retain(|pending_id| *pending_id != id);
+    }
+
+    // Holds an already debited withdrawal until the configured delay has passed
+    pub fn queue(player_id: &[u64; 2], withdraw: &Withdraw, counter: u64) -> u64 {
+        let pending = PendingWithdrawal {
+            player_id: *player_id,
+            token: withdraw.token,
+            amount: withdraw.amount,
+            address: withdraw.address,
+            release_counter: counter + ProtocolParams::get().large_withdraw_delay_ticks,
+            usage_epoch: Self::epoch(counter),
+        };
+        let id = {
+            let mut global_state = GLOBAL_STATE.0.borrow_mut();
+            let id = global_state.next_withdrawal_id;
+            global_state.next_withdrawal_id += 1;
+            global_state.pending_withdrawals.push(id);
+            id
+        };
+        Self::store_pending(id, &pending);
+        Self::emit_withdrawal_event(WITHDRAWAL_QUEUED, id, &pending, counter);
+        id
+    }
+
+    // Called from tick: settles due withdrawals, bounded per tick to keep proof size predictable
+    pub fn release_due(counter: u64) {
+        let pending_ids = GLOBAL_STATE.0.borrow().pending_withdrawals.clone();
+        let max_releases = ProtocolParams::get().max_withdraw_releases_per_tick;
+        let mut released = 0;
+        for id in pending_ids {
+            if released >= max_releases {
+                break;
+            }
+            if let Some(pending) = Self::get_pending(id) {
+                if pending.release_counter > counter {
+                    continue;
+                }
+                match pending.to_withdraw().settlement_info() {
+                    Ok(withdrawinfo) => {
+                        SettlementInfo::append_settlement(withdrawinfo);
+                        Self::remove_pending(id);
+                        Self::emit_withdrawal_event(WITHDRAWAL_SETTLED, id, &pending, counter);
+                    },
+                    // The encoding was validated on request, but should the token have become
+                    // invalid since, the funds go back to the player instead of disappearing.
+                    // If even that fails the record stays for the admin to cancel.
+                    Err(_) => {
+                        if crate::journal::atomic(|| Self::refund_owner(id, &pending, counter)).is_err() {
+                            continue;
+                        }
+                    },
+                }
+                released += 1;
+            }
+        }
+    }
+
+    // Admin cancellation returns the escrowed amount and its epoch usage to the player
+    pub fn cancel(id: u64, admin: &mut Player, counter: u64) -> Result<(), u32> {
+        let pending = Self::get_pending(id).ok_or(ERROR_WITHDRAWAL_NOT_FOUND)?;
+        if pending.player_id != admin.player_id {
+            return Self::refund_owner(id, &pending, counter);
+        }
+        // The caller stores the admin record, so refund the in-memory copy
+        Self::refund(&mut admin.data, &pending);
+        Self::remove_pending(id);
+        Self::emit_withdrawal_event(WITHDRAWAL_CANCELLED, id, &pending, counter);
+        Ok(())
+    }
+
+    fn refund_owner(id: u64, pending: &PendingWithdrawal, counter: u64) -> Result<(), u32> {
+        crate::journal::track_player(&pending.player_id);
+        let mut player = Player::get_from_pid(&pending.player_id)
+            .ok_or(ERROR_PLAYER_NOT_EXIST)?;
+        Self::refund(&mut player.data, pending);
+        player.store();
+        Self::remove_pending(id);
+        Self::emit_withdrawal_event(WITHDRAWAL_CANCELLED, id, pending, counter);
+        Ok(())
+    }
+
+    fn refund(player: &mut PlayerData, pending: &PendingWithdrawal) {
+        player.add_balance_of(pending.token, pending.amount);
+        Self::release_usage(player, pending.usage_epoch, pending.amount);
+    }
+
+    pub fn emit_withdrawal_event(status: u64, id: u64, pending: &PendingWithdrawal, counter: u64) {
+        let mut data = vec![
+            status,
+            id,
+            pending.player_id[0],
+            pending.player_id[1],
+            pending.token,
+            pending.amount,
+            pending.release_counter,
+            counter,
+        ];
+        insert_event(EVENT_WITHDRAWAL_UPDATE, &mut data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::WITHDRAW_EPOCH_TICKS;
+
+    #[test]
+    fn test_epoch_usage_resets_and_enforces_limit() {
+        let counter = WITHDRAW_EPOCH_TICKS * 3 + 5;
+        assert_eq!(WithdrawalManager::apply_epoch_usage((3, 40), counter, 50, 100), Ok((3, 90)));
+        assert_eq!(WithdrawalManager::apply_epoch_usage((3, 60), counter, 50, 100), Err(ERROR_WITHDRAW_LIMIT_EXCEEDED));
+        // A stale window starts from zero in the new epoch
+        assert_eq!(WithdrawalManager::apply_epoch_usage((2, 100), counter, 50, 100), Ok((3, 50)));
+    }
+
+    #[test]
+    fn test_cancel_gives_back_epoch_usage() {
+        use crate::command::CommandHandler;
+        let _guard = crate::test_support::lock();
+        let pid = crate::test_support::player(20_000_000);
+        let mut admin = Player::get_from_pid(&crate::test_support::player(0)).unwrap();
+        let withdraw = Withdraw { token: 0, amount: 6_000_000, address: [0, 1, 2] };
+
+        withdraw.handle(&pid, 0, &[0; 4], 0).unwrap();
+        let id = GLOBAL_STATE.0.borrow().next_withdrawal_id - 1;
+        let global_used = GLOBAL_STATE.0.borrow().withdrawn_in_epoch;
+        assert_eq!(withdraw.handle(&pid, 1, &[0; 4], 0), Err(ERROR_WITHDRAW_LIMIT_EXCEEDED));
+
+        WithdrawalManager::cancel(id, &mut admin, 0).unwrap();
+        let player = Player::get_from_pid(&pid).unwrap();
+        assert_eq!((player.data.balance, player.data.withdrawn_in_epoch), (20_000_000, 0));
+        assert_eq!(GLOBAL_STATE.0.borrow().withdrawn_in_epoch, global_used - 6_000_000);
+        withdraw.handle(&pid, 1, &[0; 4], 0).unwrap();
+    }
+}
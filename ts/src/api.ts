@@ -25,11 +25,93 @@ const RESOLVE = 6;
 const CLAIM = 7;
 const WITHDRAW_FEES = 8;
 const CREATE_MARKET = 9;
+const CLAIM_ALL = 10;
+const TRANSFER_SHARES = 11;
+const TRANSFER = 12;
+const CANCEL_WITHDRAWAL = 13;
+const SET_PARAM = 14;
+const FUND_FAUCET = 15;
+const CLAIM_FAUCET = 16;
+const GRANT_ROLE = 17;
+const REVOKE_ROLE = 18;
+const ROTATE_ADMIN = 19;
+const BATCH = 20;
+const PLACE_LIMIT_ORDER = 21;
+const CANCEL_ORDER = 22;
+const PLACE_TRIGGER = 23;
+const CANCEL_TRIGGER = 24;
+const PLACE_PARLAY = 25;
+const CLAIM_PARLAY = 26;
+const FUND_PARLAY_BANKROLL = 27;
+const CREATE_CONDITIONAL_MARKET = 28;
+const CREATE_SERIES = 29;
+const STOP_SERIES = 30;
+
+// Sub-command of an atomic batch; only BET, SELL, CLAIM, CLAIM_ALL, TRANSFER_SHARES and TRANSFER are allowed
+export interface BatchEntry {
+    command: number;
+    params: bigint[];
+}
+
+// Role bits for grantRole / revokeRole
+export const ROLE_MARKET_CREATOR = 1n << 1n;
+export const ROLE_RESOLVER = 1n << 2n;
+export const ROLE_TREASURER = 1n << 3n;
+export const ROLE_DEPOSITOR = 1n << 4n;
+
+// Market kinds for createMarket
+export const MARKET_KIND_LMSR = 0n;
+export const MARKET_KIND_CLOB = 1n; // YES bids matched against NO bids, traded only through limit orders
+export const MARKET_KIND_CONDITIONAL = 2n; // created with createConditionalMarket
+
+// Fee rate of a series template that follows the platform fee parameter
+export const FEE_RATE_PROTOCOL = (1n << 64n) - 1n;
+
+// Trigger kinds for placeTrigger
+export const TRIGGER_STOP_LOSS = 0;
+export const TRIGGER_TAKE_PROFIT = 1;
 
-// Fee constants - centralized to avoid duplication
-const PLATFORM_FEE_RATE = 100n; // 1%
 const FEE_BASIS_POINTS = 10000n;
 
+// Fee rate a market charges: its own rate, or the platform fee parameter when it follows it
+export function effectiveFeeRate(marketFeeRate: bigint, platformFeeRate: bigint): bigint {
+    return marketFeeRate === FEE_RATE_PROTOCOL ? platformFeeRate : marketFeeRate;
+}
+
+// Extended encoding (see src/spec.rs): the header carries the reserved EXTENDED id,
+// the first word the real command id with the encoding version in bits 8-15
+const EXTENDED = 255n;
+const VERSION_EXTENDED = 1n;
+
+// Extension flags
+export const EXT_VALID_UNTIL = 1n; // last counter at which a trade may still execute
+
+// Build an extended-encoding command: the command word, a flags word, the positional
+// params, then one word per set flag in ascending bit order
+export function createExtendedCommand(nonce: bigint, command: bigint, params: bigint[], extensions: Map<bigint, bigint>) {
+    let flags = 0n;
+    const flagValues = [...extensions.entries()].sort((a, b) => (a[0] < b[0] ? -1 : 1));
+    for (const [flag] of flagValues) {
+        flags |= flag;
+    }
+    return createCommand(nonce, EXTENDED, [command | (VERSION_EXTENDED << 8n), flags, ...params, ...flagValues.map(([, value]) => value)]);
+}
+
+// Trading commands carry an optional deadline; without one the legacy encoding is used
+function createTradingCommand(nonce: bigint, command: bigint, params: bigint[], validUntil?: bigint) {
+    if (validUntil === undefined) {
+        return createCommand(nonce, command, params);
+    }
+    return createExtendedCommand(nonce, command, params, new Map([[EXT_VALID_UNTIL, validUntil]]));
+}
+
+// Split a 32-byte L1 tx hash into four u64 words (most significant first)
+export function txHashToWords(txHash: string): bigint[] {
+    const hash = BigInt(txHash);
+    const mask = (1n << 64n) - 1n;
+    return [hash >> 192n, (hash >> 128n) & mask, (hash >> 64n) & mask, hash & mask];
+}
+
 export class Player extends PlayerConvention {
     constructor(key: string, rpc: ZKWasmAppRpc) {
         super(key, rpc, BigInt(DEPOSIT), BigInt(WITHDRAW));
@@ -49,6 +131,13 @@ export class Player extends PlayerConvention {
         }
     }
 
+    // Live platform fee parameter, in basis points
+    async getPlatformFeeRate(): Promise<bigint> {
+        const response: any = await this.rpc.queryState(this.processingKey);
+        const data = JSON.parse(response.data);
+        return BigInt(data.state.params.platform_fee_rate);
+    }
+
     async installPlayer() {
         try {
             let cmd = createCommand(0n, BigInt(INSTALL_PLAYER), []);
@@ -63,16 +152,16 @@ export class Player extends PlayerConvention {
     }
 
     // Updated to include market_id
-    async placeBet(marketId: bigint, betType: number, amount: bigint) {
+    async placeBet(marketId: bigint, betType: number, amount: bigint, validUntil?: bigint) {
         let nonce = await this.getNonce();
-        let cmd = createCommand(nonce, BigInt(BET), [marketId, BigInt(betType), amount]);
+        let cmd = createTradingCommand(nonce, BigInt(BET), [marketId, BigInt(betType), amount], validUntil);
         return await this.sendTransactionWithCommand(cmd);
     }
 
     // Updated to include market_id
-    async sellShares(marketId: bigint, sellType: number, shares: bigint) {
+    async sellShares(marketId: bigint, sellType: number, shares: bigint, validUntil?: bigint) {
         let nonce = await this.getNonce();
-        let cmd = createCommand(nonce, BigInt(SELL), [marketId, BigInt(sellType), shares]);
+        let cmd = createTradingCommand(nonce, BigInt(SELL), [marketId, BigInt(sellType), shares], validUntil);
         return await this.sendTransactionWithCommand(cmd);
     }
 
@@ -83,6 +172,27 @@ export class Player extends PlayerConvention {
         return await this.sendTransactionWithCommand(cmd);
     }
 
+    // Claim winnings from every resolved market the player holds a position in
+    async claimAll() {
+        let nonce = await this.getNonce();
+        let cmd = createCommand(nonce, BigInt(CLAIM_ALL), []);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Move shares of one side to another player without trading through the AMM
+    async transferShares(marketId: bigint, side: number, shares: bigint, toPid1: bigint, toPid2: bigint, validUntil?: bigint) {
+        let nonce = await this.getNonce();
+        let cmd = createTradingCommand(nonce, BigInt(TRANSFER_SHARES), [marketId, BigInt(side), shares, toPid1, toPid2], validUntil);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Move balance to another player inside the rollup
+    async transfer(toPid1: bigint, toPid2: bigint, amount: bigint, token: bigint = 0n, validUntil?: bigint) {
+        let nonce = await this.getNonce();
+        let cmd = createTradingCommand(nonce, BigInt(TRANSFER), [toPid1, toPid2, amount, token], validUntil);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
     // Updated to include market_id
     async resolveMarket(marketId: bigint, outcome: boolean) {
         let nonce = await this.getNonce();
@@ -90,6 +200,115 @@ export class Player extends PlayerConvention {
         return await this.sendTransactionWithCommand(cmd);
     }
 
+    // Admin only: cancel a delayed withdrawal and refund the player
+    async cancelWithdrawal(withdrawalId: bigint) {
+        let nonce = await this.getNonce();
+        let cmd = createCommand(nonce, BigInt(CANCEL_WITHDRAWAL), [withdrawalId]);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Admin only: update a runtime protocol parameter (see src/params.rs for ids)
+    async setParam(paramId: bigint, value: bigint) {
+        let nonce = await this.getNonce();
+        let cmd = createCommand(nonce, BigInt(SET_PARAM), [paramId, value]);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Admin only: top up the budget that funds new player grants
+    async fundFaucet(amount: bigint) {
+        let nonce = await this.getNonce();
+        let cmd = createCommand(nonce, BigInt(FUND_FAUCET), [amount]);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Claim another faucet grant once the cooldown has passed
+    async claimFaucet() {
+        let nonce = await this.getNonce();
+        let cmd = createCommand(nonce, BigInt(CLAIM_FAUCET), []);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Super-admin only: grant roles (bit mask) to a player
+    async grantRole(pid1: bigint, pid2: bigint, roles: bigint) {
+        let nonce = await this.getNonce();
+        let cmd = createCommand(nonce, BigInt(GRANT_ROLE), [pid1, pid2, roles]);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Super-admin only: revoke roles (bit mask) from a player
+    async revokeRole(pid1: bigint, pid2: bigint, roles: bigint) {
+        let nonce = await this.getNonce();
+        let cmd = createCommand(nonce, BigInt(REVOKE_ROLE), [pid1, pid2, roles]);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Super-admin only: hand the super-admin role to another player
+    async rotateAdmin(pid1: bigint, pid2: bigint) {
+        let nonce = await this.getNonce();
+        let cmd = createCommand(nonce, BigInt(ROTATE_ADMIN), [pid1, pid2]);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Apply several trading commands atomically: all succeed or none does
+    async batch(entries: BatchEntry[], validUntil?: bigint) {
+        let nonce = await this.getNonce();
+        let params: bigint[] = [BigInt(entries.length)];
+        for (const entry of entries) {
+            params.push(BigInt(entry.command) | (BigInt(entry.params.length) << 8n), ...entry.params);
+        }
+        let cmd = createTradingCommand(nonce, BigInt(BATCH), params, validUntil);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Rest a buy order that fills once the average AMM price is at or below `price` (1e6 = 1.0).
+    // On order book markets the order buys amount / price shares from opposing bids instead.
+    async placeLimitOrder(marketId: bigint, side: number, price: bigint, amount: bigint, validUntil?: bigint) {
+        let nonce = await this.getNonce();
+        let cmd = createTradingCommand(nonce, BigInt(PLACE_LIMIT_ORDER), [marketId, BigInt(side), price, amount], validUntil);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    async cancelOrder(orderId: bigint) {
+        let nonce = await this.getNonce();
+        let cmd = createCommand(nonce, BigInt(CANCEL_ORDER), [orderId]);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Sell up to `shares` of a position at tick once the side's price crosses `price` (1e6 = 1.0)
+    async placeTrigger(marketId: bigint, side: number, kind: number, price: bigint, shares: bigint, validUntil?: bigint) {
+        let nonce = await this.getNonce();
+        let cmd = createTradingCommand(nonce, BigInt(PLACE_TRIGGER), [marketId, BigInt(side), BigInt(kind), price, shares], validUntil);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    async cancelTrigger(triggerId: bigint) {
+        let nonce = await this.getNonce();
+        let cmd = createCommand(nonce, BigInt(CANCEL_TRIGGER), [triggerId]);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Combo bet paying only if every leg resolves on its chosen side
+    async placeParlay(stake: bigint, legs: { marketId: bigint, side: number }[], validUntil?: bigint) {
+        let nonce = await this.getNonce();
+        const params = [stake, ...legs.flatMap((leg) => [leg.marketId, BigInt(leg.side)])];
+        let cmd = createTradingCommand(nonce, BigInt(PLACE_PARLAY), params, validUntil);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Settle a parlay once all its markets resolved; anyone may settle, the owner is paid
+    async claimParlay(ownerPid1: bigint, ownerPid2: bigint, parlayId: bigint) {
+        let nonce = await this.getNonce();
+        let cmd = createCommand(nonce, BigInt(CLAIM_PARLAY), [ownerPid1, ownerPid2, parlayId]);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Treasurer only: move funds from the caller's balance into the parlay bankroll
+    async fundParlayBankroll(token: bigint, amount: bigint) {
+        let nonce = await this.getNonce();
+        let cmd = createCommand(nonce, BigInt(FUND_PARLAY_BANKROLL), [token, amount]);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
     // Updated to include market_id
     async withdrawFees(marketId: bigint) {
         let nonce = await this.getNonce();
@@ -105,33 +324,106 @@ export class Player extends PlayerConvention {
         resolutionTimeOffset: bigint, // Offset from current counter
         initialYesLiquidity: bigint, // Initial YES shares for LMSR
         initialNoLiquidity: bigint,  // Initial NO shares for LMSR
-        b: bigint                    // LMSR liquidity parameter (market depth)
+        b: bigint,                   // LMSR liquidity parameter (market depth)
+        token: bigint = 0n,          // Collateral token index
+        kind: bigint = MARKET_KIND_LMSR // MARKET_KIND_CLOB ignores the liquidity and b arguments
     ) {
         let nonce = await this.getNonce();
 
-        // Build command: [start_time_offset, end_time_offset, resolution_time_offset, yes_liquidity, no_liquidity, b]
+        // Build command: [start_time_offset, end_time_offset, resolution_time_offset, yes_liquidity, no_liquidity, b, token, kind]
         const params = [
             startTimeOffset,
             endTimeOffset,
             resolutionTimeOffset,
             initialYesLiquidity,
             initialNoLiquidity,
-            b
+            b,
+            token,
+            kind
         ];
 
         let cmd = createCommand(nonce, BigInt(CREATE_MARKET), params);
         return await this.sendTransactionWithCommand(cmd);
     }
 
-    async withdrawFunds(amount: bigint, addressHigh: bigint, addressLow: bigint) {
+    // "If parent resolves to condition then ..." market; it inherits the parent's token
+    // and is voided with refunds at cost basis if the parent resolves the other way
+    async createConditionalMarket(
+        startTimeOffset: bigint,
+        endTimeOffset: bigint,
+        resolutionTimeOffset: bigint,
+        initialYesLiquidity: bigint,
+        initialNoLiquidity: bigint,
+        b: bigint,
+        parentMarketId: bigint,
+        condition: bigint            // Required parent outcome: 0n = NO, 1n = YES
+    ) {
+        let nonce = await this.getNonce();
+        const params = [
+            startTimeOffset,
+            endTimeOffset,
+            resolutionTimeOffset,
+            initialYesLiquidity,
+            initialNoLiquidity,
+            b,
+            parentMarketId,
+            condition
+        ];
+        let cmd = createCommand(nonce, BigInt(CREATE_CONDITIONAL_MARKET), params);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Recurring market: a new instance opens at the first tick after the previous one closes
+    async createSeries(
+        duration: bigint,            // Ticks each instance trades for
+        resolutionDelay: bigint,     // Ticks from end of trading to resolution
+        initialYesLiquidity: bigint,
+        initialNoLiquidity: bigint,
+        b: bigint,
+        feeRate: bigint,             // Basis points, or FEE_RATE_PROTOCOL
+        token: bigint,
+        metadataHash: [bigint, bigint, bigint, bigint] // Hash of the question text
+    ) {
+        let nonce = await this.getNonce();
+        const params = [
+            duration,
+            resolutionDelay,
+            initialYesLiquidity,
+            initialNoLiquidity,
+            b,
+            feeRate,
+            token,
+            ...metadataHash
+        ];
+        let cmd = createCommand(nonce, BigInt(CREATE_SERIES), params);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    async stopSeries(seriesId: bigint) {
+        let nonce = await this.getNonce();
+        let cmd = createCommand(nonce, BigInt(STOP_SERIES), [seriesId]);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    async withdrawFunds(amount: bigint, addressHigh: bigint, addressLow: bigint, token: bigint = 0n) {
+        let nonce = await this.getNonce();
+        let cmd = createCommand(nonce, BigInt(WITHDRAW), [token, amount, addressHigh, addressLow]);
+        return await this.sendTransactionWithCommand(cmd);
+    }
+
+    // Withdraw using the full 64-bit amount layout; address is a 0x-prefixed 20-byte hex string
+    async withdrawFundsFull(amount: bigint, address: string, token: bigint = 0n) {
+        const addr = BigInt(address);
+        const mask = (1n << 64n) - 1n;
         let nonce = await this.getNonce();
-        let cmd = createCommand(nonce, BigInt(WITHDRAW), [0n, amount, addressHigh, addressLow]);
+        let cmd = createCommand(nonce, BigInt(WITHDRAW), [token, amount, addr >> 128n, (addr >> 64n) & mask, addr & mask]);
         return await this.sendTransactionWithCommand(cmd);
     }
 
-    async depositFunds(amount: bigint, targetPid1: bigint, targetPid2: bigint) {
+    // txHash is the 0x-prefixed L1 deposit transaction hash; each hash can only be credited once
+    async depositFunds(amount: bigint, targetPid1: bigint, targetPid2: bigint, txHash: string, token: bigint = 0n) {
         let nonce = await this.getNonce();
-        let cmd = createCommand(nonce, BigInt(DEPOSIT), [targetPid1, targetPid2, 0n, amount]);
+        let cmd = createCommand(nonce, BigInt(DEPOSIT), [targetPid1, targetPid2, token, amount, ...txHashToWords(txHash)]);
         return await this.sendTransactionWithCommand(cmd);
     }
 }
@@ -150,6 +442,7 @@ export interface MarketData {
     resolved: boolean;
     outcome: boolean | null;
     totalFeesCollected: string;
+    feeRate: string;        // Basis points, or FEE_RATE_PROTOCOL to follow the platform fee
     titleString?: string; // From Sanity CMS, not stored in smart contract
 }
 
@@ -172,6 +465,13 @@ export interface LiquidityHistoryData {
     noLiquidity: string;
 }
 
+export interface TwapData {
+    marketId: string;
+    counter: string;
+    priceCumulative: string;
+    twap: string;           // YES price averaged over the last hour (PRICE_PRECISION units)
+}
+
 export interface PlayerMarketPosition {
     pid: string[];
     marketId: string;
@@ -269,11 +569,22 @@ export class PredictionMarketAPI {
         return result.data;
     }
 
+    // Get the TWAP checkpoints of a market for the last day, oldest first
+    async getMarketTwap(marketId: string): Promise<TwapData[]> {
+        const response = await fetch(`${this.baseUrl}/data/market/${marketId}/twap`);
+        const result = await response.json() as any;
+        if (!result.success) {
+            throw new Error(result.message || 'Failed to get market TWAP');
+        }
+        return result.data;
+    }
+
     // Binary search to find shares for a given bet amount (LMSR)
-    // Matches Rust backend's calculate_shares implementation exactly
-    calculateShares(betType: number, amount: number, yesLiquidity: bigint, noLiquidity: bigint, b: bigint = 1000000n): bigint {
+    // Matches Rust backend's calculate_shares implementation exactly.
+    // feeRate is the market's rate in basis points, see effectiveFeeRate
+    calculateShares(betType: number, amount: number, yesLiquidity: bigint, noLiquidity: bigint, b: bigint, feeRate: bigint): bigint {
         const betAmount = BigInt(amount);
-        const fee = (betAmount * PLATFORM_FEE_RATE + FEE_BASIS_POINTS - 1n) / FEE_BASIS_POINTS;
+        const fee = (betAmount * feeRate + FEE_BASIS_POINTS - 1n) / FEE_BASIS_POINTS;
         const netAmount = betAmount - fee;
         
         const qYes = BigInt(yesLiquidity);
@@ -322,7 +633,7 @@ export class PredictionMarketAPI {
         return lo;
     }
 
-    calculateSellDetails(sellType: number, shares: number, yesLiquidity: bigint, noLiquidity: bigint, b: bigint = 1000000n): { netPayout: bigint, fee: bigint } {
+    calculateSellDetails(sellType: number, shares: number, yesLiquidity: bigint, noLiquidity: bigint, b: bigint, feeRate: bigint): { netPayout: bigint, fee: bigint } {
         const sharesToSell = BigInt(shares);
         const qYes = BigInt(yesLiquidity);
         const qNo = BigInt(noLiquidity);
@@ -336,37 +647,37 @@ export class PredictionMarketAPI {
         }
         
         const grossAmount = grossQuote / FP_SCALE;
-        const fee = (grossAmount * PLATFORM_FEE_RATE + FEE_BASIS_POINTS - 1n) / FEE_BASIS_POINTS;
+        const fee = (grossAmount * feeRate + FEE_BASIS_POINTS - 1n) / FEE_BASIS_POINTS;
         const netPayout = grossAmount - fee;
         
         return { netPayout, fee };
     }
 
-    calculateSellValue(sellType: number, shares: number, yesLiquidity: bigint, noLiquidity: bigint, b: bigint = 1000000n): bigint {
-        const result = this.calculateSellDetails(sellType, shares, yesLiquidity, noLiquidity, b);
+    calculateSellValue(sellType: number, shares: number, yesLiquidity: bigint, noLiquidity: bigint, b: bigint, feeRate: bigint): bigint {
+        const result = this.calculateSellDetails(sellType, shares, yesLiquidity, noLiquidity, b, feeRate);
         return result.netPayout;
     }
 
-    getBuyPrice(betType: number, amount: number, yesLiquidity: bigint, noLiquidity: bigint, b: bigint = 1000000n): number {
-        const shares = this.calculateShares(betType, amount, yesLiquidity, noLiquidity, b);
+    getBuyPrice(betType: number, amount: number, yesLiquidity: bigint, noLiquidity: bigint, b: bigint, feeRate: bigint): number {
+        const shares = this.calculateShares(betType, amount, yesLiquidity, noLiquidity, b, feeRate);
         if (shares === 0n) return 0;
         return (amount * 1000000) / Number(shares); // Return price in terms of precision
     }
 
-    getSellPrice(sellType: number, shares: number, yesLiquidity: bigint, noLiquidity: bigint, b: bigint = 1000000n): number {
-        const payout = this.calculateSellValue(sellType, shares, yesLiquidity, noLiquidity, b);
+    getSellPrice(sellType: number, shares: number, yesLiquidity: bigint, noLiquidity: bigint, b: bigint, feeRate: bigint): number {
+        const payout = this.calculateSellValue(sellType, shares, yesLiquidity, noLiquidity, b, feeRate);
         if (shares === 0) return 0;
         return (Number(payout) * 1000000) / shares; // Return price in terms of precision
     }
 
-    calculateMarketImpact(betType: number, amount: number, yesLiquidity: bigint, noLiquidity: bigint, b: bigint = 1000000n): { 
+    calculateMarketImpact(betType: number, amount: number, yesLiquidity: bigint, noLiquidity: bigint, b: bigint, feeRate: bigint): { 
         currentYesPrice: number, 
         currentNoPrice: number, 
         newYesPrice: number, 
         newNoPrice: number 
     } {
         const currentPrices = this.calculatePrices(yesLiquidity, noLiquidity, b);
-        const shares = this.calculateShares(betType, amount, yesLiquidity, noLiquidity, b);
+        const shares = this.calculateShares(betType, amount, yesLiquidity, noLiquidity, b, feeRate);
         
         let newYesLiquidity: bigint = yesLiquidity;
         let newNoLiquidity: bigint = noLiquidity;
@@ -386,8 +697,8 @@ export class PredictionMarketAPI {
         };
     }
 
-    calculateSlippage(betType: number, amount: number, yesLiquidity: bigint, noLiquidity: bigint, b: bigint = 1000000n): number {
-        const impact = this.calculateMarketImpact(betType, amount, yesLiquidity, noLiquidity, b);
+    calculateSlippage(betType: number, amount: number, yesLiquidity: bigint, noLiquidity: bigint, b: bigint, feeRate: bigint): number {
+        const impact = this.calculateMarketImpact(betType, amount, yesLiquidity, noLiquidity, b, feeRate);
         
         if (betType === 1) { // YES bet
             return ((impact.newYesPrice - impact.currentYesPrice) / impact.currentYesPrice) * 100;
@@ -467,9 +778,10 @@ export function buildDepositTransaction(
     nonce: number,
     targetPid1: bigint,
     targetPid2: bigint,
-    amount: bigint
+    amount: bigint,
+    txHash: string
 ): bigint[] {
-    return [BigInt(nonce), BigInt(DEPOSIT), targetPid1, targetPid2, 0n, amount];
+    return [BigInt(nonce), BigInt(DEPOSIT), targetPid1, targetPid2, 0n, amount, ...txHashToWords(txHash)];
 }
 
 export function buildInstallPlayerTransaction(nonce: number): bigint[] {
@@ -9,6 +9,13 @@ dotenv.config();
 const DEPOSIT = 3;
 const INSTALL_PLAYER = 1;
 
+// Split a 32-byte L1 tx hash into four u64 words (most significant first)
+function txHashToWords(txHash: string): bigint[] {
+    const hash = BigInt(txHash);
+    const mask = (1n << 64n) - 1n;
+    return [hash >> 192n, (hash >> 128n) & mask, (hash >> 64n) & mask, hash & mask];
+}
+
 class Player extends PlayerConvention {
     constructor(key: string, rpc: ZKWasmAppRpc) {
         super(key, rpc, BigInt(DEPOSIT), BigInt(2)); // WITHDRAW = 2
@@ -41,9 +48,9 @@ class Player extends PlayerConvention {
         }
     }
 
-    async depositFunds(amount: bigint, targetPid1: bigint, targetPid2: bigint) {
+    async depositFunds(amount: bigint, targetPid1: bigint, targetPid2: bigint, txHash: string) {
         let nonce = await this.getNonce();
-        let cmd = createCommand(nonce, BigInt(DEPOSIT), [targetPid1, targetPid2, 0n, amount]);
+        let cmd = createCommand(nonce, BigInt(DEPOSIT), [targetPid1, targetPid2, 0n, amount, ...txHashToWords(txHash)]);
         return await this.sendTransactionWithCommand(cmd);
     }
 }
@@ -73,11 +80,15 @@ async function adminDeposit() {
         const targetPid1 = 9702256456334647944n;
         const targetPid2 = 5605797091113630749n;
         const depositAmount = 10000n;
+        const txHash = process.env.DEPOSIT_TX_HASH;
+        if (!txHash) {
+            throw new Error("DEPOSIT_TX_HASH environment variable is required");
+        }
         
         console.log(`Depositing ${depositAmount} to PID: ${targetPid1}, ${targetPid2}`);
         
         // 执行充值
-        await admin.depositFunds(depositAmount, targetPid1, targetPid2);
+        await admin.depositFunds(depositAmount, targetPid1, targetPid2, txHash);
         console.log("✅ Deposit successful!");
         
     } catch (error) {
@@ -18,7 +18,11 @@ export function docToJSON(doc: mongoose.Document) {
 
 // Market info constants for IndexedObject
 export const MARKET_INFO = 1;
+
+// Market fee rate that follows the platform fee parameter (MARKET_FEE_PROTOCOL in src/config.rs)
+const FEE_RATE_PROTOCOL = (1n << 64n) - 1n;
 export const LIQUIDITY_HISTORY_INFO = 2;
+export const TWAP_INFO = 3;
 
 // ActionType enum removed - no longer needed since liquidity history only tracks snapshots
 
@@ -59,6 +63,8 @@ export class IndexedObject {
             return MarketData.fromData(this.data);
         } else if (this.index === LIQUIDITY_HISTORY_INFO) {
             return LiquidityHistoryEntry.fromData(this.data);
+        } else if (this.index === TWAP_INFO) {
+            return TwapEntry.fromData(this.data);
         } else {
             console.error("Fatal: unexpected object index:", this.index);
             process.exit();
@@ -96,6 +102,13 @@ export class IndexedObject {
                 {upsert: true}
             );
             return doc;
+        } else if (this.index === TWAP_INFO) {
+            let doc = await TwapModel.findOneAndUpdate(
+                {marketId: obj.marketId, counter: obj.counter},
+                obj,
+                {upsert: true}
+            );
+            return doc;
         }
     }
 }
@@ -118,6 +131,8 @@ export class MarketData {
     resolved: boolean;
     outcome: boolean | null;
     totalFeesCollected: bigint;
+    // Basis points, or FEE_RATE_PROTOCOL to follow the platform fee parameter
+    feeRate: bigint;
 
     constructor(data: any) {
         this.startTime = data.startTime || 0n;
@@ -131,6 +146,7 @@ export class MarketData {
         this.resolved = data.resolved || false;
         this.outcome = data.outcome;
         this.totalFeesCollected = data.totalFeesCollected || 0n;
+        this.feeRate = data.feeRate ?? FEE_RATE_PROTOCOL;
     }
 
     static fromData(data: bigint[]): MarketData {
@@ -151,6 +167,9 @@ export class MarketData {
         const outcomeValue = data[index++];
         const outcome = outcomeValue === 0n ? null : (outcomeValue === 2n ? true : false);
         const totalFeesCollected = data[index++];
+        // token, kind, parent market, condition and series precede the fee rate
+        index += 5;
+        const feeRate = data[index++] ?? FEE_RATE_PROTOCOL;
 
         const marketData = new MarketData({
             startTime,
@@ -163,7 +182,8 @@ export class MarketData {
             totalVolume,
             resolved,
             outcome,
-            totalFeesCollected
+            totalFeesCollected,
+            feeRate
         });
         marketData.marketId = marketId;
         return marketData;
@@ -194,6 +214,30 @@ export class LiquidityHistoryEntry {
     }
 }
 
+// TWAP checkpoint - YES price averaged over the last hour of trading (PRICE_PRECISION units)
+export class TwapEntry {
+    marketId: bigint;
+    counter: bigint;
+    priceCumulative: bigint; // TWAP between two checkpoints = cumulative delta / counter delta
+    twap: bigint;
+
+    constructor(data: any) {
+        this.marketId = data.marketId;
+        this.counter = data.counter;
+        this.priceCumulative = data.priceCumulative;
+        this.twap = data.twap;
+    }
+
+    static fromData(data: bigint[]): TwapEntry {
+        return new TwapEntry({
+            marketId: data[0],
+            counter: data[1],
+            priceCumulative: data[2],
+            twap: data[3]
+        });
+    }
+}
+
 // Market Object Schema for IndexedObject pattern - main storage (LMSR)
 const marketObjectSchema = new mongoose.Schema({
     marketId: { type: BigInt, required: true, unique: true },
@@ -208,6 +252,7 @@ const marketObjectSchema = new mongoose.Schema({
     resolved: { type: Boolean, default: false },
     outcome: { type: Boolean, default: null },
     totalFeesCollected: { type: BigInt, default: 0n },
+    feeRate: { type: BigInt, default: FEE_RATE_PROTOCOL },
 });
 
 marketObjectSchema.pre('init', ObjectEvent.uint64FetchPlugin);
@@ -224,6 +269,17 @@ liquidityHistorySchema.pre('init', ObjectEvent.uint64FetchPlugin);
 liquidityHistorySchema.index({ marketId: 1, counter: 1 }, { unique: true });
 liquidityHistorySchema.index({ marketId: 1, counter: -1 });
 
+// TWAP Schema - checkpoints published every ten minutes of trading
+const twapSchema = new mongoose.Schema({
+    marketId: { type: BigInt, required: true },
+    counter: { type: BigInt, required: true },
+    priceCumulative: { type: BigInt, required: true },
+    twap: { type: BigInt, required: true },
+});
+
+twapSchema.pre('init', ObjectEvent.uint64FetchPlugin);
+twapSchema.index({ marketId: 1, counter: 1 }, { unique: true });
+
 // Multi-Market Bet Interface
 export interface Bet {
     index: bigint;
@@ -275,6 +331,7 @@ playerMarketPositionSchema.index({ pid: 1, marketId: 1 }, { unique: true });
 // Main market model using IndexedObject pattern
 export const MarketModel = mongoose.model('Market', marketObjectSchema);
 export const LiquidityHistoryModel = mongoose.model('LiquidityHistory', liquidityHistorySchema);
+export const TwapModel = mongoose.model('Twap', twapSchema);
 export const BetModel = mongoose.model('Bet', betSchema);
 export const PlayerMarketPositionModel = mongoose.model('PlayerMarketPosition', playerMarketPositionSchema);
 
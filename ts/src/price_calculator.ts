@@ -10,6 +10,9 @@ import {
     lmsrPriceYesFp
 } from "./lmsr_math.js";
 
+// Default platform fee in basis points (PLATFORM_FEE_RATE in src/config.rs)
+const PLATFORM_FEE_RATE: bigint = 100n; // 1%
+
 interface PriceCalculationResult {
     targetPrice: number;
     requiredBetAmount: bigint;
@@ -24,7 +27,6 @@ export class LMSRPriceCalculator {
     private static readonly INITIAL_YES_LIQUIDITY: bigint = 100000n;
     private static readonly INITIAL_NO_LIQUIDITY: bigint = 100000n;
     private static readonly DEFAULT_B: bigint = 1_000_000n;
-    private static readonly PLATFORM_FEE_RATE: bigint = 100n; // 1%
     private static readonly FEE_BASIS_POINTS: bigint = 10000n;
 
     /**
@@ -48,6 +50,7 @@ export class LMSRPriceCalculator {
         currentYesLiquidity: bigint,
         currentNoLiquidity: bigint,
         targetPrice: number,
+        feeRate: bigint, // basis points charged by the market, see effectiveFeeRate in api.ts
         b: bigint = this.DEFAULT_B
     ): PriceCalculationResult {
         const currentYes = BigInt(currentYesLiquidity);
@@ -85,7 +88,7 @@ export class LMSRPriceCalculator {
         const deltaYes = lo;
         const netQuote = lmsrBuyYesQuote(currentYes, currentNo, bBig, deltaYes);
         const netBetAmount = netQuote / FP_SCALE;
-        const fee = (netBetAmount * this.PLATFORM_FEE_RATE + this.FEE_BASIS_POINTS - 1n) / this.FEE_BASIS_POINTS;
+        const fee = (netBetAmount * feeRate + this.FEE_BASIS_POINTS - 1n) / this.FEE_BASIS_POINTS;
         const totalBetAmount = netBetAmount + fee;
 
         const actualNewYes = currentYes + deltaYes;
@@ -107,13 +110,14 @@ export class LMSRPriceCalculator {
      * 计算达到多个目标价格所需的投注
      */
     static calculateMultipleTargets(
+        feeRate: bigint,
         currentYesLiquidity: bigint = this.INITIAL_YES_LIQUIDITY,
         currentNoLiquidity: bigint = this.INITIAL_NO_LIQUIDITY,
         targetPrices: number[] = [0.6, 0.7, 0.8, 0.9],
         b: bigint = this.DEFAULT_B
     ): PriceCalculationResult[] {
         return targetPrices.map(price => 
-            this.calculateRequiredYesBet(currentYesLiquidity, currentNoLiquidity, price, b)
+            this.calculateRequiredYesBet(currentYesLiquidity, currentNoLiquidity, price, feeRate, b)
         );
     }
 
@@ -144,6 +148,7 @@ export class LMSRPriceCalculator {
      */
     static calculateCumulativeEffect(
         targetPrices: number[],
+        feeRate: bigint,
         b: bigint = this.DEFAULT_B
     ): {
         individual: PriceCalculationResult[],
@@ -151,6 +156,7 @@ export class LMSRPriceCalculator {
     } {
         // 单独投注效果（从初始状态）
         const individual = this.calculateMultipleTargets(
+            feeRate,
             this.INITIAL_YES_LIQUIDITY,
             this.INITIAL_NO_LIQUIDITY,
             targetPrices,
@@ -163,7 +169,7 @@ export class LMSRPriceCalculator {
         let currentNo = this.INITIAL_NO_LIQUIDITY;
 
         for (const targetPrice of targetPrices) {
-            const result = this.calculateRequiredYesBet(currentYes, currentNo, targetPrice, b);
+            const result = this.calculateRequiredYesBet(currentYes, currentNo, targetPrice, feeRate, b);
             cumulative.push(result);
             
             // 更新流动性状态
@@ -175,14 +181,14 @@ export class LMSRPriceCalculator {
     }
 }
 
-// 执行计算并输出结果
-export function calculatePriceTargets() {
+// 执行计算并输出结果; feeRate is the market fee in basis points
+export function calculatePriceTargets(feeRate: bigint) {
     console.log("=== Individual Bet Effects (starting from 50%) ===");
-    const individualResults = LMSRPriceCalculator.calculateMultipleTargets();
+    const individualResults = LMSRPriceCalculator.calculateMultipleTargets(feeRate);
     console.log(LMSRPriceCalculator.formatResults(individualResults));
 
     console.log("=== Cumulative Bet Effects (consecutive price movements) ===");
-    const { cumulative } = LMSRPriceCalculator.calculateCumulativeEffect([0.6, 0.7, 0.8, 0.9]);
+    const { cumulative } = LMSRPriceCalculator.calculateCumulativeEffect([0.6, 0.7, 0.8, 0.9], feeRate);
     
     let totalInvestment = 0n;
     let currentPrice = 50;
@@ -206,5 +212,6 @@ export function calculatePriceTargets() {
 
 // 如果直接运行这个文件
 if (typeof require !== 'undefined' && require.main === module) {
-    calculatePriceTargets();
+    // Usage: price_calculator [market fee rate in basis points, defaults to the platform fee]
+    calculatePriceTargets(process.argv[2] === undefined ? PLATFORM_FEE_RATE : BigInt(process.argv[2]));
 } 
\ No newline at end of file
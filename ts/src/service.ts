@@ -2,7 +2,7 @@ import { Express } from "express";
 import mongoose from 'mongoose';
 import { Event, EventModel, Service, TxStateManager, TxWitness } from "zkwasm-ts-server";
 import { merkleRootToBeHexString } from "zkwasm-ts-server/src/lib.js";
-import { BetEvent, BetModel, docToJSON, IndexedObject, LiquidityHistoryModel, MarketModel, PlayerMarketPositionModel } from "./models.js";
+import { BetEvent, BetModel, docToJSON, IndexedObject, LiquidityHistoryModel, MarketModel, PlayerMarketPositionModel, TwapModel } from "./models.js";
 
 const service = new Service(eventCallback, batchedCallback, extra);
 await service.initialize();
@@ -269,6 +269,28 @@ function extra(app: Express) {
     }
   });
 
+  // Get the latest TWAP checkpoints of a market, the newest carries the price parlay legs are floored at
+  app.get("/data/market/:marketId/twap", async (req: any, res) => {
+    try {
+      const marketId = BigInt(req.params.marketId);
+      const limit = parseInt(req.query.limit || '144');
+
+      const doc = await TwapModel.find({ marketId }).sort({ counter: -1 }).limit(limit);
+      let data = doc.map((d) => docToJSON(d));
+
+      res.status(201).send({
+        success: true,
+        data: data.reverse(), // Return in ascending order
+      });
+    } catch (e) {
+      console.error("Error fetching market TWAP:", e);
+      res.status(500).send({
+        success: false,
+        error: "Failed to fetch market TWAP"
+      });
+    }
+  });
+
 
 }
 
@@ -2,7 +2,8 @@ import { PrivateKey, bnToHexLe } from "delphinus-curves/src/altjubjub";
 import dotenv from 'dotenv';
 import { PlayerConvention, ZKWasmAppRpc, createCommand } from 'zkwasm-minirollup-rpc';
 import { LeHexBN } from "zkwasm-ts-server";
-import { PredictionMarketAPI } from './api.js';
+import { randomBytes } from 'crypto';
+import { PredictionMarketAPI, txHashToWords } from './api.js';
 
 dotenv.config();
 
@@ -77,9 +78,9 @@ class Player extends PlayerConvention {
         return await this.sendTransactionWithCommand(cmd);
     }
 
-    async depositFunds(amount: bigint, targetPid1: bigint, targetPid2: bigint) {
+    async depositFunds(amount: bigint, targetPid1: bigint, targetPid2: bigint, txHash: string) {
         let nonce = await this.getNonce();
-        let cmd = createCommand(nonce, BigInt(DEPOSIT), [targetPid1, targetPid2, 0n, amount]);
+        let cmd = createCommand(nonce, BigInt(DEPOSIT), [targetPid1, targetPid2, 0n, amount, ...txHashToWords(txHash)]);
         return await this.sendTransactionWithCommand(cmd);
     }
 
@@ -147,7 +148,7 @@ async function logStateInfo(rpc: any, player: Player, playerName: string, stepDe
 }
 
 // Test LMSR calculations
-function testLMSRCalculations() {
+function testLMSRCalculations(feeRate: bigint) {
     console.log("=== Testing LMSR Calculations ===");
     
     const api = new PredictionMarketAPI();
@@ -167,7 +168,7 @@ function testLMSRCalculations() {
     const betAmount = 10000;
     
     // Calculate YES bet
-    const yesShares = api.calculateShares(1, betAmount, initialYesLiquidity, initialNoLiquidity, defaultB);
+    const yesShares = api.calculateShares(1, betAmount, initialYesLiquidity, initialNoLiquidity, defaultB, feeRate);
     console.log(`\nBetting ${betAmount} on YES:`);
     console.log(`Expected shares: ${yesShares}`);
     
@@ -300,13 +301,13 @@ async function testMultiMarketPrediction() {
         // Step 3: Admin deposits funds for all players (DEPOSIT command)
         console.log("\n=== STEP 3: Admin Deposits Funds (DEPOSIT) ===");
         
-        await admin.depositFunds(10000n, player1PkeyArray[1], player1PkeyArray[2]);
+        await admin.depositFunds(10000n, player1PkeyArray[1], player1PkeyArray[2], "0x" + randomBytes(32).toString("hex"));
         console.log("Deposited 10000 for Player1");
         
-        await admin.depositFunds(8000n, player2PkeyArray[1], player2PkeyArray[2]);
+        await admin.depositFunds(8000n, player2PkeyArray[1], player2PkeyArray[2], "0x" + randomBytes(32).toString("hex"));
         console.log("Deposited 8000 for Player2");
 
-        await admin.depositFunds(12000n, player3PkeyArray[1], player3PkeyArray[2]);
+        await admin.depositFunds(12000n, player3PkeyArray[1], player3PkeyArray[2], "0x" + randomBytes(32).toString("hex"));
         console.log("Deposited 12000 for Player3");
         
         await new Promise(resolve => setTimeout(resolve, 2000));
@@ -541,8 +542,10 @@ async function runExamples() {
     console.log("Running comprehensive multi-market prediction test...\n");
     
     try {
-        // Test LMSR calculations first
-        testLMSRCalculations();
+        // Test LMSR calculations first, with the live platform fee
+        const rpc = new ZKWasmAppRpc("http://localhost:3000");
+        const state: any = await rpc.queryState(process.env.SERVER_ADMIN_KEY!);
+        testLMSRCalculations(BigInt(JSON.parse(state.data).state.params.platform_fee_rate));
         
         // Then test all commands
         await testMultiMarketPrediction();
@@ -1,4 +1,4 @@
-import { Player, PredictionMarketAPI } from "./api.js";
+import { effectiveFeeRate, FEE_RATE_PROTOCOL, Player, PredictionMarketAPI } from "./api.js";
 //import { LeHexBN, ZKWasmAppRpc} from "zkwasm-minirollup-rpc";
 import { LeHexBN, query, ZKWasmAppRpc } from "zkwasm-ts-server";
 
@@ -182,26 +182,27 @@ async function main() {
     
     // Current prices (LMSR)
     const marketB = market.b ? BigInt(market.b) : 1000000n;
+    const feeRate = effectiveFeeRate(BigInt(market.feeRate ?? FEE_RATE_PROTOCOL), await player.getPlatformFeeRate());
     const prices = api.calculatePrices(yesShares, noShares, marketB);
     console.log(`  Current prices (LMSR): YES=${(prices.yesPrice * 100).toFixed(2)}%, NO=${(prices.noPrice * 100).toFixed(2)}%`);
     
     // Expected shares for 1000 unit bet (LMSR)
     const betAmount = 1000;
-    const expectedYesShares = api.calculateShares(1, betAmount, yesShares, noShares, marketB);
-    const expectedNoShares = api.calculateShares(0, betAmount, yesShares, noShares, marketB);
+    const expectedYesShares = api.calculateShares(1, betAmount, yesShares, noShares, marketB, feeRate);
+    const expectedNoShares = api.calculateShares(0, betAmount, yesShares, noShares, marketB, feeRate);
     console.log(`  Expected shares for ${betAmount} units (LMSR): YES=${expectedYesShares}, NO=${expectedNoShares}`);
     
     // Buy prices (LMSR)
-    const yesBuyPrice = api.getBuyPrice(1, betAmount, yesShares, noShares, marketB);
-    const noBuyPrice = api.getBuyPrice(0, betAmount, yesShares, noShares, marketB);
+    const yesBuyPrice = api.getBuyPrice(1, betAmount, yesShares, noShares, marketB, feeRate);
+    const noBuyPrice = api.getBuyPrice(0, betAmount, yesShares, noShares, marketB, feeRate);
     console.log(`  Buy prices (LMSR): YES=${yesBuyPrice.toFixed(6)}, NO=${noBuyPrice.toFixed(6)}`);
     
     // Market impact (LMSR)
-    const yesImpact = api.calculateMarketImpact(1, betAmount, yesShares, noShares, marketB);
+    const yesImpact = api.calculateMarketImpact(1, betAmount, yesShares, noShares, marketB, feeRate);
     console.log(`  YES bet impact (LMSR): ${(yesImpact.currentYesPrice * 100).toFixed(2)}% → ${(yesImpact.newYesPrice * 100).toFixed(2)}%`);
     
     // Slippage (LMSR)
-    const yesSlippage = api.calculateSlippage(1, betAmount, yesShares, noShares, marketB);
+    const yesSlippage = api.calculateSlippage(1, betAmount, yesShares, noShares, marketB, feeRate);
     console.log(`  YES bet slippage (LMSR): ${yesSlippage.toFixed(4)}%`);
 
     // Test 10: Platform statistics (calculated from markets data)
@@ -1,3 +1,4 @@
+import { randomBytes } from "crypto";
 import { Player, PredictionMarketAPI } from "./api.js";
 import { PrivateKey, bnToHexLe } from "delphinus-curves/src/altjubjub";
 //import { LeHexBN, ZKWasmAppRpc} from "zkwasm-minirollup-rpc";
@@ -96,10 +97,10 @@ async function main() {
   }
     await delay(3000);
 
-    await adminPlayer.depositFunds(10000n, player1PkeyArray[1], player1PkeyArray[2]);
+    await adminPlayer.depositFunds(10000n, player1PkeyArray[1], player1PkeyArray[2], "0x" + randomBytes(32).toString("hex"));
     console.log("Deposited 10000 for Player1");
     
-    await adminPlayer.depositFunds(8000n, player2PkeyArray[1], player2PkeyArray[2]);
+    await adminPlayer.depositFunds(8000n, player2PkeyArray[1], player2PkeyArray[2], "0x" + randomBytes(32).toString("hex"));
     console.log("Deposited 8000 for Player2");
 
     // Step 3: Get all markets